@@ -0,0 +1,130 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use orderbook::{OrderQueue, OrderSide};
+
+
+// resting orders in queue
+const DEPTHS: [usize; 3] = [100, 1_000, 10_000];
+// same as in orderbook
+const MAX_STALLED: u64 = 10;
+const ORDERS_PER_LEVEL: usize = 10;
+const TICK: f64 = 0.01;
+// enough to clean dangling indices a few times
+const CANCELS: u64 = 4 * MAX_STALLED;
+
+
+/// Bids with IDs from 1, ORDERS_PER_LEVEL orders per price level
+fn filled_queue(depth: usize) -> OrderQueue<f64> {
+    let mut queue = OrderQueue::new(OrderSide::Bid, MAX_STALLED, depth);
+    for n in 0..depth {
+        let price = 100.0 - (n / ORDERS_PER_LEVEL) as f64 * TICK;
+        queue.insert(n as u64 + 1, price, 1.0);
+    }
+    queue
+}
+
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_queue/insert");
+    for depth in DEPTHS {
+        // new best level
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            b.iter_batched(
+                || filled_queue(depth),
+                |mut queue| {
+                    queue.insert(depth as u64 + 1, 100.0 + TICK, 1.0);
+                    queue
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+
+fn cancel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_queue/cancel");
+    group.throughput(Throughput::Elements(CANCELS));
+    for depth in DEPTHS {
+        // orders from the middle of the queue, dangling indices are cleaned every MAX_STALLED cancels
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            let first = (depth / 2) as u64;
+            b.iter_batched(
+                || filled_queue(depth),
+                |mut queue| {
+                    for id in first..first + CANCELS {
+                        queue.cancel(id);
+                    }
+                    queue
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+
+fn reinsert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_queue/reinsert");
+    group.throughput(Throughput::Elements(CANCELS));
+    for depth in DEPTHS {
+        // IDs reused right after cancel, as when engine IDs wrap around
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            let first = (depth / 2) as u64;
+            b.iter_batched(
+                || filled_queue(depth),
+                |mut queue| {
+                    for id in first..first + CANCELS {
+                        queue.cancel(id);
+                        queue.insert(id, 100.0 - TICK, 1.0);
+                    }
+                    queue
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+
+fn amend(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_queue/amend");
+    for depth in DEPTHS {
+        // price change, index is rebuilt
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            b.iter_batched(
+                || filled_queue(depth),
+                |mut queue| {
+                    queue.amend((depth / 2) as u64, 100.0 + TICK, 1.0);
+                    queue
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+
+fn pop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_queue/pop");
+    for depth in DEPTHS {
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            b.iter_batched(
+                || filled_queue(depth),
+                |mut queue| {
+                    queue.pop();
+                    queue
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+
+criterion_group!(benches, insert, cancel, reinsert, amend, pop);
+criterion_main!(benches);
@@ -0,0 +1,118 @@
+use std::time::SystemTime;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use orderbook::{orders, Orderbook, OrderSide};
+
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+enum Asset {
+    USD,
+    BTC,
+}
+
+
+// price levels per side with one order each, order IDs rotate after 1000
+const DEPTHS: [usize; 3] = [10, 100, 300];
+const MID: f64 = 100.0;
+const TICK: f64 = 0.01;
+
+
+/// Bid and ask at every level, bid of level n has ID 2n - 1
+fn filled_book(depth: usize) -> Orderbook<Asset> {
+    let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+    for level in 1..=depth {
+        let distance = level as f64 * TICK;
+        orderbook.process_order(limit(OrderSide::Bid, MID - distance, 1.0));
+        orderbook.process_order(limit(OrderSide::Ask, MID + distance, 1.0));
+    }
+    orderbook
+}
+
+
+fn limit(side: OrderSide, price: f64, qty: f64) -> orders::OrderRequest<Asset> {
+    orders::new_limit_order_request(1, Asset::BTC, Asset::USD, side, price, qty, SystemTime::now())
+}
+
+
+fn passive_limit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_order/passive_limit");
+    for depth in DEPTHS {
+        // rests behind the worst bid
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            let price = MID - (depth + 1) as f64 * TICK;
+            b.iter_batched(
+                || filled_book(depth),
+                |mut orderbook| {
+                    let results = orderbook.process_order(limit(OrderSide::Bid, price, 1.0));
+                    (orderbook, results)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+
+fn aggressive_sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_order/aggressive_sweep");
+    for depth in DEPTHS {
+        // market order filled by every ask level
+        group.throughput(Throughput::Elements(depth as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            b.iter_batched(
+                || filled_book(depth),
+                |mut orderbook| {
+                    let results = orderbook.process_order(orders::new_market_order_request(
+                        2,
+                        Asset::BTC,
+                        Asset::USD,
+                        OrderSide::Bid,
+                        depth as f64,
+                        SystemTime::now(),
+                    ));
+                    (orderbook, results)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+
+fn cancel_heavy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_order/cancel_heavy");
+    for depth in DEPTHS {
+        // every bid is cancelled and placed again, leaving dangling indices which are cleaned
+        // periodically, then a market order looks through the bid queue
+        group.throughput(Throughput::Elements(2 * depth as u64 + 1));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            b.iter_batched(
+                || filled_book(depth),
+                |mut orderbook| {
+                    for level in 1..=depth {
+                        let id = 2 * level as u64 - 1;
+                        orderbook.process_order(orders::limit_order_cancel_request(1, id, OrderSide::Bid));
+                        orderbook.process_order(limit(OrderSide::Bid, MID - level as f64 * TICK, 1.0));
+                    }
+                    let results = orderbook.process_order(orders::new_market_order_request(
+                        2,
+                        Asset::BTC,
+                        Asset::USD,
+                        OrderSide::Ask,
+                        1.0,
+                        SystemTime::now(),
+                    ));
+                    (orderbook, results)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+
+criterion_group!(benches, passive_limit, aggressive_sweep, cancel_heavy);
+criterion_main!(benches);
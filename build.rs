@@ -0,0 +1,44 @@
+// gRPC service stubs for `proto/orderbook.proto`.
+//
+// Messages are written by hand in `src/gateway/grpc.rs`,
+// so only the service code is generated and protoc is not required.
+
+#[cfg(feature = "grpc")]
+fn main() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("super::{}", input))
+            .output_type(format!("super::{}", output))
+            .codec_path("tonic::codec::ProstCodec")
+    };
+
+    let service = Service::builder()
+        .name("MatchingEngine")
+        .package("orderbook")
+        .method(method("submit_order", "SubmitOrder", "SubmitOrderRequest", "OrderEvents").build())
+        .method(method("cancel_order", "CancelOrder", "CancelOrderRequest", "OrderEvents").build())
+        .method(method("amend_order", "AmendOrder", "AmendOrderRequest", "OrderEvents").build())
+        .method(method("get_depth", "GetDepth", "DepthRequest", "Depth").build())
+        .method(
+            method("stream_trades", "StreamTrades", "StreamTradesRequest", "Trade")
+                .server_streaming()
+                .build(),
+        )
+        .method(
+            method("stream_book_updates", "StreamBookUpdates", "StreamBookRequest", "BookUpdate")
+                .server_streaming()
+                .build(),
+        )
+        .build();
+
+    Builder::new().compile(&[service]);
+}
+
+#[cfg(not(feature = "grpc"))]
+fn main() {}
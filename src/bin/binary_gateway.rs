@@ -0,0 +1,68 @@
+use std::env;
+use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
+use std::process;
+use std::thread;
+use orderbook::Orderbook;
+use orderbook::binary::{self, BinaryGateway};
+
+mod common;
+
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: binary_gateway [--orders ADDR] [--market-data ADDR] \
+         [--multicast GROUP:PORT] [--symbol ORDER/PRICE]"
+    );
+    process::exit(2);
+}
+
+
+fn main() {
+    let mut orders_addr = "127.0.0.1:9890".to_string();
+    let mut market_data_addr = "127.0.0.1:9891".to_string();
+    let mut multicast = None;
+    let mut symbol = "BTC/USD".to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--orders" => orders_addr = value,
+            "--market-data" => market_data_addr = value,
+            "--multicast" => multicast = Some(value.parse::<SocketAddrV4>().unwrap_or_else(|_| usage())),
+            "--symbol" => symbol = value,
+            _ => usage(),
+        }
+    }
+
+    let (order_asset, price_asset) = common::parse_symbol(&symbol);
+
+    let gateway = BinaryGateway::new(Orderbook::new(order_asset, price_asset));
+    if let Some(group) = multicast {
+        let socket = binary::multicast_socket(Ipv4Addr::LOCALHOST).unwrap_or_else(|err| {
+            eprintln!("failed to open multicast socket: {}", err);
+            process::exit(1);
+        });
+        gateway.publish_multicast(socket, group.into());
+        println!("market data multicast to {}", group);
+    }
+
+    let orders = bind(&orders_addr);
+    let market_data = bind(&market_data_addr);
+    println!("order entry on {}, market data on {}", orders_addr, market_data_addr);
+
+    let feed = gateway.clone();
+    thread::spawn(move || feed.serve_market_data(market_data));
+    if let Err(err) = gateway.serve_orders(orders) {
+        eprintln!("gateway stopped: {}", err);
+        process::exit(1);
+    }
+}
+
+
+fn bind(addr: &str) -> TcpListener {
+    TcpListener::bind(addr).unwrap_or_else(|err| {
+        eprintln!("failed to listen on {}: {}", addr, err);
+        process::exit(1);
+    })
+}
@@ -0,0 +1,37 @@
+//! Asset and symbol parsing shared by the binaries
+
+use std::process;
+
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum BrokerAsset {
+    USD,
+    EUR,
+    BTC,
+    ETH,
+}
+
+
+fn parse_asset(asset: &str) -> Option<BrokerAsset> {
+    match asset {
+        "USD" => Some(BrokerAsset::USD),
+        "EUR" => Some(BrokerAsset::EUR),
+        "BTC" => Some(BrokerAsset::BTC),
+        "ETH" => Some(BrokerAsset::ETH),
+        _ => None,
+    }
+}
+
+
+/// Order and price assets of an `ORDER/PRICE` symbol, exits on unknown symbol
+pub fn parse_symbol(symbol: &str) -> (BrokerAsset, BrokerAsset) {
+    let mut assets = symbol.split('/').map(parse_asset);
+    match (assets.next(), assets.next(), assets.next()) {
+        (Some(Some(order_asset)), Some(Some(price_asset)), None) => (order_asset, price_asset),
+        _ => {
+            eprintln!("unknown symbol {}", symbol);
+            process::exit(2);
+        }
+    }
+}
@@ -1,6 +1,3 @@
-
-extern crate orderbook;
-
 use std::time::SystemTime;
 use orderbook::{Orderbook, OrderSide, orders};
 
@@ -34,6 +31,7 @@ fn main() {
     let order_list =
         vec![
             orders::new_limit_order_request(
+                1,
                 order_asset,
                 price_asset,
                 OrderSide::Bid,
@@ -43,6 +41,7 @@ fn main() {
             ),
 
             orders::new_limit_order_request(
+                1,
                 order_asset,
                 price_asset,
                 OrderSide::Ask,
@@ -51,9 +50,10 @@ fn main() {
                 SystemTime::now()
             ),
 
-            orders::amend_order_request(1, OrderSide::Bid, 0.99, 4.0, SystemTime::now()),
+            orders::amend_order_request(1, 1, OrderSide::Bid, 0.99, 4.0, SystemTime::now()),
 
             orders::new_limit_order_request(
+                1,
                 order_asset,
                 price_asset,
                 OrderSide::Bid,
@@ -63,6 +63,7 @@ fn main() {
             ),
 
             orders::new_limit_order_request(
+                1,
                 order_asset,
                 price_asset,
                 OrderSide::Ask,
@@ -71,9 +72,10 @@ fn main() {
                 SystemTime::now()
             ),
 
-            orders::new_market_order_request(order_asset, price_asset, OrderSide::Bid, 1.0, SystemTime::now()),
+            orders::new_market_order_request(1, order_asset, price_asset, OrderSide::Bid, 1.0, SystemTime::now()),
 
             orders::new_limit_order_request(
+                1,
                 order_asset,
                 price_asset,
                 OrderSide::Ask,
@@ -82,9 +84,10 @@ fn main() {
                 SystemTime::now()
             ),
 
-            orders::limit_order_cancel_request(4, OrderSide::Ask),
+            orders::limit_order_cancel_request(1, 4, OrderSide::Ask),
 
             orders::new_limit_order_request(
+                1,
                 order_asset,
                 price_asset,
                 OrderSide::Bid,
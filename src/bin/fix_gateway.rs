@@ -0,0 +1,48 @@
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use orderbook::Orderbook;
+use orderbook::fix::{FixConfig, FixGateway};
+
+mod common;
+
+
+fn usage() -> ! {
+    eprintln!("usage: fix_gateway [--listen ADDR] [--symbol ORDER/PRICE] [--comp-id ID]");
+    process::exit(2);
+}
+
+
+fn main() {
+    let mut listen = "127.0.0.1:9878".to_string();
+    let mut symbol = "BTC/USD".to_string();
+    let mut comp_id = "ORDERBOOK".to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--listen" => listen = value,
+            "--symbol" => symbol = value,
+            "--comp-id" => comp_id = value,
+            _ => usage(),
+        }
+    }
+
+    let (order_asset, price_asset) = common::parse_symbol(&symbol);
+
+    let gateway = FixGateway::new(
+        Orderbook::new(order_asset, price_asset),
+        FixConfig { comp_id, symbol },
+    );
+    let listener = TcpListener::bind(&listen).unwrap_or_else(|err| {
+        eprintln!("failed to listen on {}: {}", listen, err);
+        process::exit(1);
+    });
+    println!("FIX gateway listening on {}", listen);
+
+    if let Err(err) = gateway.serve(listener) {
+        eprintln!("gateway stopped: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,48 @@
+use std::env;
+use std::process;
+use orderbook::Orderbook;
+use orderbook::grpc::GrpcServer;
+use tokio::net::TcpListener;
+use tokio::runtime;
+
+mod common;
+
+
+fn usage() -> ! {
+    eprintln!("usage: grpc_server [--listen ADDR] [--symbol ORDER/PRICE]");
+    process::exit(2);
+}
+
+
+fn main() {
+    let mut listen = "127.0.0.1:50051".to_string();
+    let mut symbol = "BTC/USD".to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--listen" => listen = value,
+            "--symbol" => symbol = value,
+            _ => usage(),
+        }
+    }
+
+    let (order_asset, price_asset) = common::parse_symbol(&symbol);
+
+    let rt = runtime::Builder::new_multi_thread().enable_all().build().unwrap_or_else(|err| {
+        eprintln!("failed to start runtime: {}", err);
+        process::exit(1);
+    });
+    let server = GrpcServer::new(Orderbook::new(order_asset, price_asset));
+    let listener = rt.block_on(TcpListener::bind(&listen)).unwrap_or_else(|err| {
+        eprintln!("failed to listen on {}: {}", listen, err);
+        process::exit(1);
+    });
+    println!("gRPC server listening on {}", listen);
+
+    if let Err(err) = rt.block_on(server.serve(listener)) {
+        eprintln!("server stopped: {}", err);
+        process::exit(1);
+    }
+}
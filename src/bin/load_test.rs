@@ -0,0 +1,110 @@
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+use orderbook::{Failed, Orderbook};
+use orderbook::generator::{Generator, GeneratorConfig};
+
+mod common;
+
+
+// same as in orderbook
+const DEFAULT_MAX_ORDER_ID: u64 = 1000;
+
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: load_test [--requests N] [--depth LEVELS] [--max-order-id N] \
+         [--symbol ORDER/PRICE] [--seed N] [--market WEIGHT] [--limit WEIGHT] [--cancel WEIGHT]"
+    );
+    process::exit(2);
+}
+
+
+fn main() {
+    let mut requests = 1_000_000;
+    let mut depth = 100;
+    let mut max_order_id = DEFAULT_MAX_ORDER_ID;
+    let mut symbol = "BTC/USD".to_string();
+    let mut config = GeneratorConfig::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--requests" => requests = parse_or_usage(&value),
+            "--depth" => depth = parse_or_usage(&value),
+            "--max-order-id" => max_order_id = parse_or_usage(&value),
+            "--symbol" => symbol = value,
+            "--seed" => config.seed = parse_or_usage(&value),
+            "--market" => config.market_weight = parse_or_usage(&value),
+            "--limit" => config.limit_weight = parse_or_usage(&value),
+            "--cancel" => config.cancel_weight = parse_or_usage(&value),
+            _ => usage(),
+        }
+    }
+
+    // every level has a resting order on both sides, and engine IDs wrap after max_order_id
+    if depth as u64 * 2 > max_order_id {
+        eprintln!("depth {} needs {} order IDs, raise --max-order-id", depth, depth * 2);
+        process::exit(2);
+    }
+
+    let (order_asset, price_asset) = common::parse_symbol(&symbol);
+    let mut orderbook = Orderbook::new(order_asset, price_asset);
+    orderbook.set_max_order_id(max_order_id);
+    let mut generator = Generator::new(order_asset, price_asset, config);
+    for request in generator.book(depth) {
+        let results = orderbook.process_order(request.clone());
+        generator.observe(&request, &results);
+    }
+    let initial = orderbook.depth(usize::MAX);
+    let initial_orders = generator.live_orders();
+
+    // requests rejected because the ID of a resting order came around are not timed
+    let mut duplicates = 0;
+    let mut latencies = Vec::with_capacity(requests);
+    let started = Instant::now();
+    for _ in 0..requests {
+        let (_, request) = generator.next_request();
+        let observed = request.clone();
+
+        let processing = Instant::now();
+        let results = orderbook.process_order(request);
+        let elapsed = processing.elapsed();
+        if results.iter().any(|result| matches!(*result, Err(Failed::DuplicateOrderID(_)))) {
+            duplicates += 1;
+        } else {
+            latencies.push(elapsed);
+        }
+
+        generator.observe(&observed, &results);
+    }
+    let wall = started.elapsed();
+
+    let busy: Duration = latencies.iter().sum();
+    latencies.sort_unstable();
+    let percentile = |p: f64| {
+        let idx = ((latencies.len() as f64 * p).ceil() as usize).clamp(1, latencies.len()) - 1;
+        latencies[idx].as_nanos()
+    };
+
+    let timed = latencies.len();
+    let book = orderbook.depth(usize::MAX);
+    println!("requests {}, {} order IDs", requests, max_order_id);
+    println!("initial depth {} bid / {} ask levels, {} resting orders",
+        initial.bids.len(), initial.asks.len(), initial_orders);
+    println!("final depth {} bid / {} ask levels, {} resting orders",
+        book.bids.len(), book.asks.len(), generator.live_orders());
+    println!("duplicate order ID rejects {}", duplicates);
+    println!("throughput {:.0} req/s, {:.0} req/s including generation",
+        timed as f64 / busy.as_secs_f64(), timed as f64 / wall.as_secs_f64());
+    if !latencies.is_empty() {
+        println!("latency ns: p50 {} p90 {} p99 {} p99.9 {} max {}",
+            percentile(0.5), percentile(0.9), percentile(0.99), percentile(0.999), percentile(1.0));
+    }
+}
+
+
+fn parse_or_usage<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| usage())
+}
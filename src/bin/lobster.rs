@@ -0,0 +1,67 @@
+use std::env;
+use std::fs::File;
+use std::process;
+use std::path::{Path, PathBuf};
+use orderbook::lobster;
+
+mod common;
+
+
+fn usage() -> ! {
+    eprintln!("usage: lobster --messages FILE --orderbook FILE [--symbol ORDER/PRICE] [--show N]");
+    process::exit(2);
+}
+
+
+fn main() {
+    let mut messages = None;
+    let mut book = None;
+    let mut symbol = "BTC/USD".to_string();
+    let mut show = 20;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--messages" => messages = Some(PathBuf::from(value)),
+            "--orderbook" => book = Some(PathBuf::from(value)),
+            "--symbol" => symbol = value,
+            "--show" => show = value.parse().unwrap_or_else(|_| usage()),
+            _ => usage(),
+        }
+    }
+    let (messages, book) = match (messages, book) {
+        (Some(messages), Some(book)) => (messages, book),
+        _ => usage(),
+    };
+
+    let (order_asset, price_asset) = common::parse_symbol(&symbol);
+
+    let report = lobster::reconstruct(order_asset, price_asset, open(&messages), open(&book))
+        .unwrap_or_else(|err| {
+            eprintln!("failed to read LOBSTER files: {}", err);
+            process::exit(1);
+        });
+
+    for discrepancy in report.discrepancies.iter().take(show) {
+        println!("{}", discrepancy);
+    }
+    if report.discrepancies.len() > show {
+        println!("... {} more", report.discrepancies.len() - show);
+    }
+    println!(
+        "messages {}, skipped {}, with discrepancies {}",
+        report.messages, report.skipped, report.mismatched
+    );
+    if !report.discrepancies.is_empty() {
+        process::exit(1);
+    }
+}
+
+
+fn open(path: &Path) -> File {
+    File::open(path).unwrap_or_else(|err| {
+        eprintln!("failed to open {}: {}", path.display(), err);
+        process::exit(1);
+    })
+}
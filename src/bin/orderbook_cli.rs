@@ -0,0 +1,93 @@
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process;
+use std::path::PathBuf;
+use orderbook::repl::{Command, Session};
+use common::BrokerAsset;
+
+mod common;
+
+
+fn usage() -> ! {
+    eprintln!("usage: orderbook_cli [--symbol ORDER/PRICE] [--load FILE] [--script FILE]");
+    process::exit(2);
+}
+
+
+fn main() {
+    let mut symbol = "BTC/USD".to_string();
+    let mut load = None;
+    let mut script = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--symbol" => symbol = value,
+            "--load" => load = Some(PathBuf::from(value)),
+            "--script" => script = Some(PathBuf::from(value)),
+            _ => usage(),
+        }
+    }
+
+    let (order_asset, price_asset) = common::parse_symbol(&symbol);
+
+    let mut session = Session::new(order_asset, price_asset);
+    if let Some(path) = load {
+        run_or_exit(&mut session, Command::Load(path));
+    }
+    // scripted run does not read stdin
+    if let Some(path) = script {
+        run_or_exit(&mut session, Command::Run(path));
+        return;
+    }
+
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    if interactive {
+        println!("orderbook {}, type 'help' for commands", symbol);
+    }
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("> ");
+            let _ = io::stdout().flush();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(err)) => {
+                eprintln!("failed to read input: {}", err);
+                process::exit(1);
+            }
+            None => break,
+        };
+
+        match Command::parse(&line) {
+            Ok(Some(Command::Quit)) => break,
+            Ok(Some(command)) => match session.run(command) {
+                Ok(output) => print_output(&output),
+                Err(err) => eprintln!("error: {}", err),
+            },
+            Ok(None) => (),
+            Err(err) => eprintln!("error: {}", err),
+        }
+    }
+}
+
+
+fn run_or_exit(session: &mut Session<BrokerAsset>, command: Command) {
+    match session.run(command) {
+        Ok(output) => print_output(&output),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+
+fn print_output(output: &str) {
+    if !output.is_empty() {
+        println!("{}", output);
+    }
+}
@@ -0,0 +1,92 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use orderbook::replay::{self, Format, RecordWriter, Replay, ReplayConfig};
+
+mod common;
+
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: replay --input FILE [--trades FILE] [--book FILE] [--symbol ORDER/PRICE] \
+         [--speed X] [--start NS] [--end NS] [--depth N] [--interval MS] [--max-order-id N]"
+    );
+    process::exit(2);
+}
+
+
+fn main() {
+    let mut input = None;
+    let mut trades = None;
+    let mut book = None;
+    let mut symbol = "BTC/USD".to_string();
+    let mut config = ReplayConfig::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--input" => input = Some(PathBuf::from(value)),
+            "--trades" => trades = Some(PathBuf::from(value)),
+            "--book" => book = Some(PathBuf::from(value)),
+            "--symbol" => symbol = value,
+            "--speed" => config.speed = Some(parse_or_usage::<f64>(&value)).filter(|speed| *speed > 0.0),
+            "--start" => config.start = Some(UNIX_EPOCH + Duration::from_nanos(parse_or_usage(&value))),
+            "--end" => config.end = Some(UNIX_EPOCH + Duration::from_nanos(parse_or_usage(&value))),
+            "--depth" => config.depth = parse_or_usage(&value),
+            "--interval" => config.sample_interval = Some(Duration::from_millis(parse_or_usage(&value))),
+            "--max-order-id" => config.max_order_id = Some(parse_or_usage(&value)),
+            _ => usage(),
+        }
+    }
+    let input = input.unwrap_or_else(|| usage());
+
+    let (order_asset, price_asset) = common::parse_symbol(&symbol);
+
+    let events = File::open(&input).unwrap_or_else(|err| {
+        eprintln!("failed to open {}: {}", input.display(), err);
+        process::exit(1);
+    });
+    let mut trades = output(trades.as_deref());
+    let mut book = output(book.as_deref());
+
+    let mut replay = Replay::new(order_asset, price_asset, config);
+    let result = replay.run(
+        replay::read_events(events, Format::from_path(&input)),
+        &mut trades,
+        &mut book,
+    );
+
+    let stats = replay.stats();
+    eprintln!(
+        "events {}, skipped {}, rejected {}, trades {}, book samples {}",
+        stats.events, stats.skipped, stats.rejected, stats.trades, stats.samples
+    );
+    if let Err(err) = result {
+        eprintln!("replay failed: {}", err);
+        process::exit(1);
+    }
+}
+
+
+fn parse_or_usage<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| usage())
+}
+
+
+/// Records are dropped if the path is not given
+fn output(path: Option<&Path>) -> RecordWriter<Box<dyn Write>> {
+    match path {
+        Some(path) => {
+            let file = File::create(path).unwrap_or_else(|err| {
+                eprintln!("failed to create {}: {}", path.display(), err);
+                process::exit(1);
+            });
+            RecordWriter::new(Box::new(BufWriter::new(file)), Format::from_path(path))
+        }
+        None => RecordWriter::new(Box::new(io::sink()), Format::Ndjson),
+    }
+}
@@ -0,0 +1,43 @@
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use orderbook::Orderbook;
+use orderbook::ws::WsServer;
+
+mod common;
+
+
+fn usage() -> ! {
+    eprintln!("usage: ws_server [--listen ADDR] [--symbol ORDER/PRICE]");
+    process::exit(2);
+}
+
+
+fn main() {
+    let mut listen = "127.0.0.1:9880".to_string();
+    let mut symbol = "BTC/USD".to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--listen" => listen = value,
+            "--symbol" => symbol = value,
+            _ => usage(),
+        }
+    }
+
+    let (order_asset, price_asset) = common::parse_symbol(&symbol);
+
+    let server = WsServer::new(Orderbook::new(order_asset, price_asset));
+    let listener = TcpListener::bind(&listen).unwrap_or_else(|err| {
+        eprintln!("failed to listen on {}: {}", listen, err);
+        process::exit(1);
+    });
+    println!("WebSocket server listening on ws://{}", listen);
+
+    if let Err(err) = server.serve(listener) {
+        eprintln!("server stopped: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+
+const LOCKED_ROUNDING: f64 = 1e-9;
+
+
+/// Funds of a single owner in one asset
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Balance {
+    /// Could be used for new orders or withdrawn
+    pub available: f64,
+    /// Reserved by active orders
+    pub locked: f64,
+}
+
+
+/// Ledger of owners' balances.
+///
+/// Funds required by order are moved from available to locked
+/// balance on acceptance and spent from there on fills.
+#[derive(Debug, Clone)]
+pub struct Accounts<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    // every owner holds just a few assets, so plain list is enough
+    balances: HashMap<u64, Vec<(Asset, Balance)>>,
+}
+
+
+impl<Asset> Accounts<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub fn new() -> Self {
+        Accounts { balances: HashMap::new() }
+    }
+
+
+    pub fn balance(&self, owner: u64, asset: Asset) -> Balance {
+        self.balances
+            .get(&owner)
+            .and_then(|assets| assets.iter().find(|&&(a, _)| a == asset))
+            .map(|&(_, balance)| balance)
+            .unwrap_or_default()
+    }
+
+
+    pub fn deposit(&mut self, owner: u64, asset: Asset, amount: f64) {
+        self.balance_mut(owner, asset).available += amount;
+    }
+
+
+    /// Take funds from available balance, if there is enough
+    pub fn withdraw(&mut self, owner: u64, asset: Asset, amount: f64) -> bool {
+        let balance = self.balance_mut(owner, asset);
+        if balance.available < amount {
+            return false;
+        }
+        balance.available -= amount;
+        true
+    }
+
+
+    /// Take up to `amount` from available balance, returns amount taken
+    pub fn charge(&mut self, owner: u64, asset: Asset, amount: f64) -> f64 {
+        let balance = self.balance_mut(owner, asset);
+        let amount = amount.min(balance.available);
+        balance.available -= amount;
+        amount
+    }
+
+
+    /// Lock funds for order, if there is enough available
+    pub fn reserve(&mut self, owner: u64, asset: Asset, amount: f64) -> bool {
+        let balance = self.balance_mut(owner, asset);
+        if balance.available < amount {
+            return false;
+        }
+        balance.available -= amount;
+        balance.locked += amount;
+        true
+    }
+
+
+    /// Return locked funds to available balance
+    pub fn release(&mut self, owner: u64, asset: Asset, amount: f64) {
+        let balance = self.balance_mut(owner, asset);
+        let amount = amount.min(balance.locked);
+        balance.locked -= amount;
+        balance.available += amount;
+    }
+
+
+    /// Move locked funds to another owner's available balance.
+    ///
+    /// Never moves more than is locked, returns amount moved.
+    pub fn transfer_locked(&mut self, from: u64, to: u64, asset: Asset, amount: f64) -> f64 {
+        let amount = {
+            let balance = self.balance_mut(from, asset);
+            // allow for rounding of the locked sums
+            debug_assert!(
+                amount <= balance.locked + LOCKED_ROUNDING * amount.abs().max(1.0),
+                "transfer of {} exceeds locked {}",
+                amount,
+                balance.locked,
+            );
+            let amount = amount.min(balance.locked);
+            balance.locked -= amount;
+            amount
+        };
+        self.balance_mut(to, asset).available += amount;
+        amount
+    }
+
+
+    fn balance_mut(&mut self, owner: u64, asset: Asset) -> &mut Balance {
+        let assets = self.balances.entry(owner).or_default();
+        let pos = match assets.iter().position(|&(a, _)| a == asset) {
+            Some(pos) => pos,
+            None => {
+                assets.push((asset, Balance::default()));
+                assets.len() - 1
+            }
+        };
+        &mut assets[pos].1
+    }
+}
+
+
+impl<Asset> Default for Accounts<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_and_release() {
+        let mut accounts = Accounts::new();
+        accounts.deposit(1, "USD", 100.0);
+
+        assert!(!accounts.reserve(1, "USD", 150.0));
+        assert!(accounts.reserve(1, "USD", 60.0));
+        assert_eq!(accounts.balance(1, "USD"), Balance { available: 40.0, locked: 60.0 });
+        assert!(!accounts.withdraw(1, "USD", 50.0));
+
+        accounts.release(1, "USD", 20.0);
+        accounts.transfer_locked(1, 2, "USD", 40.0);
+        assert_eq!(accounts.balance(1, "USD"), Balance { available: 60.0, locked: 0.0 });
+        assert_eq!(accounts.balance(2, "USD"), Balance { available: 40.0, locked: 0.0 });
+        assert_eq!(accounts.balance(2, "BTC"), Balance::default());
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "exceeds locked"))]
+    fn transfer_limited_by_locked() {
+        let mut accounts = Accounts::new();
+        accounts.deposit(1, "USD", 100.0);
+        accounts.reserve(1, "USD", 30.0);
+
+        assert_eq!(accounts.transfer_locked(1, 2, "USD", 50.0), 30.0);
+        assert_eq!(accounts.balance(1, "USD"), Balance { available: 70.0, locked: 0.0 });
+        assert_eq!(accounts.balance(2, "USD"), Balance { available: 30.0, locked: 0.0 });
+    }
+}
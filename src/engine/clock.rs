@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// Source of timestamps for engine events.
+///
+/// Orderbook asks the clock once per request, so all the events
+/// generated while processing a single request share the same timestamp.
+pub trait Clock: Send {
+    /// Get timestamp for the request being processed.
+    ///
+    /// `request_ts` is the time supplied with request by client, if any.
+    fn now(&mut self, request_ts: Option<SystemTime>) -> SystemTime;
+}
+
+
+/// Wall clock, ignores request timestamps
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&mut self, _request_ts: Option<SystemTime>) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+
+/// Simulated clock, changed only manually.
+///
+/// Clones share the same time, so one could keep a copy
+/// and move the time forward after passing the clock to orderbook.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    epoch_nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        ManualClock { epoch_nanos: Arc::new(AtomicU64::new(to_epoch_nanos(start))) }
+    }
+
+    pub fn set(&self, ts: SystemTime) {
+        self.epoch_nanos.store(to_epoch_nanos(ts), Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, delta: Duration) {
+        self.epoch_nanos.fetch_add(
+            delta.as_secs() * 1_000_000_000 + u64::from(delta.subsec_nanos()),
+            Ordering::SeqCst,
+        );
+    }
+
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_nanos(self.epoch_nanos.load(Ordering::SeqCst))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&mut self, _request_ts: Option<SystemTime>) -> SystemTime {
+        self.time()
+    }
+}
+
+
+/// Clock trusting timestamps supplied with requests.
+///
+/// Requests without timestamp (cancels) get the latest seen one.
+#[derive(Debug, Copy, Clone)]
+pub struct RequestClock {
+    last_ts: SystemTime,
+}
+
+impl RequestClock {
+    pub fn new() -> Self {
+        RequestClock { last_ts: UNIX_EPOCH }
+    }
+}
+
+impl Default for RequestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for RequestClock {
+    fn now(&mut self, request_ts: Option<SystemTime>) -> SystemTime {
+        if let Some(ts) = request_ts {
+            self.last_ts = ts;
+        }
+        self.last_ts
+    }
+}
+
+
+fn to_epoch_nanos(ts: SystemTime) -> u64 {
+    let since_epoch = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_secs() * 1_000_000_000 + u64::from(since_epoch.subsec_nanos())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_shared_between_clones() {
+        let start = UNIX_EPOCH + Duration::from_secs(100);
+        let handle = ManualClock::new(start);
+        let mut clock = handle.clone();
+
+        assert_eq!(clock.now(None), start);
+        handle.advance(Duration::from_millis(1500));
+        assert_eq!(clock.now(Some(UNIX_EPOCH)), start + Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn request_clock_keeps_last_timestamp() {
+        let mut clock = RequestClock::new();
+        let ts = UNIX_EPOCH + Duration::from_secs(5);
+
+        assert_eq!(clock.now(None), UNIX_EPOCH);
+        assert_eq!(clock.now(Some(ts)), ts);
+        assert_eq!(clock.now(None), ts);
+    }
+}
@@ -1,18 +1,33 @@
 
 use std::fmt::Debug;
 
-#[derive(Debug, Copy, Clone)]
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OrderSide {
     Bid,
     Ask,
 }
 
+impl OrderSide {
+    pub fn opposite(self) -> OrderSide {
+        match self {
+            OrderSide::Bid => OrderSide::Ask,
+            OrderSide::Ask => OrderSide::Bid,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Order<Asset>
 where
     Asset: Debug + Clone,
 {
     pub order_id: u64,
+    pub owner: u64,
     pub order_asset: Asset,
     pub price_asset: Asset,
     pub side: OrderSide,
@@ -22,6 +37,7 @@ where
 
 
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OrderType {
     Market,
     Limit,
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use super::orderbook::{Failed, Orderbook, Success};
+use super::orders::OrderRequest;
+
+
+/// Pair of assets traded in a single orderbook
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Instrument<Asset> {
+    pub order_asset: Asset,
+    pub price_asset: Asset,
+}
+
+impl<Asset> Instrument<Asset> {
+    pub fn new(order_asset: Asset, price_asset: Asset) -> Self {
+        Instrument {
+            order_asset,
+            price_asset,
+        }
+    }
+}
+
+
+/// Orderbook event numbered in exchange-wide sequence
+#[derive(Debug)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub event: Result<Success, Failed>,
+}
+
+
+/// Result of request processing by one of the orderbooks
+#[derive(Debug)]
+pub struct ExchangeResult<Asset> {
+    pub instrument: Instrument<Asset>,
+    pub events: Vec<SequencedEvent>,
+}
+
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExchangeError<Asset> {
+    UnknownInstrument(Instrument<Asset>),
+    InstrumentExists(Instrument<Asset>),
+    /// Amend and cancel requests carry no assets, use `process_order_for`
+    InstrumentRequired,
+}
+
+
+/// Matching engine, hosting orderbooks for many instruments
+pub struct Exchange<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    books: HashMap<Instrument<Asset>, Orderbook<Asset>>,
+    event_seq: u64,
+}
+
+
+impl<Asset> Exchange<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        Exchange {
+            books: HashMap::new(),
+            event_seq: 0,
+        }
+    }
+
+
+    /// Start trading new instrument with default orderbook
+    pub fn add_instrument(
+        &mut self,
+        order_asset: Asset,
+        price_asset: Asset,
+    ) -> Result<(), ExchangeError<Asset>> {
+        self.add_orderbook(Orderbook::new(order_asset, price_asset))
+    }
+
+
+    /// Start trading instrument with preconfigured (or restored) orderbook
+    pub fn add_orderbook(&mut self, orderbook: Orderbook<Asset>) -> Result<(), ExchangeError<Asset>> {
+        let instrument = Instrument::new(orderbook.order_asset(), orderbook.price_asset());
+        if self.books.contains_key(&instrument) {
+            return Err(ExchangeError::InstrumentExists(instrument));
+        }
+        self.books.insert(instrument, orderbook);
+        Ok(())
+    }
+
+
+    /// Stop trading instrument, returning its orderbook
+    pub fn remove_instrument(&mut self, instrument: &Instrument<Asset>) -> Option<Orderbook<Asset>> {
+        self.books.remove(instrument)
+    }
+
+
+    pub fn instruments(&self) -> Vec<Instrument<Asset>> {
+        self.books.keys().cloned().collect()
+    }
+
+
+    pub fn orderbook(&self, instrument: &Instrument<Asset>) -> Option<&Orderbook<Asset>> {
+        self.books.get(instrument)
+    }
+
+
+    pub fn orderbook_mut(&mut self, instrument: &Instrument<Asset>) -> Option<&mut Orderbook<Asset>> {
+        self.books.get_mut(instrument)
+    }
+
+
+    /// Sequence number of the last event produced by exchange
+    pub fn event_seq(&self) -> u64 {
+        self.event_seq
+    }
+
+
+    /// Route new order to orderbook by its asset pair
+    pub fn process_order(
+        &mut self,
+        order: OrderRequest<Asset>,
+    ) -> Result<ExchangeResult<Asset>, ExchangeError<Asset>> {
+        let instrument = match order {
+            OrderRequest::NewMarketOrder { order_asset, price_asset, .. } |
+            OrderRequest::NewLimitOrder { order_asset, price_asset, .. } => {
+                Instrument::new(order_asset, price_asset)
+            }
+            OrderRequest::AmendOrder { .. } |
+            OrderRequest::CancelOrder { .. } => return Err(ExchangeError::InstrumentRequired),
+        };
+        self.process_order_for(instrument, order)
+    }
+
+
+    /// Process any request by orderbook of given instrument
+    pub fn process_order_for(
+        &mut self,
+        instrument: Instrument<Asset>,
+        order: OrderRequest<Asset>,
+    ) -> Result<ExchangeResult<Asset>, ExchangeError<Asset>> {
+        let results = match self.books.get_mut(&instrument) {
+            Some(orderbook) => orderbook.process_order(order),
+            None => return Err(ExchangeError::UnknownInstrument(instrument)),
+        };
+
+        let events = results
+            .into_iter()
+            .map(|event| {
+                self.event_seq += 1;
+                SequencedEvent {
+                    seq: self.event_seq,
+                    event,
+                }
+            })
+            .collect();
+
+        Ok(ExchangeResult { instrument, events })
+    }
+}
+
+
+impl<Asset> Default for Exchange<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::domain::OrderSide;
+    use super::super::orders;
+    use std::time::SystemTime;
+
+    #[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+    #[allow(clippy::upper_case_acronyms)]
+    enum Asset {
+        USD,
+        BTC,
+        ETH,
+    }
+
+    #[test]
+    fn routing_by_asset_pair() {
+        let mut exchange = Exchange::new();
+        let btc_usd = Instrument::new(Asset::BTC, Asset::USD);
+        let eth_usd = Instrument::new(Asset::ETH, Asset::USD);
+
+        assert_eq!(exchange.add_instrument(Asset::BTC, Asset::USD), Ok(()));
+        assert_eq!(exchange.add_instrument(Asset::ETH, Asset::USD), Ok(()));
+        assert_eq!(
+            exchange.add_instrument(Asset::BTC, Asset::USD),
+            Err(ExchangeError::InstrumentExists(btc_usd))
+        );
+
+        let result = exchange
+            .process_order(orders::new_limit_order_request(
+                1,
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                10.0,
+                1.0,
+                SystemTime::now(),
+            ))
+            .unwrap();
+        assert_eq!(result.instrument, btc_usd);
+        assert_eq!(result.events[0].seq, 1);
+
+        // market order in other book finds no match
+        let result = exchange
+            .process_order(orders::new_market_order_request(
+                1,
+                Asset::ETH,
+                Asset::USD,
+                OrderSide::Ask,
+                1.0,
+                SystemTime::now(),
+            ))
+            .unwrap();
+        assert_eq!(result.instrument, eth_usd);
+        assert_eq!(result.events.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(matches!(result.events[1].event, Err(Failed::NoMatch(1))));
+
+        // cancel needs explicit instrument
+        let cancel = orders::limit_order_cancel_request(1, 1, OrderSide::Bid);
+        assert_eq!(
+            exchange.process_order(cancel.clone()).err(),
+            Some(ExchangeError::InstrumentRequired)
+        );
+        let result = exchange.process_order_for(btc_usd, cancel).unwrap();
+        assert!(matches!(result.events[0].event, Ok(Success::Cancelled { id: 1, .. })));
+        assert_eq!(exchange.event_seq(), 4);
+    }
+
+    #[test]
+    fn add_remove_instruments() {
+        let mut exchange = Exchange::new();
+        let btc_eth = Instrument::new(Asset::BTC, Asset::ETH);
+
+        let request = orders::new_market_order_request(
+            1,
+            Asset::BTC,
+            Asset::ETH,
+            OrderSide::Bid,
+            1.0,
+            SystemTime::now(),
+        );
+        assert_eq!(
+            exchange.process_order(request.clone()).err(),
+            Some(ExchangeError::UnknownInstrument(btc_eth))
+        );
+
+        exchange.add_orderbook(Orderbook::new(Asset::BTC, Asset::ETH)).unwrap();
+        assert_eq!(exchange.instruments(), vec![btc_eth]);
+        assert!(exchange.process_order(request.clone()).is_ok());
+
+        assert!(exchange.remove_instrument(&btc_eth).is_some());
+        assert!(exchange.instruments().is_empty());
+        assert!(exchange.process_order(request).is_err());
+    }
+}
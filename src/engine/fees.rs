@@ -0,0 +1,235 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+
+/// Default length of the volume window used for tier selection
+pub const DEFAULT_VOLUME_WINDOW: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+
+/// Asset fees are charged in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FeeAsset {
+    /// Fee is a share of the deal notional
+    Price,
+    /// Fee is a share of the deal quantity
+    Order,
+}
+
+
+/// Fee rates as a fraction of the deal, negative rate is a rebate
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FeeRates {
+    pub maker: f64,
+    pub taker: f64,
+}
+
+
+/// Rates applied to owners who traded at least `min_volume` within the window
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FeeTier {
+    pub min_volume: f64,
+    pub rates: FeeRates,
+}
+
+
+/// Notional traded by owner, recent fills only
+#[derive(Debug, Clone, Default)]
+struct RollingVolume {
+    fills: VecDeque<(SystemTime, f64)>,
+    total: f64,
+}
+
+impl RollingVolume {
+    fn add(&mut self, ts: SystemTime, notional: f64) {
+        self.fills.push_back((ts, notional));
+        self.total += notional;
+    }
+
+    /// Forget fills made before `since`
+    fn expire(&mut self, since: SystemTime) {
+        while let Some(&(ts, notional)) = self.fills.front() {
+            if ts >= since {
+                break;
+            }
+            self.fills.pop_front();
+            self.total -= notional;
+        }
+        if self.fills.is_empty() {
+            self.total = 0.0;
+        }
+    }
+}
+
+
+/// Maker/taker fee model of the orderbook.
+///
+/// Rates are picked from the owner override, if any, otherwise from
+/// the highest tier reached by owner's volume traded within the window.
+/// Volume is counted in price asset and includes both maker and taker fills.
+#[derive(Debug, Clone)]
+pub struct FeeSchedule {
+    fee_asset: FeeAsset,
+    base_rates: FeeRates,
+    // sorted by min_volume
+    tiers: Vec<FeeTier>,
+    owner_rates: HashMap<u64, FeeRates>,
+    window: Duration,
+    volumes: HashMap<u64, RollingVolume>,
+    revenue: [f64; 2],
+}
+
+
+impl FeeSchedule {
+    /// Fee schedule with rates applied to everyone below the first tier
+    pub fn new(fee_asset: FeeAsset, base_rates: FeeRates) -> Self {
+        FeeSchedule {
+            fee_asset,
+            base_rates,
+            tiers: Vec::new(),
+            owner_rates: HashMap::new(),
+            window: DEFAULT_VOLUME_WINDOW,
+            volumes: HashMap::new(),
+            revenue: [0.0; 2],
+        }
+    }
+
+
+    pub fn fee_asset(&self) -> FeeAsset {
+        self.fee_asset
+    }
+
+
+    pub fn set_fee_asset(&mut self, fee_asset: FeeAsset) {
+        self.fee_asset = fee_asset;
+    }
+
+
+    /// Change length of the rolling volume window
+    pub fn set_volume_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+
+    /// Add volume tier, replacing the one with the same threshold
+    pub fn add_tier(&mut self, tier: FeeTier) {
+        self.tiers.retain(|t| t.min_volume != tier.min_volume);
+        self.tiers.push(tier);
+        self.tiers.sort_by(|a, b| a.min_volume.total_cmp(&b.min_volume));
+    }
+
+
+    /// Fixed rates for the owner, regardless of traded volume
+    pub fn set_owner_rates(&mut self, owner: u64, rates: FeeRates) {
+        self.owner_rates.insert(owner, rates);
+    }
+
+
+    pub fn remove_owner_rates(&mut self, owner: u64) {
+        self.owner_rates.remove(&owner);
+    }
+
+
+    /// Rates applied to owner's fills made at `now`
+    pub fn rates(&mut self, owner: u64, now: SystemTime) -> FeeRates {
+        if let Some(&rates) = self.owner_rates.get(&owner) {
+            return rates;
+        }
+
+        let volume = self.volume(owner, now);
+        self.tiers
+            .iter()
+            .rev()
+            .find(|tier| volume >= tier.min_volume)
+            .map_or(self.base_rates, |tier| tier.rates)
+    }
+
+
+    /// Owner's volume traded within the window before `now`
+    pub fn volume(&mut self, owner: u64, now: SystemTime) -> f64 {
+        let since = now.checked_sub(self.window);
+        match self.volumes.get_mut(&owner) {
+            Some(volume) => {
+                if let Some(since) = since {
+                    volume.expire(since);
+                }
+                volume.total
+            }
+            None => 0.0,
+        }
+    }
+
+
+    /// Total fees collected minus rebates paid, in given asset
+    pub fn revenue(&self, asset: FeeAsset) -> f64 {
+        self.revenue[asset as usize]
+    }
+
+
+    /// Fees of the deal as (taker fee, maker fee) in the fee asset.
+    ///
+    /// Rates are chosen by volume traded before the deal,
+    /// then the deal is added to volumes of both owners.
+    pub fn fill_fees(
+        &mut self,
+        taker: u64,
+        maker: u64,
+        price: f64,
+        qty: f64,
+        ts: SystemTime,
+    ) -> (f64, f64) {
+        let taker_rate = self.rates(taker, ts).taker;
+        let maker_rate = self.rates(maker, ts).maker;
+
+        let notional = price * qty;
+        self.volumes.entry(taker).or_default().add(ts, notional);
+        self.volumes.entry(maker).or_default().add(ts, notional);
+
+        let base = match self.fee_asset {
+            FeeAsset::Price => notional,
+            FeeAsset::Order => qty,
+        };
+        (taker_rate * base, maker_rate * base)
+    }
+
+
+    /// Account collected fee or paid rebate
+    pub fn book_revenue(&mut self, asset: FeeAsset, amount: f64) {
+        self.revenue[asset as usize] += amount;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn tiers_by_rolling_volume() {
+        let mut fees = FeeSchedule::new(FeeAsset::Price, FeeRates { maker: 0.001, taker: 0.002 });
+        fees.add_tier(FeeTier {
+            min_volume: 1000.0,
+            rates: FeeRates { maker: -0.0001, taker: 0.001 },
+        });
+        fees.set_owner_rates(9, FeeRates { maker: 0.0, taker: 0.0 });
+
+        let day = Duration::from_secs(24 * 60 * 60);
+        let start = UNIX_EPOCH + day * 100;
+
+        assert_eq!(fees.fill_fees(1, 2, 10.0, 100.0, start), (2.0, 1.0));
+        // both owners reached the tier
+        assert_eq!(fees.volume(2, start), 1000.0);
+        assert_eq!(fees.fill_fees(2, 1, 10.0, 10.0, start + day), (0.1, -0.01));
+        assert_eq!(fees.fill_fees(9, 1, 10.0, 10.0, start + day), (0.0, -0.01));
+
+        // first deal left the window
+        assert_eq!(fees.volume(1, start + day * 31), 200.0);
+        assert_eq!(fees.rates(1, start + day * 31), FeeRates { maker: 0.001, taker: 0.002 });
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+use super::orders::OrderRequest;
+
+
+/// Change of the orderbook state recorded into journal
+#[derive(Debug, Clone)]
+pub enum JournalCommand<Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// Request admitted by orderbook
+    Request(OrderRequest<Asset>),
+    /// Owner killed, their resting orders are cancelled
+    Kill(u64),
+    Revive(u64),
+    /// Global kill switch turned on
+    KillAll,
+    ReviveAll,
+}
+
+
+/// Journaled command with engine-assigned sequence number and timestamp
+#[derive(Debug, Clone)]
+pub struct JournalEntry<Asset>
+where
+    Asset: Debug + Clone,
+{
+    pub seq: u64,
+    pub ts: SystemTime,
+    pub command: JournalCommand<Asset>,
+}
+
+
+/// Append-only log of requests admitted by orderbook and kill switch changes.
+///
+/// Contains everything required to rebuild the book from scratch,
+/// see `Orderbook::replay`.
+#[derive(Debug, Clone)]
+pub struct Journal<Asset>
+where
+    Asset: Debug + Clone,
+{
+    order_asset: Asset,
+    price_asset: Asset,
+    entries: Vec<JournalEntry<Asset>>,
+}
+
+
+impl<Asset> Journal<Asset>
+where
+    Asset: Debug + Clone,
+{
+    pub fn new(order_asset: Asset, price_asset: Asset) -> Self {
+        Journal {
+            order_asset,
+            price_asset,
+            entries: Vec::new(),
+        }
+    }
+
+
+    pub fn order_asset(&self) -> &Asset {
+        &self.order_asset
+    }
+
+
+    pub fn price_asset(&self) -> &Asset {
+        &self.price_asset
+    }
+
+
+    /// Add new entry to the end of journal.
+    ///
+    /// Sequence numbers must grow, otherwise entry is rejected.
+    pub fn append(&mut self, entry: JournalEntry<Asset>) -> bool {
+        if self.last_seq().is_some_and(|last_seq| entry.seq <= last_seq) {
+            return false;
+        }
+        self.entries.push(entry);
+        true
+    }
+
+
+    pub fn entries(&self) -> &[JournalEntry<Asset>] {
+        &self.entries
+    }
+
+
+    /// Entries recorded after given sequence number
+    pub fn entries_since(&self, seq: u64) -> &[JournalEntry<Asset>] {
+        let start = self.entries
+            .iter()
+            .position(|entry| entry.seq > seq)
+            .unwrap_or(self.entries.len());
+        &self.entries[start..]
+    }
+
+
+    pub fn last_seq(&self) -> Option<u64> {
+        self.entries.last().map(|entry| entry.seq)
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::domain::OrderSide;
+    use super::super::orders;
+    use std::time::UNIX_EPOCH;
+
+    fn cancel_entry(seq: u64) -> JournalEntry<&'static str> {
+        JournalEntry {
+            seq,
+            ts: UNIX_EPOCH,
+            command: JournalCommand::Request(orders::limit_order_cancel_request(1, 1, OrderSide::Bid)),
+        }
+    }
+
+    #[test]
+    fn append_only_increasing() {
+        let mut journal = Journal::new("BTC", "USD");
+
+        assert!(journal.append(cancel_entry(1)));
+        assert!(journal.append(cancel_entry(3)));
+        assert!(!journal.append(cancel_entry(3)));
+        assert!(!journal.append(cancel_entry(2)));
+        assert_eq!(journal.len(), 2);
+        assert_eq!(journal.last_seq(), Some(3));
+
+        assert_eq!(journal.entries_since(0).len(), 2);
+        assert_eq!(journal.entries_since(1)[0].seq, 3);
+        assert!(journal.entries_since(3).is_empty());
+    }
+}
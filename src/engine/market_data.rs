@@ -0,0 +1,234 @@
+use std::cmp::Ordering;
+use std::time::SystemTime;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::domain::OrderSide;
+use super::orderbook::{OrderProcessingResult, Success};
+
+
+/// Single deal between incoming (taker) and resting (maker) orders
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Trade {
+    pub taker_order_id: u64,
+    pub maker_order_id: u64,
+    pub taker_side: OrderSide,
+    pub price: f64,
+    pub qty: f64,
+    /// Fees charged in the fee asset, negative for rebates
+    pub taker_fee: f64,
+    pub maker_fee: f64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
+    pub ts: SystemTime,
+}
+
+
+/// Aggregated volume of orders at one price
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PriceLevel {
+    pub price: f64,
+    pub qty: f64,
+    pub orders: usize,
+}
+
+
+/// Aggregated view of the orderbook, best levels first
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Depth {
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+
+/// Change of the aggregated price level, zero quantity means level removal
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BookDelta {
+    pub side: OrderSide,
+    pub price: f64,
+    pub qty: f64,
+}
+
+
+/// Extract trades from orderbook processing results.
+///
+/// Every deal is reported by a pair of fill events: taker first, maker second.
+pub fn trades(results: &OrderProcessingResult) -> Vec<Trade> {
+    let fills: Vec<(u64, OrderSide, f64, f64, f64, SystemTime)> = results
+        .iter()
+        .filter_map(|event| match *event {
+            Ok(Success::Filled { order_id, side, price, qty, fee, ts, .. }) |
+            Ok(Success::PartiallyFilled { order_id, side, price, qty, fee, ts, .. }) => {
+                Some((order_id, side, price, qty, fee, ts))
+            }
+            _ => None,
+        })
+        .collect();
+
+    fills
+        .chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| {
+            let (taker_order_id, taker_side, price, qty, taker_fee, ts) = pair[0];
+            Trade {
+                taker_order_id,
+                maker_order_id: pair[1].0,
+                taker_side,
+                price,
+                qty,
+                taker_fee,
+                maker_fee: pair[1].4,
+                ts,
+            }
+        })
+        .collect()
+}
+
+
+/// Aggregate orders (price, qty) into price levels, best price first
+pub fn aggregate_levels<I>(side: OrderSide, orders: I) -> Vec<PriceLevel>
+where
+    I: IntoIterator<Item = (f64, f64)>,
+{
+    let mut orders: Vec<(f64, f64)> = orders.into_iter().collect();
+    match side {
+        OrderSide::Bid => orders.sort_by(|a, b| b.0.total_cmp(&a.0)),
+        OrderSide::Ask => orders.sort_by(|a, b| a.0.total_cmp(&b.0)),
+    }
+
+    let mut levels: Vec<PriceLevel> = Vec::new();
+    for (price, qty) in orders {
+        match levels.last_mut() {
+            Some(ref mut level) if level.price == price => {
+                level.qty += qty;
+                level.orders += 1;
+                continue;
+            }
+            _ => (),
+        }
+        levels.push(PriceLevel { price, qty, orders: 1 });
+    }
+    levels
+}
+
+
+/// Changes turning one depth view into another, best levels first
+pub fn depth_deltas(before: &Depth, after: &Depth) -> Vec<BookDelta> {
+    let mut deltas = Vec::new();
+    for &(side, old, new) in &[
+        (OrderSide::Bid, &before.bids, &after.bids),
+        (OrderSide::Ask, &before.asks, &after.asks),
+    ]
+    {
+        // both views are sorted best first, walk them together
+        let (mut old_idx, mut new_idx) = (0, 0);
+        loop {
+            let position = match (old.get(old_idx), new.get(new_idx)) {
+                (Some(old_level), Some(new_level)) => match side {
+                    OrderSide::Bid => new_level.price.total_cmp(&old_level.price),
+                    OrderSide::Ask => old_level.price.total_cmp(&new_level.price),
+                },
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => break,
+            };
+            match position {
+                Ordering::Less => {
+                    deltas.push(BookDelta { side, price: old[old_idx].price, qty: 0.0 });
+                    old_idx += 1;
+                }
+                Ordering::Greater => {
+                    deltas.push(BookDelta { side, price: new[new_idx].price, qty: new[new_idx].qty });
+                    new_idx += 1;
+                }
+                Ordering::Equal => {
+                    if old[old_idx].qty != new[new_idx].qty {
+                        deltas.push(BookDelta { side, price: new[new_idx].price, qty: new[new_idx].qty });
+                    }
+                    old_idx += 1;
+                    new_idx += 1;
+                }
+            }
+        }
+    }
+    deltas
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::domain::OrderType;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn trades_from_fills() {
+        let results: OrderProcessingResult = vec![
+            Ok(Success::Accepted { id: 3, order_type: OrderType::Market, ts: UNIX_EPOCH }),
+            Ok(Success::PartiallyFilled {
+                order_id: 3,
+                side: OrderSide::Ask,
+                order_type: OrderType::Market,
+                price: 12.0,
+                qty: 1.0,
+                fee: 0.0,
+                ts: UNIX_EPOCH,
+            }),
+            Ok(Success::Filled {
+                order_id: 2,
+                side: OrderSide::Bid,
+                order_type: OrderType::Limit,
+                price: 12.0,
+                qty: 1.0,
+                fee: 0.0,
+                ts: UNIX_EPOCH,
+            }),
+            Ok(Success::Filled {
+                order_id: 3,
+                side: OrderSide::Ask,
+                order_type: OrderType::Market,
+                price: 10.0,
+                qty: 0.5,
+                fee: 0.0,
+                ts: UNIX_EPOCH,
+            }),
+            Ok(Success::PartiallyFilled {
+                order_id: 1,
+                side: OrderSide::Bid,
+                order_type: OrderType::Limit,
+                price: 10.0,
+                qty: 0.5,
+                fee: 0.0,
+                ts: UNIX_EPOCH,
+            }),
+        ];
+
+        let trades = trades(&results);
+        assert_eq!(trades.len(), 2);
+        assert_eq!((trades[0].taker_order_id, trades[0].maker_order_id), (3, 2));
+        assert_eq!((trades[1].price, trades[1].qty), (10.0, 0.5));
+    }
+
+    #[test]
+    fn levels_and_deltas() {
+        let bids = aggregate_levels(OrderSide::Bid, vec![(1.0, 1.0), (1.1, 2.0), (1.0, 0.5)]);
+        assert_eq!(bids, vec![
+            PriceLevel { price: 1.1, qty: 2.0, orders: 1 },
+            PriceLevel { price: 1.0, qty: 1.5, orders: 2 },
+        ]);
+
+        let before = Depth { bids, asks: aggregate_levels(OrderSide::Ask, vec![(1.2, 1.0)]) };
+        let after = Depth {
+            bids: aggregate_levels(OrderSide::Bid, vec![(1.0, 1.0), (1.1, 2.0)]),
+            asks: vec![],
+        };
+        assert_eq!(depth_deltas(&before, &after), vec![
+            BookDelta { side: OrderSide::Bid, price: 1.0, qty: 1.0 },
+            BookDelta { side: OrderSide::Ask, price: 1.2, qty: 0.0 },
+        ]);
+    }
+}
@@ -1,7 +1,20 @@
+pub mod accounts;
+pub mod clock;
 
 pub mod domain;
+pub mod exchange;
+pub mod fees;
+pub mod journal;
+pub mod market_data;
 pub mod orderbook;
 pub mod order_queues;
 pub mod orders;
+pub mod positions;
+pub mod risk;
 pub mod sequence;
+pub mod snapshot;
+#[cfg(feature = "serde")]
+pub mod serde_ts;
+pub mod throttle;
+pub mod trade_history;
 pub mod validation;
@@ -1,7 +1,6 @@
 
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap};
-use std::time;
 
 use super::domain::OrderSide;
 
@@ -10,11 +9,12 @@ use super::domain::OrderSide;
 struct OrderIndex {
     id: u64,
     price: f64,
-    timestamp: time::SystemTime,
+    // arrival sequence number, strictly increasing within queue
+    seq: u64,
     order_side: OrderSide,
 }
 
-// Arrange at first by price and after that by time
+// Arrange at first by price and after that by arrival sequence
 impl Ord for OrderIndex {
     fn cmp(&self, other: &Self) -> Ordering {
         if self.price < other.price {
@@ -29,7 +29,7 @@ impl Ord for OrderIndex {
             }
         } else {
             // FIFO
-            other.timestamp.cmp(&self.timestamp)
+            other.seq.cmp(&self.seq)
         }
     }
 }
@@ -42,10 +42,10 @@ impl PartialOrd for OrderIndex {
 
 impl PartialEq for OrderIndex {
     fn eq(&self, other: &Self) -> bool {
-        if self.price > other.price || self.price < other.price {
+        if self.price != other.price {
             false
         } else {
-            self.timestamp == other.timestamp
+            self.seq == other.seq
         }
     }
 }
@@ -57,10 +57,13 @@ impl Eq for OrderIndex {}
 pub struct OrderQueue<T> {
     // use Option in order to replace heap in mutable borrow
     idx_queue: Option<BinaryHeap<OrderIndex>>,
-    orders: HashMap<u64, T>,
+    // orders with arrival sequence of their index, dangling indices of
+    // cancelled orders don't match it even if the order ID is reused
+    orders: HashMap<u64, (u64, T)>,
     op_counter: u64,
     max_stalled: u64,
     queue_side: OrderSide,
+    next_seq: u64,
 }
 
 
@@ -75,65 +78,79 @@ impl<T> OrderQueue<T> {
             op_counter: 0,
             max_stalled,
             queue_side: side,
+            next_seq: 0,
         }
     }
 
 
     pub fn peek(&mut self) -> Option<&T> {
-        // get best order ID
         let order_id = self.get_current_order_id()?;
-
-        // obtain order info
-        if self.orders.contains_key(&order_id) {
-            self.orders.get(&order_id)
-        } else {
-            self.idx_queue.as_mut().unwrap().pop()?;
-            self.peek()
-        }
+        self.orders.get(&order_id).map(|(_, order)| order)
     }
 
 
     pub fn pop(&mut self) -> Option<T> {
-        // remove order index from queue in any case
-        let order_id = self.idx_queue.as_mut()?.pop()?.id;
-
-        if self.orders.contains_key(&order_id) {
-            self.orders.remove(&order_id)
-        } else {
-            self.pop()
+        loop {
+            // remove order index from queue in any case
+            let index = self.idx_queue.as_mut()?.pop()?;
+            if is_active(&self.orders, &index) {
+                return self.orders.remove(&index.id).map(|(_, order)| order);
+            }
         }
     }
 
 
     // Add new limit order to the queue
-    pub fn insert(&mut self, id: u64, price: f64, ts: time::SystemTime, order: T) -> bool {
+    pub fn insert(&mut self, id: u64, price: f64, order: T) -> bool {
         if self.orders.contains_key(&id) {
             // do not update existing order
             return false;
         }
 
         // store new order
+        let seq = self.next_arrival_seq();
         self.idx_queue.as_mut().unwrap().push(OrderIndex {
             id,
             price,
-            timestamp: ts,
+            seq,
             order_side: self.queue_side,
         });
-        self.orders.insert(id, order);
+        self.orders.insert(id, (seq, order));
         true
     }
 
 
-    // use it when price was changed
-    pub fn amend(&mut self, id: u64, price: f64, ts: time::SystemTime, order: T) -> bool {
-        if self.orders.contains_key(&id) {
+    // use it when price was changed, order loses its time priority
+    pub fn amend(&mut self, id: u64, price: f64, order: T) -> bool {
+        let seq = self.next_arrival_seq();
+        if let Some(stored_order) = self.orders.get_mut(&id) {
             // store new order data
-            self.orders.insert(id, order);
-            self.rebuild_idx(id, price, ts);
-            true
+            *stored_order = (seq, order);
         } else {
-            false
+            return false;
         }
+        self.rebuild_idx(id, price, seq);
+        true
+    }
+
+
+    /// Replace order data keeping its price and time priority.
+    ///
+    /// Note: do not modify price, cause index doesn't change!
+    pub fn modify(&mut self, id: u64, order: T) -> bool {
+        match self.orders.get_mut(&id) {
+            Some((_, stored_order)) => {
+                *stored_order = order;
+                true
+            }
+            None => false,
+        }
+    }
+
+
+    /// Active order by ID
+    pub fn get(&self, id: u64) -> Option<&T> {
+        self.orders.get(&id).map(|(_, order)| order)
     }
 
 
@@ -148,6 +165,61 @@ impl<T> OrderQueue<T> {
     }
 
 
+    /// Active orders in no particular order
+    pub fn orders(&self) -> impl Iterator<Item = &T> {
+        self.orders.values().map(|(_, order)| order)
+    }
+
+
+    /// Active orders with index data: (id, price, arrival sequence, order).
+    ///
+    /// Ordered by arrival.
+    pub fn indexed_orders(&self) -> Vec<(u64, f64, u64, &T)> {
+        let mut indexed: Vec<(u64, f64, u64, &T)> = self.idx_queue
+            .as_ref()
+            .map(|idx_queue| {
+                idx_queue
+                    .iter()
+                    .filter(|idx| is_active(&self.orders, idx))
+                    .map(|idx| (idx.id, idx.price, idx.seq, &self.orders[&idx.id].1))
+                    .collect()
+            })
+            .unwrap_or_default();
+        indexed.sort_by_key(|&(_, _, seq, _)| seq);
+        indexed
+    }
+
+
+    /// Sequence number to be assigned to the next arriving order
+    pub fn arrival_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+
+    /// Put order back with its original arrival sequence, e.g. from snapshot
+    pub fn restore(&mut self, id: u64, price: f64, seq: u64, order: T) -> bool {
+        if self.orders.contains_key(&id) {
+            return false;
+        }
+
+        self.idx_queue.as_mut().unwrap().push(OrderIndex {
+            id,
+            price,
+            seq,
+            order_side: self.queue_side,
+        });
+        self.orders.insert(id, (seq, order));
+        self.next_seq = self.next_seq.max(seq + 1);
+        true
+    }
+
+
+    /// Continue arrival sequence from given number
+    pub fn set_arrival_seq(&mut self, seq: u64) {
+        self.next_seq = self.next_seq.max(seq);
+    }
+
+
     /* Internal methods */
 
 
@@ -156,8 +228,8 @@ impl<T> OrderQueue<T> {
     /// Note: do not modify price or time, cause index doesn't change!
     pub fn modify_current_order(&mut self, new_order: T) -> bool {
         if let Some(order_id) = self.get_current_order_id() {
-            if self.orders.contains_key(&order_id) {
-                self.orders.insert(order_id, new_order);
+            if let Some((_, current_order)) = self.orders.get_mut(&order_id) {
+                *current_order = new_order;
                 return true;
             }
         }
@@ -180,14 +252,14 @@ impl<T> OrderQueue<T> {
     fn remove_stalled(&mut self) {
         if let Some(idx_queue) = self.idx_queue.take() {
             let mut active_orders = idx_queue.into_vec();
-            active_orders.retain(|order_ptr| self.orders.contains_key(&order_ptr.id));
+            active_orders.retain(|order_ptr| is_active(&self.orders, order_ptr));
             self.idx_queue = Some(BinaryHeap::from(active_orders));
         }
     }
 
 
     /// Recreate order-index queue with changed index info
-    fn rebuild_idx(&mut self, id: u64, price: f64, ts: time::SystemTime) {
+    fn rebuild_idx(&mut self, id: u64, price: f64, seq: u64) {
         if let Some(idx_queue) = self.idx_queue.take() {
             // deconstruct queue
             let mut active_orders = idx_queue.into_vec();
@@ -197,24 +269,44 @@ impl<T> OrderQueue<T> {
             active_orders.push(OrderIndex {
                 id,
                 price,
-                timestamp: ts,
+                seq,
                 order_side: self.queue_side,
             });
             // construct new queue
-            let mut amended_queue = BinaryHeap::from(active_orders);
+            let amended_queue = BinaryHeap::from(active_orders);
             self.idx_queue = Some(amended_queue);
         }
     }
 
 
-    /// Return ID of current order in queue
-    fn get_current_order_id(&self) -> Option<u64> {
-        let order_id = self.idx_queue.as_ref()?.peek()?;
-        Some(order_id.id)
+    /// Assign sequence number to the order arriving in queue
+    fn next_arrival_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+
+    /// Return ID of current order in queue, dropping dangling indices on top
+    fn get_current_order_id(&mut self) -> Option<u64> {
+        let idx_queue = self.idx_queue.as_mut()?;
+        loop {
+            let index = idx_queue.peek()?;
+            if is_active(&self.orders, index) {
+                return Some(index.id);
+            }
+            idx_queue.pop();
+        }
     }
 }
 
 
+/// Index points to the order still in queue, not to a cancelled one with the same ID
+fn is_active<T>(orders: &HashMap<u64, (u64, T)>, index: &OrderIndex) -> bool {
+    orders.get(&index.id).is_some_and(|&(seq, _)| seq == index.seq)
+}
+
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -236,20 +328,17 @@ mod test {
         assert!(bid_queue.insert(
             1,
             1.01,
-            time::SystemTime::now(),
             TestOrder { name: "low bid" },
         ));
         assert!(bid_queue.insert(
             2,
             1.02,
-            time::SystemTime::now(),
             TestOrder { name: "high bid first" },
         ));
         // same price but later
         assert!(bid_queue.insert(
             3,
             1.02,
-            time::SystemTime::now(),
             TestOrder { name: "high bid second" },
         ));
         assert_eq!(bid_queue.peek().unwrap().name, "high bid first");
@@ -263,19 +352,16 @@ mod test {
         assert!(ask_queue.insert(
             1,
             1.01,
-            time::SystemTime::now(),
             TestOrder { name: "low ask first" },
         ));
         assert!(ask_queue.insert(
             2,
             1.02,
-            time::SystemTime::now(),
             TestOrder { name: "high ask" },
         ));
         assert!(ask_queue.insert(
             3,
             1.01,
-            time::SystemTime::now(),
             TestOrder { name: "low ask second" },
         ));
         assert_eq!(ask_queue.peek().unwrap().name, "low ask first");
@@ -293,7 +379,6 @@ mod test {
         assert!(bid_queue.insert(
             1,
             1.01,
-            time::SystemTime::now(),
             TestOrder { name: "first bid" },
         ));
 
@@ -301,7 +386,6 @@ mod test {
         assert!(!bid_queue.insert(
             1,
             1.02,
-            time::SystemTime::now(),
             TestOrder { name: "another first bid" },
         ));
     }
@@ -350,20 +434,17 @@ mod test {
         assert!(ask_queue.amend(
             2,
             0.99,
-            time::SystemTime::now(),
             TestOrder { name: "new first" },
         ));
         assert!(ask_queue.amend(
             1,
             1.01,
-            time::SystemTime::now(),
             TestOrder { name: "new last" },
         ));
         // non-exist order
         assert!(!ask_queue.amend(
             4,
             3.03,
-            time::SystemTime::now(),
             TestOrder { name: "nonexistent" },
         ));
 
@@ -384,6 +465,29 @@ mod test {
     }
 
 
+    #[test]
+    fn queue_operations_reuse_cancelled_id() {
+        let mut bid_queue = get_queue_bids();
+
+        // new order with ID of cancelled one is not matched at the old price
+        bid_queue.cancel(2);
+        assert!(bid_queue.insert(
+            2,
+            1.00,
+            TestOrder { name: "lowest bid" },
+        ));
+        assert_eq!(bid_queue.indexed_orders().len(), 3);
+        assert_eq!(bid_queue.peek().unwrap().name, "high bid second");
+
+        assert!(bid_queue.modify_current_order(TestOrder { name: "high bid modified" }));
+        assert_eq!(bid_queue.get(2).unwrap().name, "lowest bid");
+        assert_eq!(bid_queue.pop().unwrap().name, "high bid modified");
+        assert_eq!(bid_queue.pop().unwrap().name, "low bid");
+        assert_eq!(bid_queue.pop().unwrap().name, "lowest bid");
+        assert!(bid_queue.pop().is_none());
+    }
+
+
     #[test]
     fn queue_operations_cancel_order2() {
         let mut ask_queue = get_queue_asks();
@@ -393,4 +497,40 @@ mod test {
         assert_eq!(ask_queue.pop().unwrap().name, "low ask first");
         assert_eq!(ask_queue.pop().unwrap().name, "high ask");
     }
+
+
+    #[test]
+    fn queue_operations_same_price_fifo() {
+        let mut ask_queue: OrderQueue<u64> = OrderQueue::new(OrderSide::Ask, 5, 10);
+
+        for id in 1..101 {
+            assert!(ask_queue.insert(id, 1.01, id));
+        }
+
+        for id in 1..101 {
+            assert_eq!(ask_queue.pop(), Some(id));
+        }
+        assert_eq!(ask_queue.pop(), None);
+    }
+
+
+    #[test]
+    fn queue_operations_restore() {
+        let mut ask_queue = get_queue_asks();
+        ask_queue.cancel(1);
+
+        let mut restored = get_queue_empty(OrderSide::Ask);
+        for (id, price, seq, order) in ask_queue.indexed_orders() {
+            assert!(restored.restore(id, price, seq, TestOrder { name: order.name }));
+        }
+        restored.set_arrival_seq(ask_queue.arrival_seq());
+        assert_eq!(restored.arrival_seq(), ask_queue.arrival_seq());
+
+        // later order keeps lower priority at the same price
+        assert!(restored.insert(4, 1.01, TestOrder { name: "low ask third" }));
+
+        assert_eq!(restored.pop().unwrap().name, "low ask second");
+        assert_eq!(restored.pop().unwrap().name, "low ask third");
+        assert_eq!(restored.pop().unwrap().name, "high ask");
+    }
 }
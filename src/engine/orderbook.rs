@@ -1,12 +1,27 @@
 
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::time::SystemTime;
 use std::fmt::Debug;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
+
+use super::accounts::Accounts;
+use super::clock::{Clock, SystemClock};
 use super::domain::{Order, OrderSide, OrderType};
+use super::fees::{FeeAsset, FeeSchedule};
+use super::journal::{Journal, JournalCommand, JournalEntry};
+use super::market_data::{self, BookDelta, Depth, Trade};
 use super::orders::OrderRequest;
+use super::positions::{Position, Positions};
+use super::risk::RiskChecks;
+use super::throttle::{RequestKind, Throttle};
+use super::trade_history::TradeHistory;
 use super::order_queues::OrderQueue;
 use super::sequence;
+use super::snapshot::{AssetCode, SnapshotError, SnapshotReader, SnapshotWriter};
 use super::validation::OrderRequestValidator;
 
 
@@ -14,16 +29,20 @@ const MIN_SEQUENCE_ID: u64 = 1;
 const MAX_SEQUENCE_ID: u64 = 1000;
 const MAX_STALLED_INDICES_IN_QUEUE: u64 = 10;
 const ORDER_QUEUE_INIT_CAPACITY: usize = 500;
+// carried by failures of new orders rejected before getting an ID
+const UNASSIGNED_ORDER_ID: u64 = 0;
 
 
 pub type OrderProcessingResult = Vec<Result<Success, Failed>>;
 
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Success {
     Accepted {
         id: u64,
         order_type: OrderType,
+        #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
         ts: SystemTime,
     },
 
@@ -33,6 +52,9 @@ pub enum Success {
         order_type: OrderType,
         price: f64,
         qty: f64,
+        /// Paid by order owner, negative for rebate
+        fee: f64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
         ts: SystemTime,
     },
 
@@ -42,6 +64,8 @@ pub enum Success {
         order_type: OrderType,
         price: f64,
         qty: f64,
+        fee: f64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
         ts: SystemTime,
     },
 
@@ -49,19 +73,41 @@ pub enum Success {
         id: u64,
         price: f64,
         qty: f64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
         ts: SystemTime,
     },
 
-    Cancelled { id: u64, ts: SystemTime },
+    Cancelled {
+        id: u64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
+        ts: SystemTime,
+    },
 }
 
 
+/// Request failures.
+///
+/// Risk and funds failures of new orders are reported before the order gets
+/// an ID and carry 0, for amends they carry ID of the amended order.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Failed {
     ValidationFailed(String),
     DuplicateOrderID(u64),
     NoMatch(u64),
     OrderNotFound(u64),
+    InsufficientFunds(u64),
+    MaxQtyExceeded(u64),
+    MaxNotionalExceeded(u64),
+    MaxOpenOrdersExceeded(u64),
+    PositionLimitExceeded(u64),
+    PriceDeviationExceeded(u64),
+    /// Owner exceeded request rate limit
+    Throttled(u64),
+    /// Requests of the owner are blocked by kill switch
+    OwnerKilled(u64),
+    /// Global kill switch is on, only cancels are accepted
+    TradingHalted,
 }
 
 
@@ -75,6 +121,46 @@ where
     ask_queue: OrderQueue<Order<Asset>>,
     seq: sequence::TradeSequence,
     order_validator: OrderRequestValidator<Asset>,
+    clock: Box<dyn Clock>,
+    journal_seq: u64,
+    journal: Option<Journal<Asset>>,
+    accounts: Option<Accounts<Asset>>,
+    fees: Option<FeeSchedule>,
+    risk: Option<RiskChecks>,
+    throttle: Option<Throttle>,
+    positions: Option<Positions>,
+    trade_history: Option<TradeHistory>,
+    killed_owners: HashSet<u64>,
+    halted: bool,
+}
+
+
+/// Incoming order being matched against the book
+struct Taker<Asset> {
+    order_id: u64,
+    owner: u64,
+    order_asset: Asset,
+    price_asset: Asset,
+    side: OrderSide,
+    order_type: OrderType,
+    price: Option<f64>,
+    // funds locked for the order and not yet spent
+    reserved: f64,
+    // taker fee locked with the order funds and not yet charged
+    fee_reserved: f64,
+}
+
+
+impl<Asset> Taker<Asset>
+where
+    Asset: Copy,
+{
+    fn locked_asset(&self) -> Asset {
+        match self.side {
+            OrderSide::Bid => self.price_asset,
+            OrderSide::Ask => self.order_asset,
+        }
+    }
 }
 
 
@@ -88,12 +174,39 @@ where
     ///
     /// Basic usage:
     /// ```
+    /// use std::time::SystemTime;
+    /// use orderbook::{Orderbook, OrderSide, orders};
+    ///
+    /// #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    /// enum Asset {
+    ///     USD,
+    ///     BTC,
+    /// }
+    ///
     /// let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
-    /// let result = orderbook.process_order(OrderRequest::MarketOrder{  });
-    /// assert_eq!(orderbook)
+    /// let request = orders::new_limit_order_request(
+    ///     1,
+    ///     Asset::BTC,
+    ///     Asset::USD,
+    ///     OrderSide::Bid,
+    ///     10.0,
+    ///     1.0,
+    ///     SystemTime::now(),
+    /// );
+    ///
+    /// let result = orderbook.process_order(request);
+    /// assert_eq!(result.len(), 1);
     /// ```
-    // todo fix doc test!
     pub fn new(order_asset: Asset, price_asset: Asset) -> Self {
+        Self::with_clock(order_asset, price_asset, SystemClock)
+    }
+
+
+    /// Create new orderbook using provided clock for event timestamps
+    pub fn with_clock<C>(order_asset: Asset, price_asset: Asset, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
         Orderbook {
             order_asset,
             price_asset,
@@ -114,85 +227,478 @@ where
                 MIN_SEQUENCE_ID,
                 MAX_SEQUENCE_ID,
             ),
+            clock: Box::new(clock),
+            journal_seq: 0,
+            journal: None,
+            accounts: None,
+            fees: None,
+            risk: None,
+            throttle: None,
+            positions: None,
+            trade_history: None,
+            killed_owners: HashSet::new(),
+            halted: false,
         }
     }
 
 
-    pub fn process_order(&mut self, order: OrderRequest<Asset>) -> OrderProcessingResult {
-        // processing result accumulator
-        let mut proc_result: OrderProcessingResult = vec![];
+    /// Rebuild orderbook from the journal.
+    ///
+    /// Returns restored orderbook and results of processing
+    /// for every journal entry, identical to the original ones.
+    /// Journal holds admitted requests only, so the book is restored even if
+    /// the original one rejected requests by risk or funds checks. Balances
+    /// and fees are not restored, use `apply_journal` on a configured book.
+    pub fn replay(journal: &Journal<Asset>) -> (Self, Vec<OrderProcessingResult>) {
+        let mut orderbook = Orderbook::new(*journal.order_asset(), *journal.price_asset());
+        let results = orderbook.apply_journal(journal);
+        (orderbook, results)
+    }
+
+
+    /// Apply journal entries, not yet processed by this orderbook
+    pub fn apply_journal(&mut self, journal: &Journal<Asset>) -> Vec<OrderProcessingResult> {
+        journal
+            .entries_since(self.journal_seq)
+            .iter()
+            .map(|entry| self.apply_command(entry.seq, entry.ts, entry.command.clone()))
+            .collect()
+    }
+
+
+    pub fn order_asset(&self) -> Asset {
+        self.order_asset
+    }
+
+
+    pub fn price_asset(&self) -> Asset {
+        self.price_asset
+    }
+
+
+    /// Change the last order ID, after which IDs wrap around to the first one.
+    ///
+    /// Resting IDs are unique per side only, and a new order getting the ID of
+    /// an order still resting on its side is rejected with `DuplicateOrderID`
+    /// after matching. The range should exceed the number of new orders placed
+    /// while the oldest order rests. Journals of such book are replayed with
+    /// `apply_journal` on a book with the same range.
+    pub fn set_max_order_id(&mut self, max_id: u64) {
+        let (min_id, _, current_id) = self.seq.state();
+        let max_id = max_id.max(min_id);
+        let current_id = if current_id > max_id { min_id } else { current_id };
+        self.seq = sequence::restore_sequence_gen(min_id, max_id, current_id);
+        self.order_validator =
+            OrderRequestValidator::new(self.order_asset, self.price_asset, min_id, max_id);
+    }
+
+
+    /// Start recording admitted requests into journal
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Journal::new(self.order_asset, self.price_asset));
+        }
+    }
+
+
+    pub fn journal(&self) -> Option<&Journal<Asset>> {
+        self.journal.as_ref()
+    }
+
+
+    /// Check and settle owners' balances, starting with provided ledger.
+    ///
+    /// New orders lock required funds on acceptance and are rejected
+    /// with `InsufficientFunds` if owner could not cover them.
+    pub fn enable_accounts(&mut self, accounts: Accounts<Asset>) {
+        self.accounts = Some(accounts);
+    }
+
+
+    pub fn accounts(&self) -> Option<&Accounts<Asset>> {
+        self.accounts.as_ref()
+    }
+
+
+    pub fn accounts_mut(&mut self) -> Option<&mut Accounts<Asset>> {
+        self.accounts.as_mut()
+    }
+
+
+    /// Charge maker and taker fees on every fill.
+    ///
+    /// With accounts enabled, taker fee paid in the asset the order spends is
+    /// locked on acceptance. Other fees are taken from available balance,
+    /// fills report the amount actually charged.
+    pub fn enable_fees(&mut self, fees: FeeSchedule) {
+        self.fees = Some(fees);
+    }
+
+
+    pub fn fees(&self) -> Option<&FeeSchedule> {
+        self.fees.as_ref()
+    }
+
+
+    /// Fee schedule could be changed between requests
+    pub fn fees_mut(&mut self) -> Option<&mut FeeSchedule> {
+        self.fees.as_mut()
+    }
+
+
+    /// Evaluate risk limits for new and amended orders before matching.
+    ///
+    /// Enables positions, limits are checked against them.
+    pub fn enable_risk(&mut self, mut risk: RiskChecks) {
+        // account orders already resting in the book
+        for queue in &[&self.bid_queue, &self.ask_queue] {
+            for (_, _, _, order) in queue.indexed_orders() {
+                risk.order_opened(order.owner);
+            }
+        }
+        self.risk = Some(risk);
+        self.enable_positions();
+    }
+
+
+    pub fn risk(&self) -> Option<&RiskChecks> {
+        self.risk.as_ref()
+    }
+
+
+    /// Limits could be changed between requests
+    pub fn risk_mut(&mut self) -> Option<&mut RiskChecks> {
+        self.risk.as_mut()
+    }
+
+
+    /// Limit request rate of every owner, excess requests are rejected
+    /// before reaching the journal
+    pub fn enable_throttle(&mut self, throttle: Throttle) {
+        self.throttle = Some(throttle);
+    }
+
+
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_ref()
+    }
+
+
+    pub fn throttle_mut(&mut self) -> Option<&mut Throttle> {
+        self.throttle.as_mut()
+    }
+
+
+    /// Track positions and PnL of owners from fills
+    pub fn enable_positions(&mut self) {
+        if self.positions.is_none() {
+            self.positions = Some(Positions::new());
+        }
+    }
+
+
+    pub fn positions(&self) -> Option<&Positions> {
+        self.positions.as_ref()
+    }
+
+
+    pub fn position(&self, owner: u64) -> Option<Position> {
+        self.positions.as_ref().map(|positions| positions.position(owner))
+    }
+
+
+    /// Price open positions are valued at: last trade, or mid price if nothing traded yet
+    pub fn mark_price(&mut self) -> Option<f64> {
+        if let Some(price) = self.positions.as_ref().and_then(|p| p.last_price()) {
+            return Some(price);
+        }
+        self.current_spread().map(|(bid, ask)| (bid + ask) / 2.0)
+    }
+
+
+    /// Profit of the owner's open position at the mark price
+    pub fn unrealized_pnl(&mut self, owner: u64) -> Option<f64> {
+        let mark_price = self.mark_price()?;
+        self.position(owner).map(|position| position.unrealized_pnl(mark_price))
+    }
+
+
+    /// Record executed trades and aggregate them into candles
+    pub fn enable_trade_history(&mut self, history: TradeHistory) {
+        self.trade_history = Some(history);
+    }
+
+
+    pub fn trade_history(&self) -> Option<&TradeHistory> {
+        self.trade_history.as_ref()
+    }
+
+
+    /// Mutable access is needed to compute rolling statistics
+    pub fn trade_history_mut(&mut self) -> Option<&mut TradeHistory> {
+        self.trade_history.as_mut()
+    }
+
+
+    /// Block new orders of the owner and cancel all their resting orders.
+    ///
+    /// Kill switch changes are journaled, replay repeats the cancels.
+    pub fn kill(&mut self, owner: u64) -> OrderProcessingResult {
+        self.switch_kill(JournalCommand::Kill(owner))
+    }
+
+
+    /// Accept orders of the killed owner again
+    pub fn revive(&mut self, owner: u64) {
+        self.switch_kill(JournalCommand::Revive(owner));
+    }
+
+
+    pub fn is_killed(&self, owner: u64) -> bool {
+        self.killed_owners.contains(&owner)
+    }
+
+
+    /// Global kill switch: reject everything except cancels
+    pub fn kill_all(&mut self) {
+        self.switch_kill(JournalCommand::KillAll);
+    }
+
 
+    pub fn revive_all(&mut self) {
+        self.switch_kill(JournalCommand::ReviveAll);
+    }
+
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+
+    /// Sequence number of the last accepted request
+    pub fn journal_seq(&self) -> u64 {
+        self.journal_seq
+    }
+
+
+    pub fn process_order(&mut self, order: OrderRequest<Asset>) -> OrderProcessingResult {
         // validate request
         if let Err(reason) = self.order_validator.validate(&order) {
-            proc_result.push(Err(Failed::ValidationFailed(String::from(reason))));
-            return proc_result;
+            return vec![Err(Failed::ValidationFailed(String::from(reason)))];
+        }
+
+        // kill switch lets cancels through only
+        let (owner, kind) = request_kind(&order);
+        if kind != RequestKind::Cancel {
+            if self.halted {
+                return vec![Err(Failed::TradingHalted)];
+            }
+            if self.killed_owners.contains(&owner) {
+                return vec![Err(Failed::OwnerKilled(owner))];
+            }
+        }
+
+        // single timestamp for all events of the request
+        let ts = self.clock.now(request_timestamp(&order));
+
+        if let Some(ref mut throttle) = self.throttle {
+            if !throttle.allow(owner, kind, ts) {
+                return vec![Err(Failed::Throttled(owner))];
+            }
+        }
+        let seq = self.journal_seq + 1;
+        self.execute_request(seq, ts, order)
+    }
+
+
+    /// Get current spread as a tuple: (bid, ask)
+    pub fn current_spread(&mut self) -> Option<(f64, f64)> {
+        let bid = self.bid_queue.peek()?.price;
+        let ask = self.ask_queue.peek()?.price;
+        Some((bid, ask))
+    }
+
+
+    /// Aggregated price levels, at most `max_levels` from each side
+    pub fn depth(&self, max_levels: usize) -> Depth {
+        let level_orders = |queue: &OrderQueue<Order<Asset>>| {
+            queue
+                .indexed_orders()
+                .into_iter()
+                .map(|(_, price, _, order)| (price, order.qty))
+                .collect::<Vec<(f64, f64)>>()
+        };
+
+        let mut bids = market_data::aggregate_levels(OrderSide::Bid, level_orders(&self.bid_queue));
+        let mut asks = market_data::aggregate_levels(OrderSide::Ask, level_orders(&self.ask_queue));
+        bids.truncate(max_levels);
+        asks.truncate(max_levels);
+        Depth { bids, asks }
+    }
+
+
+    /// Process request and report changes of the price levels it touched.
+    ///
+    /// Only the levels of the request itself and of the makers it traded with
+    /// are aggregated, instead of comparing full depth before and after.
+    pub fn process_order_with_deltas(
+        &mut self,
+        order: OrderRequest<Asset>,
+    ) -> (OrderProcessingResult, Vec<BookDelta>) {
+        let request_levels = self.request_levels(&order);
+        let qty_before = self.level_quantities(&request_levels);
+        let results = self.process_order(order);
+
+        let mut levels = request_levels.clone();
+        for trade in market_data::trades(&results) {
+            levels.push((trade.taker_side.opposite(), trade.price));
         }
+        // bids first, best levels first
+        levels.sort_by(|a, b| match (a.0, b.0) {
+            (OrderSide::Bid, OrderSide::Bid) => b.1.total_cmp(&a.1),
+            (OrderSide::Ask, OrderSide::Ask) => a.1.total_cmp(&b.1),
+            (OrderSide::Bid, OrderSide::Ask) => Ordering::Less,
+            (OrderSide::Ask, OrderSide::Bid) => Ordering::Greater,
+        });
+        levels.dedup();
+
+        let qty_after = self.level_quantities(&levels);
+        let deltas = levels
+            .into_iter()
+            .zip(qty_after)
+            .filter(|&(level, qty)| {
+                // levels of makers are always changed by fills
+                request_levels
+                    .iter()
+                    .position(|&request_level| request_level == level)
+                    .is_none_or(|idx| qty_before[idx] != qty)
+            })
+            .map(|((side, price), qty)| BookDelta { side, price, qty })
+            .collect();
+        (results, deltas)
+    }
+
+
+    /* Processing logic */
+
+    /// Process valid request, recording it into journal once admitted.
+    ///
+    /// New orders get their ID only after risk and funds checks pass,
+    /// rejected requests do not reach the journal.
+    fn execute_request(
+        &mut self,
+        seq: u64,
+        ts: SystemTime,
+        order: OrderRequest<Asset>,
+    ) -> OrderProcessingResult {
+        // processing result accumulator
+        let mut proc_result: OrderProcessingResult = vec![];
 
         match order {
             OrderRequest::NewMarketOrder {
+                owner,
                 order_asset,
                 price_asset,
                 side,
                 qty,
                 ts: _ts,
             } => {
+                let mut taker = Taker {
+                    order_id: UNASSIGNED_ORDER_ID,
+                    owner,
+                    order_asset,
+                    price_asset,
+                    side,
+                    order_type: OrderType::Market,
+                    price: None,
+                    reserved: 0.0,
+                    fee_reserved: 0.0,
+                };
+                if !(self.check_risk(&mut proc_result, &taker, qty) &&
+                    self.reserve_taker_funds(&mut proc_result, &mut taker, qty, ts))
+                {
+                    return proc_result;
+                }
+                self.record(seq, ts, JournalCommand::Request(order.clone()));
+
                 // generate new ID for order
-                let order_id = self.seq.next_id();
+                taker.order_id = self.seq.next_id();
                 proc_result.push(Ok(Success::Accepted {
-                    id: order_id,
+                    id: taker.order_id,
                     order_type: OrderType::Market,
-                    ts: SystemTime::now(),
+                    ts,
                 }));
 
-                self.process_market_order(
-                    &mut proc_result,
-                    order_id,
-                    order_asset,
-                    price_asset,
-                    side,
-                    qty,
-                );
+                self.process_market_order(&mut proc_result, &mut taker, qty, ts);
+                // return funds left after matching
+                self.release_funds(owner, taker.locked_asset(), taker.reserved + taker.fee_reserved);
             }
 
             OrderRequest::NewLimitOrder {
+                owner,
                 order_asset,
                 price_asset,
                 side,
                 price,
                 qty,
-                ts,
+                ts: _ts,
             } => {
+                let mut taker = Taker {
+                    order_id: UNASSIGNED_ORDER_ID,
+                    owner,
+                    order_asset,
+                    price_asset,
+                    side,
+                    order_type: OrderType::Limit,
+                    price: Some(price),
+                    reserved: 0.0,
+                    fee_reserved: 0.0,
+                };
+                if !(self.check_risk(&mut proc_result, &taker, qty) &&
+                    self.reserve_taker_funds(&mut proc_result, &mut taker, qty, ts))
+                {
+                    return proc_result;
+                }
+                self.record(seq, ts, JournalCommand::Request(order.clone()));
+
                 let order_id = self.seq.next_id();
+                taker.order_id = order_id;
                 proc_result.push(Ok(Success::Accepted {
                     id: order_id,
                     order_type: OrderType::Limit,
-                    ts: SystemTime::now(),
+                    ts,
                 }));
 
-                self.process_limit_order(
-                    &mut proc_result,
-                    order_id,
-                    order_asset,
-                    price_asset,
-                    side,
-                    price,
-                    qty,
-                    ts,
-                );
+                self.process_limit_order(&mut proc_result, &mut taker, price, qty, ts);
+                // funds of the resting order stay locked, unused taker fee does not
+                let unused = match self.queue(side).get(order_id) {
+                    Some(_) => taker.fee_reserved,
+                    None => taker.reserved + taker.fee_reserved,
+                };
+                self.release_funds(owner, taker.locked_asset(), unused);
             }
 
             OrderRequest::AmendOrder {
+                owner,
                 id,
                 side,
                 price,
                 qty,
-                ts,
+                ts: _ts,
             } => {
-                self.process_order_amend(&mut proc_result, id, side, price, qty, ts);
+                if let Some(current_order) =
+                    self.admit_amend(&mut proc_result, owner, id, side, price, qty)
+                {
+                    self.record(seq, ts, JournalCommand::Request(order.clone()));
+                    self.process_order_amend(&mut proc_result, current_order, price, qty, ts);
+                }
             }
 
-            OrderRequest::CancelOrder { id, side } => {
-                self.process_order_cancel(&mut proc_result, id, side);
+            OrderRequest::CancelOrder { owner, id, side } => {
+                if let Some(current_order) = self.owned_order(&mut proc_result, owner, id, side) {
+                    self.record(seq, ts, JournalCommand::Request(order.clone()));
+                    self.process_order_cancel(&mut proc_result, current_order, ts);
+                }
             }
         }
 
@@ -201,61 +707,97 @@ where
     }
 
 
-    /// Get current spread as a tuple: (bid, ask)
-    pub fn current_spread(&mut self) -> Option<(f64, f64)> {
-        let bid = self.bid_queue.peek()?.price;
-        let ask = self.ask_queue.peek()?.price;
-        Some((bid, ask))
+    /// Apply journaled command, recording it into journal
+    fn apply_command(
+        &mut self,
+        seq: u64,
+        ts: SystemTime,
+        command: JournalCommand<Asset>,
+    ) -> OrderProcessingResult {
+        match command {
+            JournalCommand::Request(request) => self.execute_request(seq, ts, request),
+            JournalCommand::Kill(owner) => {
+                self.record(seq, ts, JournalCommand::Kill(owner));
+                self.killed_owners.insert(owner);
+                self.cancel_owner_orders(owner, ts)
+            }
+            JournalCommand::Revive(owner) => {
+                self.record(seq, ts, JournalCommand::Revive(owner));
+                self.killed_owners.remove(&owner);
+                Vec::new()
+            }
+            JournalCommand::KillAll => {
+                self.record(seq, ts, JournalCommand::KillAll);
+                self.halted = true;
+                Vec::new()
+            }
+            JournalCommand::ReviveAll => {
+                self.record(seq, ts, JournalCommand::ReviveAll);
+                self.halted = false;
+                Vec::new()
+            }
+        }
     }
 
 
-    /* Processing logic */
+    /// Kill switch changes take the next sequence number, like requests
+    fn switch_kill(&mut self, command: JournalCommand<Asset>) -> OrderProcessingResult {
+        let seq = self.journal_seq + 1;
+        let ts = self.clock.now(None);
+        self.apply_command(seq, ts, command)
+    }
+
+
+    /// Cancel resting orders of the owner in arrival order
+    fn cancel_owner_orders(&mut self, owner: u64, ts: SystemTime) -> OrderProcessingResult {
+        let mut resting: Vec<(u64, Order<Asset>)> = Vec::new();
+        for queue in &[&self.bid_queue, &self.ask_queue] {
+            for (_, _, seq, order) in queue.indexed_orders() {
+                if order.owner == owner {
+                    resting.push((seq, order.clone()));
+                }
+            }
+        }
+        resting.sort_by_key(|&(seq, _)| seq);
+
+        let mut results = Vec::with_capacity(resting.len());
+        for (_, order) in resting {
+            self.process_order_cancel(&mut results, order, ts);
+        }
+        results
+    }
+
+
+    /// Record command with its sequence number
+    fn record(&mut self, seq: u64, ts: SystemTime, command: JournalCommand<Asset>) {
+        self.journal_seq = seq;
+        if let Some(ref mut journal) = self.journal {
+            journal.append(JournalEntry { seq, ts, command });
+        }
+    }
+
 
     fn process_market_order(
         &mut self,
         results: &mut OrderProcessingResult,
-        order_id: u64,
-        order_asset: Asset,
-        price_asset: Asset,
-        side: OrderSide,
+        taker: &mut Taker<Asset>,
         qty: f64,
+        ts: SystemTime,
     ) {
         // get copy of the current limit order
-        let opposite_order_result = {
-            let opposite_queue = match side {
-                OrderSide::Bid => &mut self.ask_queue,
-                OrderSide::Ask => &mut self.bid_queue,
-            };
-            opposite_queue.peek().cloned()
-        };
+        let opposite_order_result = self.opposite_queue(taker.side).peek().cloned();
 
         if let Some(opposite_order) = opposite_order_result {
-            let matching_complete = self.order_matching(
-                results,
-                &opposite_order,
-                order_id,
-                order_asset,
-                price_asset,
-                OrderType::Market,
-                side,
-                qty,
-            );
+            let matching_complete = self.order_matching(results, &opposite_order, taker, qty, ts);
 
             if !matching_complete {
                 // match the rest
-                self.process_market_order(
-                    results,
-                    order_id,
-                    order_asset,
-                    price_asset,
-                    side,
-                    qty - opposite_order.qty,
-                );
+                self.process_market_order(results, taker, qty - opposite_order.qty, ts);
             }
 
         } else {
             // no limit orders found
-            results.push(Err(Failed::NoMatch(order_id)));
+            results.push(Err(Failed::NoMatch(taker.order_id)));
         }
     }
 
@@ -263,25 +805,16 @@ where
     fn process_limit_order(
         &mut self,
         results: &mut OrderProcessingResult,
-        order_id: u64,
-        order_asset: Asset,
-        price_asset: Asset,
-        side: OrderSide,
+        taker: &mut Taker<Asset>,
         price: f64,
         qty: f64,
         ts: SystemTime,
     ) {
         // take a look at current opposite limit order
-        let opposite_order_result = {
-            let opposite_queue = match side {
-                OrderSide::Bid => &mut self.ask_queue,
-                OrderSide::Ask => &mut self.bid_queue,
-            };
-            opposite_queue.peek().cloned()
-        };
+        let opposite_order_result = self.opposite_queue(taker.side).peek().cloned();
 
         if let Some(opposite_order) = opposite_order_result {
-            let could_be_matched = match side {
+            let could_be_matched = match taker.side {
                 // verify bid/ask price overlap
                 OrderSide::Bid => price >= opposite_order.price,
                 OrderSide::Ask => price <= opposite_order.price,
@@ -289,156 +822,201 @@ where
 
             if could_be_matched {
                 // match immediately
-                let matching_complete = self.order_matching(
-                    results,
-                    &opposite_order,
-                    order_id,
-                    order_asset,
-                    price_asset,
-                    OrderType::Limit,
-                    side,
-                    qty,
-                );
+                let matching_complete =
+                    self.order_matching(results, &opposite_order, taker, qty, ts);
 
                 if !matching_complete {
                     // process the rest of new limit order
-                    self.process_limit_order(
-                        results,
-                        order_id,
-                        order_asset,
-                        price_asset,
-                        side,
-                        price,
-                        qty - opposite_order.qty,
-                        ts,
-                    );
+                    self.process_limit_order(results, taker, price, qty - opposite_order.qty, ts);
                 }
 
             } else {
                 // just insert new order in queue
-                self.store_new_limit_order(
-                    results,
-                    order_id,
-                    order_asset,
-                    price_asset,
-                    side,
-                    price,
-                    qty,
-                    ts,
-                );
+                self.store_new_limit_order(results, taker, price, qty);
             }
 
         } else {
-            self.store_new_limit_order(
-                results,
-                order_id,
-                order_asset,
-                price_asset,
-                side,
-                price,
-                qty,
-                ts,
-            );
+            self.store_new_limit_order(results, taker, price, qty);
         }
     }
 
 
-    fn process_order_amend(
+    /// Resting order of the owner, orders of others are reported as not found
+    fn owned_order(
+        &self,
+        results: &mut OrderProcessingResult,
+        owner: u64,
+        order_id: u64,
+        side: OrderSide,
+    ) -> Option<Order<Asset>> {
+        match self.queue(side).get(order_id) {
+            Some(order) if order.owner == owner => Some(order.clone()),
+            // do not reveal orders of other owners
+            _ => {
+                results.push(Err(Failed::OrderNotFound(order_id)));
+                None
+            }
+        }
+    }
+
+
+    /// Check new price and quantity of the resting order and adjust funds it locks
+    fn admit_amend(
         &mut self,
         results: &mut OrderProcessingResult,
+        owner: u64,
         order_id: u64,
         side: OrderSide,
         price: f64,
         qty: f64,
+    ) -> Option<Order<Asset>> {
+        let current_order = self.owned_order(results, owner, order_id, side)?;
+
+        if let (Some(risk), Some(positions)) = (&self.risk, &self.positions) {
+            if let Err(failed) = risk.check_amend(positions, order_id, owner, side, price, qty) {
+                results.push(Err(failed));
+                return None;
+            }
+        }
+
+        // adjust funds locked by the order
+        if self.accounts.is_some() {
+            let locked_asset = self.locked_asset(side);
+            let current_lock = order_lock(side, current_order.price, current_order.qty);
+            let new_lock = order_lock(side, price, qty);
+            if new_lock > current_lock {
+                if !self.reserve_funds(owner, locked_asset, new_lock - current_lock) {
+                    results.push(Err(Failed::InsufficientFunds(order_id)));
+                    return None;
+                }
+            } else {
+                self.release_funds(owner, locked_asset, current_lock - new_lock);
+            }
+        }
+        Some(current_order)
+    }
+
+
+    fn process_order_amend(
+        &mut self,
+        results: &mut OrderProcessingResult,
+        current_order: Order<Asset>,
+        price: f64,
+        qty: f64,
         ts: SystemTime,
     ) {
-        let order_queue = match side {
-            OrderSide::Bid => &mut self.bid_queue,
-            OrderSide::Ask => &mut self.ask_queue,
-        };
-
-        if order_queue.amend(
-            order_id,
+        let (order_id, side) = (current_order.order_id, current_order.side);
+        // quantity decrease at the same price keeps time priority
+        let keeps_priority = price == current_order.price && qty <= current_order.qty;
+        let amended_order = Order {
             price,
-            ts,
-            Order {
-                order_id,
-                order_asset: self.order_asset,
-                price_asset: self.price_asset,
-                side,
-                price,
-                qty,
-            },
-        )
-        {
-            results.push(Ok(Success::Amended {
-                id: order_id,
-                price,
-                qty,
-                ts: SystemTime::now(),
-            }));
+            qty,
+            ..current_order
+        };
+        if keeps_priority {
+            self.queue_mut(side).modify(order_id, amended_order);
         } else {
-            results.push(Err(Failed::OrderNotFound(order_id)));
+            self.queue_mut(side).amend(order_id, price, amended_order);
         }
+        results.push(Ok(Success::Amended {
+            id: order_id,
+            price,
+            qty,
+            ts,
+        }));
     }
 
 
     fn process_order_cancel(
         &mut self,
         results: &mut OrderProcessingResult,
-        order_id: u64,
-        side: OrderSide,
+        order: Order<Asset>,
+        ts: SystemTime,
     ) {
-        let order_queue = match side {
-            OrderSide::Bid => &mut self.bid_queue,
-            OrderSide::Ask => &mut self.ask_queue,
-        };
+        let (order_id, owner, side) = (order.order_id, order.owner, order.side);
+        self.queue_mut(side).cancel(order_id);
+        self.order_closed(owner);
+        let locked_asset = self.locked_asset(side);
+        self.release_funds(owner, locked_asset, order_lock(side, order.price, order.qty));
+        results.push(Ok(Success::Cancelled { id: order_id, ts }));
+    }
 
-        if order_queue.cancel(order_id) {
-            results.push(Ok(Success::Cancelled {
-                id: order_id,
-                ts: SystemTime::now(),
-            }));
-        } else {
-            results.push(Err(Failed::OrderNotFound(order_id)));
+
+    /* Helpers */
+
+
+    /// Price levels the request could change before matching: (side, price)
+    fn request_levels(&self, order: &OrderRequest<Asset>) -> Vec<(OrderSide, f64)> {
+        let mut levels = Vec::with_capacity(2);
+        match *order {
+            OrderRequest::NewLimitOrder { side, price, .. } => levels.push((side, price)),
+            OrderRequest::AmendOrder { id, side, price, .. } => {
+                if let Some(current_order) = self.queue(side).get(id) {
+                    levels.push((side, current_order.price));
+                }
+                levels.push((side, price));
+            }
+            OrderRequest::CancelOrder { id, side, .. } => {
+                if let Some(current_order) = self.queue(side).get(id) {
+                    levels.push((side, current_order.price));
+                }
+            }
+            OrderRequest::NewMarketOrder { .. } => (),
         }
+        levels
     }
 
 
-    /* Helpers */
+    /// Total resting quantity at every given level, single pass over each side
+    fn level_quantities(&self, levels: &[(OrderSide, f64)]) -> Vec<f64> {
+        let mut quantities = vec![0.0; levels.len()];
+        for side in [OrderSide::Bid, OrderSide::Ask] {
+            let positions: HashMap<u64, usize> = levels
+                .iter()
+                .enumerate()
+                .filter(|&(_, &(level_side, _))| level_side == side)
+                .map(|(idx, &(_, price))| (price.to_bits(), idx))
+                .collect();
+            if positions.is_empty() {
+                continue;
+            }
+            for order in self.queue(side).orders() {
+                if let Some(&idx) = positions.get(&order.price.to_bits()) {
+                    quantities[idx] += order.qty;
+                }
+            }
+        }
+        quantities
+    }
+
 
 
     fn store_new_limit_order(
         &mut self,
         results: &mut OrderProcessingResult,
-        order_id: u64,
-        order_asset: Asset,
-        price_asset: Asset,
-        side: OrderSide,
+        taker: &Taker<Asset>,
         price: f64,
         qty: f64,
-        ts: SystemTime,
     ) {
-        let order_queue = match side {
-            OrderSide::Bid => &mut self.bid_queue,
-            OrderSide::Ask => &mut self.ask_queue,
-        };
+        let order_queue = self.queue_mut(taker.side);
         if !order_queue.insert(
-            order_id,
+            taker.order_id,
             price,
-            ts,
             Order {
-                order_id,
-                order_asset,
-                price_asset,
-                side,
+                order_id: taker.order_id,
+                owner: taker.owner,
+                order_asset: taker.order_asset,
+                price_asset: taker.price_asset,
+                side: taker.side,
                 price,
                 qty,
             },
         )
         {
-            results.push(Err(Failed::DuplicateOrderID(order_id)))
-        };
+            results.push(Err(Failed::DuplicateOrderID(taker.order_id)))
+        } else if let Some(ref mut risk) = self.risk {
+            risk.order_opened(taker.owner);
+        }
     }
 
 
@@ -446,28 +1024,24 @@ where
         &mut self,
         results: &mut OrderProcessingResult,
         opposite_order: &Order<Asset>,
-        order_id: u64,
-        order_asset: Asset,
-        price_asset: Asset,
-        order_type: OrderType,
-        side: OrderSide,
+        taker: &mut Taker<Asset>,
         qty: f64,
+        deal_time: SystemTime,
     ) -> bool {
 
-        // real processing time
-        let deal_time = SystemTime::now();
-
         // match immediately
         if qty < opposite_order.qty {
             // fill new limit and modify opposite limit
+            let (taker_fee, maker_fee) = self.settle_fill(taker, opposite_order, qty, deal_time);
 
             // report filled new order
             results.push(Ok(Success::Filled {
-                order_id,
-                side,
-                order_type,
+                order_id: taker.order_id,
+                side: taker.side,
+                order_type: taker.order_type,
                 price: opposite_order.price,
                 qty,
+                fee: taker_fee,
                 ts: deal_time,
             }));
 
@@ -478,35 +1052,29 @@ where
                 order_type: OrderType::Limit,
                 price: opposite_order.price,
                 qty,
+                fee: maker_fee,
                 ts: deal_time,
             }));
 
             // modify unmatched part of the opposite limit order
-            {
-                let opposite_queue = match side {
-                    OrderSide::Bid => &mut self.ask_queue,
-                    OrderSide::Ask => &mut self.bid_queue,
-                };
-                opposite_queue.modify_current_order(Order {
-                    order_id: opposite_order.order_id,
-                    order_asset,
-                    price_asset,
-                    side: opposite_order.side,
-                    price: opposite_order.price,
-                    qty: opposite_order.qty - qty,
-                });
-            }
+            self.opposite_queue(taker.side).modify_current_order(Order {
+                qty: opposite_order.qty - qty,
+                ..opposite_order.clone()
+            });
 
         } else if qty > opposite_order.qty {
             // partially fill new limit order, fill opposite limit and notify to process the rest
+            let (taker_fee, maker_fee) =
+                self.settle_fill(taker, opposite_order, opposite_order.qty, deal_time);
 
             // report new order partially filled
             results.push(Ok(Success::PartiallyFilled {
-                order_id,
-                side,
-                order_type,
+                order_id: taker.order_id,
+                side: taker.side,
+                order_type: taker.order_type,
                 price: opposite_order.price,
                 qty: opposite_order.qty,
+                fee: taker_fee,
                 ts: deal_time,
             }));
 
@@ -517,31 +1085,29 @@ where
                 order_type: OrderType::Limit,
                 price: opposite_order.price,
                 qty: opposite_order.qty,
+                fee: maker_fee,
                 ts: deal_time,
             }));
 
             // remove filled limit order from the queue
-            {
-                let opposite_queue = match side {
-                    OrderSide::Bid => &mut self.ask_queue,
-                    OrderSide::Ask => &mut self.bid_queue,
-                };
-                opposite_queue.pop();
-            }
+            self.opposite_queue(taker.side).pop();
+            self.order_closed(opposite_order.owner);
 
             // matching incomplete
             return false;
 
         } else {
             // orders exactly match -> fill both and remove old limit
+            let (taker_fee, maker_fee) = self.settle_fill(taker, opposite_order, qty, deal_time);
 
             // report filled new order
             results.push(Ok(Success::Filled {
-                order_id,
-                side,
-                order_type,
+                order_id: taker.order_id,
+                side: taker.side,
+                order_type: taker.order_type,
                 price: opposite_order.price,
                 qty,
+                fee: taker_fee,
                 ts: deal_time,
             }));
             // report filled opposite limit order
@@ -551,41 +1117,482 @@ where
                 order_type: OrderType::Limit,
                 price: opposite_order.price,
                 qty,
+                fee: maker_fee,
                 ts: deal_time,
             }));
 
             // remove filled limit order from the queue
-            {
-                let opposite_queue = match side {
-                    OrderSide::Bid => &mut self.ask_queue,
-                    OrderSide::Ask => &mut self.bid_queue,
-                };
-                opposite_queue.pop();
-            }
+            self.opposite_queue(taker.side).pop();
+            self.order_closed(opposite_order.owner);
         }
 
         // complete matching
         true
     }
+
+
+    /* Risk management */
+
+
+    fn check_risk(
+        &mut self,
+        results: &mut OrderProcessingResult,
+        taker: &Taker<Asset>,
+        qty: f64,
+    ) -> bool {
+        // positions are enabled with risk checks
+        let (risk, positions) = match (&self.risk, &self.positions) {
+            (Some(risk), Some(positions)) => (risk, positions),
+            _ => return true,
+        };
+
+        match risk.check_new(positions, taker.order_id, taker.owner, taker.side, taker.price, qty) {
+            Ok(()) => true,
+            Err(failed) => {
+                results.push(Err(failed));
+                false
+            }
+        }
+    }
+
+
+    fn order_closed(&mut self, owner: u64) {
+        if let Some(ref mut risk) = self.risk {
+            risk.order_closed(owner);
+        }
+    }
+
+
+    /* Funds management */
+
+
+    /// Lock funds required by the new order.
+    ///
+    /// Market bid locks the cost of sweeping current asks. Taker fee paid
+    /// in the locked asset is locked too, at the owner's current rate.
+    fn reserve_taker_funds(
+        &mut self,
+        results: &mut OrderProcessingResult,
+        taker: &mut Taker<Asset>,
+        qty: f64,
+        ts: SystemTime,
+    ) -> bool {
+        if self.accounts.is_none() {
+            return true;
+        }
+
+        let amount = match (taker.side, taker.price) {
+            (side, Some(price)) => order_lock(side, price, qty),
+            (OrderSide::Bid, None) => self.market_bid_cost(qty),
+            (OrderSide::Ask, None) => qty,
+        };
+        let fee = self.taker_fee_reserve(taker, amount, ts);
+
+        if self.reserve_funds(taker.owner, taker.locked_asset(), amount + fee) {
+            taker.reserved = amount;
+            taker.fee_reserved = fee;
+            true
+        } else {
+            results.push(Err(Failed::InsufficientFunds(taker.order_id)));
+            false
+        }
+    }
+
+
+    /// Move funds between buyer and seller, both legs at once,
+    /// and charge fees of the deal.
+    ///
+    /// Returns fees paid as (taker fee, maker fee).
+    fn settle_fill(
+        &mut self,
+        taker: &mut Taker<Asset>,
+        maker: &Order<Asset>,
+        qty: f64,
+        ts: SystemTime,
+    ) -> (f64, f64) {
+        let price = maker.price;
+        let (taker_fee, maker_fee) = match self.fees {
+            Some(ref mut fees) => fees.fill_fees(taker.owner, maker.owner, price, qty, ts),
+            None => (0.0, 0.0),
+        };
+
+        let (buyer, seller) = match taker.side {
+            OrderSide::Bid => (taker.owner, maker.owner),
+            OrderSide::Ask => (maker.owner, taker.owner),
+        };
+        if let Some(ref mut positions) = self.positions {
+            positions.fill(buyer, seller, price, qty);
+        }
+
+        if let Some(ref mut accounts) = self.accounts {
+            let notional = price * qty;
+            // funds taken from taker reservation
+            let taker_spent = match taker.side {
+                OrderSide::Bid => taker.price.unwrap_or(price) * qty,
+                OrderSide::Ask => qty,
+            };
+            taker.reserved -= taker_spent;
+            let buyer_spent = match taker.side {
+                OrderSide::Bid => taker_spent,
+                OrderSide::Ask => notional,
+            };
+
+            accounts.transfer_locked(buyer, seller, self.price_asset, notional);
+            // buyer could lock more than paid, if matched at better price
+            accounts.release(buyer, self.price_asset, buyer_spent - notional);
+            accounts.transfer_locked(seller, buyer, self.order_asset, qty);
+        }
+
+        // fee reserved with the order is spent first
+        let covered = taker_fee.clamp(0.0, taker.fee_reserved);
+        taker.fee_reserved -= covered;
+        self.release_funds(taker.owner, taker.locked_asset(), covered);
+        let taker_fee = self.collect_fee(taker.owner, taker_fee);
+        let maker_fee = self.collect_fee(maker.owner, maker_fee);
+
+        if let Some(ref mut history) = self.trade_history {
+            history.record(Trade {
+                taker_order_id: taker.order_id,
+                maker_order_id: maker.order_id,
+                taker_side: taker.side,
+                price,
+                qty,
+                taker_fee,
+                maker_fee,
+                ts,
+            });
+        }
+        (taker_fee, maker_fee)
+    }
+
+
+    /// Take fee from owner's available funds or pay rebate to them.
+    ///
+    /// Fee is limited by available balance, returns amount actually charged,
+    /// which is reported in fills and trades.
+    fn collect_fee(&mut self, owner: u64, fee: f64) -> f64 {
+        let fee_asset = match self.fees {
+            Some(ref fees) if fee != 0.0 => fees.fee_asset(),
+            _ => return fee,
+        };
+
+        let fee = match self.accounts {
+            Some(ref mut accounts) => {
+                let asset = match fee_asset {
+                    FeeAsset::Price => self.price_asset,
+                    FeeAsset::Order => self.order_asset,
+                };
+                if fee > 0.0 {
+                    accounts.charge(owner, asset, fee)
+                } else {
+                    accounts.deposit(owner, asset, -fee);
+                    fee
+                }
+            }
+            None => fee,
+        };
+
+        if let Some(ref mut fees) = self.fees {
+            fees.book_revenue(fee_asset, fee);
+        }
+        fee
+    }
+
+
+    /// Price of buying given quantity from the current asks
+    fn market_bid_cost(&self, qty: f64) -> f64 {
+        let mut asks: Vec<(f64, u64, f64)> = self.ask_queue
+            .indexed_orders()
+            .into_iter()
+            .map(|(_, price, seq, order)| (price, seq, order.qty))
+            .collect();
+        asks.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let mut rest = qty;
+        let mut cost = 0.0;
+        for (price, _, ask_qty) in asks {
+            if rest <= 0.0 {
+                break;
+            }
+            let fill_qty = rest.min(ask_qty);
+            cost += fill_qty * price;
+            rest -= fill_qty;
+        }
+        cost
+    }
+
+
+    /// Highest taker fee for the order locking `amount`, if paid in the locked asset
+    fn taker_fee_reserve(&mut self, taker: &Taker<Asset>, amount: f64, ts: SystemTime) -> f64 {
+        let fees = match self.fees {
+            Some(ref mut fees) => fees,
+            None => return 0.0,
+        };
+        // otherwise fee is paid from the funds received
+        let paid_in_locked = matches!(
+            (fees.fee_asset(), taker.side),
+            (FeeAsset::Price, OrderSide::Bid) | (FeeAsset::Order, OrderSide::Ask)
+        );
+        if !paid_in_locked {
+            return 0.0;
+        }
+        // fee base never exceeds locked amount
+        fees.rates(taker.owner, ts).taker.max(0.0) * amount
+    }
+
+
+    fn reserve_funds(&mut self, owner: u64, asset: Asset, amount: f64) -> bool {
+        match self.accounts {
+            Some(ref mut accounts) => accounts.reserve(owner, asset, amount),
+            None => true,
+        }
+    }
+
+
+    fn release_funds(&mut self, owner: u64, asset: Asset, amount: f64) {
+        if let Some(ref mut accounts) = self.accounts {
+            if amount > 0.0 {
+                accounts.release(owner, asset, amount);
+            }
+        }
+    }
+
+
+    /// Asset locked by orders of given side
+    fn locked_asset(&self, side: OrderSide) -> Asset {
+        match side {
+            OrderSide::Bid => self.price_asset,
+            OrderSide::Ask => self.order_asset,
+        }
+    }
+
+
+    fn queue(&self, side: OrderSide) -> &OrderQueue<Order<Asset>> {
+        match side {
+            OrderSide::Bid => &self.bid_queue,
+            OrderSide::Ask => &self.ask_queue,
+        }
+    }
+
+
+    fn queue_mut(&mut self, side: OrderSide) -> &mut OrderQueue<Order<Asset>> {
+        match side {
+            OrderSide::Bid => &mut self.bid_queue,
+            OrderSide::Ask => &mut self.ask_queue,
+        }
+    }
+
+
+    fn opposite_queue(&mut self, side: OrderSide) -> &mut OrderQueue<Order<Asset>> {
+        match side {
+            OrderSide::Bid => &mut self.ask_queue,
+            OrderSide::Ask => &mut self.bid_queue,
+        }
+    }
+}
+
+
+impl<Asset> Orderbook<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + AssetCode,
+{
+    /// Serialize full state of the orderbook into compact binary form.
+    ///
+    /// Snapshot covers requests up to `journal_seq()`, the rest
+    /// could be applied from journal after restoring.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new();
+
+        // configuration
+        writer.put_u32(self.order_asset.to_code());
+        writer.put_u32(self.price_asset.to_code());
+        writer.put_u64(MAX_STALLED_INDICES_IN_QUEUE);
+
+        // sequences
+        let (min_id, max_id, current_id) = self.seq.state();
+        writer.put_u64(min_id);
+        writer.put_u64(max_id);
+        writer.put_u64(current_id);
+        writer.put_u64(self.journal_seq);
+
+        for queue in &[&self.bid_queue, &self.ask_queue] {
+            let orders = queue.indexed_orders();
+            writer.put_u64(queue.arrival_seq());
+            writer.put_u64(orders.len() as u64);
+            for (id, price, arrival_seq, order) in orders {
+                writer.put_u64(id);
+                writer.put_f64(price);
+                writer.put_u64(arrival_seq);
+                writer.put_f64(order.qty);
+                writer.put_u64(order.owner);
+            }
+        }
+
+        // kill switch
+        writer.put_u16(self.halted as u16);
+        let mut killed: Vec<u64> = self.killed_owners.iter().cloned().collect();
+        killed.sort();
+        writer.put_u64(killed.len() as u64);
+        for owner in killed {
+            writer.put_u64(owner);
+        }
+
+        writer.into_bytes()
+    }
+
+
+    /// Rebuild orderbook from snapshot
+    pub fn restore(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        Self::restore_with_clock(snapshot, SystemClock)
+    }
+
+
+    /// Rebuild orderbook from snapshot, using provided clock for new events
+    pub fn restore_with_clock<C>(snapshot: &[u8], clock: C) -> Result<Self, SnapshotError>
+    where
+        C: Clock + 'static,
+    {
+        let mut reader = SnapshotReader::new(snapshot)?;
+
+        let order_asset: Asset = reader.get_asset()?;
+        let price_asset: Asset = reader.get_asset()?;
+        let max_stalled = reader.get_u64()?;
+        let min_id = reader.get_u64()?;
+        let max_id = reader.get_u64()?;
+        let current_id = reader.get_u64()?;
+        if min_id > max_id || current_id < min_id || current_id > max_id {
+            return Err(SnapshotError::Corrupted("order ID sequence out of range"));
+        }
+
+        let mut orderbook = Orderbook::with_clock(order_asset, price_asset, clock);
+        orderbook.seq = sequence::restore_sequence_gen(min_id, max_id, current_id);
+        orderbook.order_validator =
+            OrderRequestValidator::new(order_asset, price_asset, min_id, max_id);
+        orderbook.journal_seq = reader.get_u64()?;
+
+        for &side in &[OrderSide::Bid, OrderSide::Ask] {
+            let mut queue = OrderQueue::new(side, max_stalled, ORDER_QUEUE_INIT_CAPACITY);
+            let arrival_seq = reader.get_u64()?;
+            let orders_count = reader.get_u64()?;
+
+            for _ in 0..orders_count {
+                let order_id = reader.get_u64()?;
+                let price = reader.get_f64()?;
+                let order_seq = reader.get_u64()?;
+                let qty = reader.get_f64()?;
+                // owners are not recorded before version 2
+                let owner = if reader.version() >= 2 { reader.get_u64()? } else { 0 };
+
+                let order = Order {
+                    order_id,
+                    owner,
+                    order_asset,
+                    price_asset,
+                    side,
+                    price,
+                    qty,
+                };
+                if !queue.restore(order_id, price, order_seq, order) {
+                    return Err(SnapshotError::Corrupted("duplicate order ID"));
+                }
+            }
+            queue.set_arrival_seq(arrival_seq);
+
+            match side {
+                OrderSide::Bid => orderbook.bid_queue = queue,
+                OrderSide::Ask => orderbook.ask_queue = queue,
+            }
+        }
+
+        // kill switch is recorded since version 3
+        if reader.version() >= 3 {
+            orderbook.halted = reader.get_u16()? != 0;
+            let killed_count = reader.get_u64()?;
+            for _ in 0..killed_count {
+                orderbook.killed_owners.insert(reader.get_u64()?);
+            }
+        }
+
+        reader.finish()?;
+        Ok(orderbook)
+    }
+}
+
+
+/// Funds locked by resting order: price asset for bids, order asset for asks
+fn order_lock(side: OrderSide, price: f64, qty: f64) -> f64 {
+    match side {
+        OrderSide::Bid => price * qty,
+        OrderSide::Ask => qty,
+    }
+}
+
+
+/// Owner and kind of the request
+fn request_kind<Asset>(request: &OrderRequest<Asset>) -> (u64, RequestKind)
+where
+    Asset: Debug + Clone,
+{
+    match *request {
+        OrderRequest::NewMarketOrder { owner, .. } |
+        OrderRequest::NewLimitOrder { owner, .. } => (owner, RequestKind::NewOrder),
+        OrderRequest::AmendOrder { owner, .. } => (owner, RequestKind::Amend),
+        OrderRequest::CancelOrder { owner, .. } => (owner, RequestKind::Cancel),
+    }
+}
+
+
+/// Timestamp supplied by client with the request
+fn request_timestamp<Asset>(request: &OrderRequest<Asset>) -> Option<SystemTime>
+where
+    Asset: Debug + Clone,
+{
+    match *request {
+        OrderRequest::NewMarketOrder { ts, .. } |
+        OrderRequest::NewLimitOrder { ts, .. } |
+        OrderRequest::AmendOrder { ts, .. } => Some(ts),
+        OrderRequest::CancelOrder { .. } => None,
+    }
 }
 
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
 
     use super::*;
+    use super::super::accounts::Balance;
+    use super::super::fees::FeeRates;
+    use super::super::risk::RiskLimits;
+    use super::super::throttle::{RateLimit, ThrottleConfig};
+    use super::super::clock::{ManualClock, RequestClock};
     use super::super::orders;
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+    #[allow(clippy::upper_case_acronyms)]
     pub enum Asset {
         USD,
         BTC,
     }
 
+    impl AssetCode for Asset {
+        fn to_code(&self) -> u32 {
+            *self as u32
+        }
+
+        fn from_code(code: u32) -> Option<Self> {
+            match code {
+                0 => Some(Asset::USD),
+                1 => Some(Asset::BTC),
+                _ => None,
+            }
+        }
+    }
+
     #[test]
     fn cancel_nonexisting() {
         let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
-        let request = orders::limit_order_cancel_request(1, OrderSide::Bid);
+        let request = orders::limit_order_cancel_request(1, 1, OrderSide::Bid);
         let mut result = orderbook.process_order(request);
 
         assert_eq!(result.len(), 1);
@@ -594,4 +1601,657 @@ mod test {
             _ => panic!("unexpected events"),
         }
     }
+
+
+    fn event_timestamp(event: &Result<Success, Failed>) -> Option<SystemTime> {
+        match *event {
+            Ok(Success::Accepted { ts, .. }) |
+            Ok(Success::Filled { ts, .. }) |
+            Ok(Success::PartiallyFilled { ts, .. }) |
+            Ok(Success::Amended { ts, .. }) |
+            Ok(Success::Cancelled { ts, .. }) => Some(ts),
+            Err(_) => None,
+        }
+    }
+
+    #[test]
+    fn manual_clock_timestamps() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = ManualClock::new(start);
+        let mut orderbook = Orderbook::with_clock(Asset::BTC, Asset::USD, clock.clone());
+
+        let result = orderbook.process_order(orders::new_limit_order_request(
+            1,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            10.0,
+            1.0,
+            SystemTime::now(),
+        ));
+        assert_eq!(event_timestamp(&result[0]), Some(start));
+
+        clock.advance(Duration::from_secs(1));
+        let result = orderbook.process_order(orders::new_market_order_request(
+            1,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            0.5,
+            SystemTime::now(),
+        ));
+        assert_eq!(result.len(), 3);
+        for event in &result {
+            assert_eq!(event_timestamp(event), Some(start + Duration::from_secs(1)));
+        }
+
+        clock.advance(Duration::from_secs(1));
+        let result = orderbook.process_order(orders::limit_order_cancel_request(1, 1, OrderSide::Bid));
+        assert_eq!(event_timestamp(&result[0]), Some(start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn request_clock_timestamps() {
+        let mut orderbook = Orderbook::with_clock(Asset::BTC, Asset::USD, RequestClock::new());
+        let request_ts = UNIX_EPOCH + Duration::from_secs(42);
+
+        let result = orderbook.process_order(orders::new_limit_order_request(
+            1,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            10.0,
+            1.0,
+            request_ts,
+        ));
+        assert_eq!(event_timestamp(&result[0]), Some(request_ts));
+
+        let amend_ts = request_ts + Duration::from_secs(3);
+        let result = orderbook.process_order(orders::amend_order_request(
+            1,
+            1,
+            OrderSide::Ask,
+            11.0,
+            1.0,
+            amend_ts,
+        ));
+        assert_eq!(event_timestamp(&result[0]), Some(amend_ts));
+
+        // cancel request has no own timestamp
+        let result = orderbook.process_order(orders::limit_order_cancel_request(1, 1, OrderSide::Ask));
+        assert_eq!(event_timestamp(&result[0]), Some(amend_ts));
+    }
+
+    #[test]
+    fn identical_timestamps_fifo() {
+        let ts = UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut orderbook = Orderbook::with_clock(Asset::BTC, Asset::USD, ManualClock::new(ts));
+
+        for _ in 0..50 {
+            orderbook.process_order(orders::new_limit_order_request(
+                1,
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Ask,
+                10.0,
+                1.0,
+                ts,
+            ));
+        }
+
+        let result = orderbook.process_order(orders::new_market_order_request(
+            1,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            50.0,
+            ts,
+        ));
+
+        // makers are filled in order of arrival
+        let filled_makers: Vec<u64> = result
+            .iter()
+            .filter_map(|event| match *event {
+                Ok(Success::Filled { order_id, side: OrderSide::Ask, .. }) => Some(order_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(filled_makers, (1..51).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn amend_priority() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        for _ in 0..3 {
+            orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 2.0));
+        }
+
+        // reduced order keeps its place, increased one goes to the back
+        let amend = |id, qty| orders::amend_order_request(1, id, OrderSide::Ask, 10.0, qty, UNIX_EPOCH);
+        orderbook.process_order(amend(1, 1.0));
+        orderbook.process_order(amend(2, 3.0));
+
+        let result = orderbook.process_order(orders::new_market_order_request(
+            2,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            6.0,
+            SystemTime::now(),
+        ));
+        let filled_makers: Vec<u64> = result
+            .iter()
+            .filter_map(|event| match *event {
+                Ok(Success::Filled { order_id, side: OrderSide::Ask, .. }) => Some(order_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(filled_makers, vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn journal_replay() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.enable_journal();
+
+        let requests = vec![
+            orders::new_limit_order_request(1, Asset::BTC, Asset::USD, OrderSide::Bid, 0.98, 5.0, SystemTime::now()),
+            orders::new_limit_order_request(1, Asset::BTC, Asset::USD, OrderSide::Ask, 1.02, 1.0, SystemTime::now()),
+            orders::amend_order_request(1, 1, OrderSide::Bid, 0.99, 4.0, SystemTime::now()),
+            orders::new_limit_order_request(1, Asset::BTC, Asset::USD, OrderSide::Ask, 1.03, 0.5, SystemTime::now()),
+            orders::new_market_order_request(1, Asset::BTC, Asset::USD, OrderSide::Bid, 1.2, SystemTime::now()),
+            // unknown order, not journaled
+            orders::limit_order_cancel_request(1, 7, OrderSide::Ask),
+            orders::new_limit_order_request(1, Asset::BTC, Asset::USD, OrderSide::Ask, 0.95, 2.0, SystemTime::now()),
+            // rejected by validator, not journaled
+            orders::new_limit_order_request(1, Asset::BTC, Asset::USD, OrderSide::Ask, -1.0, 2.0, SystemTime::now()),
+        ];
+
+        let live_results: Vec<OrderProcessingResult> = requests
+            .into_iter()
+            .map(|request| orderbook.process_order(request))
+            .filter(|result| {
+                !matches!(result[0], Err(Failed::ValidationFailed(_)) | Err(Failed::OrderNotFound(_)))
+            })
+            .collect();
+
+        let journal = orderbook.journal().unwrap();
+        assert_eq!(journal.len(), 6);
+        assert_eq!(journal.last_seq(), Some(orderbook.journal_seq()));
+
+        let (mut replayed, replay_results) = Orderbook::replay(journal);
+        assert_eq!(format!("{:?}", live_results), format!("{:?}", replay_results));
+        assert_eq!(replayed.journal_seq(), orderbook.journal_seq());
+        assert_eq!(replayed.current_spread(), orderbook.current_spread());
+
+        // both books continue identically
+        let next = orders::new_market_order_request(1, Asset::BTC, Asset::USD, OrderSide::Ask, 0.5, SystemTime::now());
+        let live_next = orderbook.process_order(next.clone());
+        let replay_next = replayed.process_order(next);
+        assert_eq!(live_next.len(), replay_next.len());
+        match (&live_next[0], &replay_next[0]) {
+            (&Ok(Success::Accepted { id: live_id, .. }), &Ok(Success::Accepted { id: replay_id, .. })) => {
+                assert_eq!(live_id, replay_id)
+            }
+            _ => panic!("unexpected events: {:?} {:?}", live_next, replay_next),
+        }
+    }
+
+    #[test]
+    fn snapshot_restore_with_journal() {
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000));
+        let mut orderbook = Orderbook::with_clock(Asset::BTC, Asset::USD, clock.clone());
+        orderbook.enable_journal();
+
+        let new_limit = |side, price, qty| {
+            orders::new_limit_order_request(1, Asset::BTC, Asset::USD, side, price, qty, UNIX_EPOCH)
+        };
+
+        orderbook.process_order(new_limit(OrderSide::Bid, 0.98, 5.0));
+        orderbook.process_order(new_limit(OrderSide::Bid, 0.99, 1.0));
+        orderbook.process_order(new_limit(OrderSide::Ask, 1.02, 1.0));
+        orderbook.process_order(new_limit(OrderSide::Ask, 1.02, 2.0));
+        orderbook.process_order(new_limit(OrderSide::Bid, 0.99, 3.0));
+        orderbook.process_order(orders::limit_order_cancel_request(1, 2, OrderSide::Bid));
+
+        let snapshot = orderbook.snapshot();
+        let snapshot_seq = orderbook.journal_seq();
+
+        // continue processing after snapshot
+        clock.advance(Duration::from_secs(1));
+        let live_results = vec![
+            orderbook.process_order(new_limit(OrderSide::Ask, 0.99, 2.0)),
+            orderbook.process_order(new_limit(OrderSide::Bid, 0.99, 0.5)),
+            orderbook.process_order(orders::new_market_order_request(
+                1,
+                Asset::BTC,
+                Asset::USD,
+                OrderSide::Bid,
+                2.5,
+                UNIX_EPOCH,
+            )),
+        ];
+
+        let mut restored = Orderbook::restore_with_clock(&snapshot, clock.clone()).unwrap();
+        assert_eq!(restored.journal_seq(), snapshot_seq);
+
+        let replay_results = restored.apply_journal(orderbook.journal().unwrap());
+        assert_eq!(format!("{:?}", live_results), format!("{:?}", replay_results));
+        assert_eq!(restored.journal_seq(), orderbook.journal_seq());
+        assert_eq!(restored.current_spread(), orderbook.current_spread());
+        assert_eq!(restored.snapshot(), orderbook.snapshot());
+    }
+
+    #[test]
+    fn restore_broken_snapshot() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.process_order(orders::new_limit_order_request(
+            1,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            1.0,
+            1.0,
+            SystemTime::now(),
+        ));
+        let snapshot = orderbook.snapshot();
+
+        let truncated = &snapshot[..snapshot.len() - 1];
+        assert_eq!(Orderbook::<Asset>::restore(truncated).err(), Some(SnapshotError::Truncated));
+
+        let mut unknown_asset = snapshot.clone();
+        unknown_asset[6] = 9;
+        assert_eq!(
+            Orderbook::<Asset>::restore(&unknown_asset).err(),
+            Some(SnapshotError::UnknownAsset(9))
+        );
+    }
+
+    fn owner_limit(owner: u64, side: OrderSide, price: f64, qty: f64) -> OrderRequest<Asset> {
+        orders::new_limit_order_request(owner, Asset::BTC, Asset::USD, side, price, qty, UNIX_EPOCH)
+    }
+
+    #[test]
+    fn order_id_range() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let accepted_id = |results: &OrderProcessingResult| match results[0] {
+            Ok(Success::Accepted { id, .. }) => id,
+            ref result => panic!("unexpected {:?}", result),
+        };
+
+        orderbook.set_max_order_id(2);
+        assert_eq!(accepted_id(&orderbook.process_order(owner_limit(1, OrderSide::Bid, 1.0, 1.0))), 1);
+        assert_eq!(accepted_id(&orderbook.process_order(owner_limit(1, OrderSide::Bid, 0.9, 1.0))), 2);
+        // wrapped ID is taken by the resting bid
+        let results = orderbook.process_order(owner_limit(1, OrderSide::Bid, 0.8, 1.0));
+        assert!(matches!(results[..], [Ok(Success::Accepted { id: 1, .. }), Err(Failed::DuplicateOrderID(1))]));
+
+        orderbook.set_max_order_id(MAX_SEQUENCE_ID * 10);
+        let cancel = orders::limit_order_cancel_request(1, MAX_SEQUENCE_ID + 1, OrderSide::Bid);
+        assert!(matches!(orderbook.process_order(cancel)[0], Err(Failed::OrderNotFound(_))));
+    }
+
+    fn funded_orderbook() -> Orderbook<Asset> {
+        let mut accounts = Accounts::new();
+        accounts.deposit(1, Asset::BTC, 10.0);
+        accounts.deposit(2, Asset::USD, 100.0);
+
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.enable_accounts(accounts);
+        orderbook
+    }
+
+    #[test]
+    fn accounts_settle_fills() {
+        let mut orderbook = funded_orderbook();
+
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 2.0));
+        let accounts = orderbook.accounts().unwrap();
+        assert_eq!(accounts.balance(1, Asset::BTC), Balance { available: 8.0, locked: 2.0 });
+
+        // bid locks its limit price, matched at the better one
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 12.0, 1.0));
+        let accounts = orderbook.accounts().unwrap();
+        assert_eq!(accounts.balance(2, Asset::USD), Balance { available: 90.0, locked: 0.0 });
+        assert_eq!(accounts.balance(2, Asset::BTC), Balance { available: 1.0, locked: 0.0 });
+        assert_eq!(accounts.balance(1, Asset::USD), Balance { available: 10.0, locked: 0.0 });
+        assert_eq!(accounts.balance(1, Asset::BTC), Balance { available: 8.0, locked: 1.0 });
+
+        // market bid pays for the rest of the book
+        let result = orderbook.process_order(orders::new_market_order_request(
+            2,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            3.0,
+            UNIX_EPOCH,
+        ));
+        assert_eq!(result.len(), 4);
+        assert!(matches!(result[3], Err(Failed::NoMatch(_))));
+        let accounts = orderbook.accounts().unwrap();
+        assert_eq!(accounts.balance(2, Asset::USD), Balance { available: 80.0, locked: 0.0 });
+        assert_eq!(accounts.balance(1, Asset::BTC), Balance { available: 8.0, locked: 0.0 });
+    }
+
+    #[test]
+    fn accounts_insufficient_funds() {
+        let mut orderbook = funded_orderbook();
+        orderbook.enable_journal();
+
+        // rejected before getting an ID
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 20.0));
+        assert_eq!(result.len(), 1);
+        assert!(matches!(result[0], Err(Failed::InsufficientFunds(0))));
+        assert_eq!(orderbook.current_spread(), None);
+
+        let result = orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 11.0));
+        assert_eq!(result.len(), 1);
+        assert!(matches!(result[0], Err(Failed::InsufficientFunds(0))));
+        assert_eq!(orderbook.journal_seq(), 0);
+
+        let accounts = orderbook.accounts().unwrap();
+        assert_eq!(accounts.balance(2, Asset::USD), Balance { available: 100.0, locked: 0.0 });
+        assert_eq!(accounts.balance(1, Asset::BTC), Balance { available: 10.0, locked: 0.0 });
+
+        let result = orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 1.0));
+        assert!(matches!(result[0], Ok(Success::Accepted { id: 1, .. })));
+        assert_eq!(orderbook.journal().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn replay_skips_rejected_requests() {
+        let mut orderbook = funded_orderbook();
+        orderbook.enable_journal();
+
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 2.0));
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 20.0));
+        assert!(matches!(result[0], Err(Failed::InsufficientFunds(0))));
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 1.0));
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 9.0, 1.0));
+
+        // plain book reproduces the funded one
+        let (mut replayed, _) = Orderbook::replay(orderbook.journal().unwrap());
+        assert_eq!(replayed.depth(usize::MAX), orderbook.depth(usize::MAX));
+        assert_eq!(replayed.journal_seq(), orderbook.journal_seq());
+
+        let next = owner_limit(2, OrderSide::Bid, 9.0, 1.0);
+        let live_next = orderbook.process_order(next.clone());
+        assert!(matches!(live_next[0], Ok(Success::Accepted { id: 4, .. })));
+        assert!(matches!(replayed.process_order(next)[0], Ok(Success::Accepted { id: 4, .. })));
+    }
+
+    #[test]
+    fn accounts_amend_and_cancel() {
+        let mut orderbook = funded_orderbook();
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 5.0));
+
+        let result = orderbook.process_order(
+            orders::amend_order_request(2, 1, OrderSide::Bid, 30.0, 5.0, UNIX_EPOCH),
+        );
+        assert!(matches!(result[0], Err(Failed::InsufficientFunds(1))));
+
+        orderbook.process_order(
+            orders::amend_order_request(2, 1, OrderSide::Bid, 10.0, 2.0, UNIX_EPOCH),
+        );
+        let accounts = orderbook.accounts().unwrap();
+        assert_eq!(accounts.balance(2, Asset::USD), Balance { available: 80.0, locked: 20.0 });
+
+        // orders could be cancelled by their owners only
+        let result = orderbook.process_order(orders::limit_order_cancel_request(1, 1, OrderSide::Bid));
+        assert!(matches!(result[0], Err(Failed::OrderNotFound(1))));
+
+        orderbook.process_order(orders::limit_order_cancel_request(2, 1, OrderSide::Bid));
+        let accounts = orderbook.accounts().unwrap();
+        assert_eq!(accounts.balance(2, Asset::USD), Balance { available: 100.0, locked: 0.0 });
+    }
+
+    #[test]
+    fn fees_charged_on_fills() {
+        let mut orderbook = funded_orderbook();
+        let mut fees = FeeSchedule::new(FeeAsset::Price, FeeRates { maker: -0.01, taker: 0.02 });
+        fees.set_owner_rates(1, FeeRates { maker: -0.005, taker: 0.01 });
+        orderbook.enable_fees(fees);
+
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 2.0));
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 1.0));
+        let trades = market_data::trades(&result);
+        assert_eq!((trades[0].taker_fee, trades[0].maker_fee), (0.2, -0.05));
+
+        let accounts = orderbook.accounts().unwrap();
+        assert_eq!(accounts.balance(2, Asset::USD).available, 89.8);
+        assert_eq!(accounts.balance(1, Asset::USD).available, 10.05);
+        assert!((orderbook.fees().unwrap().revenue(FeeAsset::Price) - 0.15).abs() < 1e-9);
+    }
+
+    #[test]
+    fn taker_fee_reserved_with_order() {
+        let mut accounts = Accounts::new();
+        accounts.deposit(1, Asset::BTC, 10.0);
+        accounts.deposit(2, Asset::USD, 10.1);
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.enable_accounts(accounts);
+        orderbook.enable_fees(FeeSchedule::new(FeeAsset::Price, FeeRates { maker: 0.0, taker: 0.02 }));
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 1.0));
+
+        // funds cover the order, but not its fee
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 1.0));
+        assert!(matches!(result[..], [Err(Failed::InsufficientFunds(0))]));
+
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 0.5));
+        assert_eq!(market_data::trades(&result)[0].taker_fee, 0.1);
+        let accounts = orderbook.accounts().unwrap();
+        assert_eq!(accounts.balance(2, Asset::USD), Balance { available: 5.0, locked: 0.0 });
+
+        // resting order keeps its funds locked, fee reserve is returned
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 9.0, 0.5));
+        let balance = orderbook.accounts().unwrap().balance(2, Asset::USD);
+        assert!((balance.available - 0.5).abs() < 1e-9);
+        assert_eq!(balance.locked, 4.5);
+    }
+
+    #[test]
+    fn risk_checks_before_matching() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 2.0));
+        orderbook.enable_risk(RiskChecks::new(RiskLimits {
+            max_open_orders: Some(1),
+            ..Default::default()
+        }));
+        assert_eq!(orderbook.risk().unwrap().open_orders(1), 1);
+
+        let result = orderbook.process_order(owner_limit(1, OrderSide::Ask, 11.0, 1.0));
+        assert_eq!(result.len(), 1);
+        assert!(matches!(result[0], Err(Failed::MaxOpenOrdersExceeded(0))));
+
+        // filled order frees the slot
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 2.0));
+        assert_eq!(orderbook.position(1).unwrap().qty, -2.0);
+        let result = orderbook.process_order(owner_limit(1, OrderSide::Ask, 11.0, 1.0));
+        assert_eq!(result.len(), 1);
+
+        orderbook.risk_mut().unwrap().set_limits(RiskLimits {
+            max_price_deviation: Some(0.05),
+            ..Default::default()
+        });
+        let result = orderbook.process_order(
+            orders::amend_order_request(1, 3, OrderSide::Ask, 12.0, 1.0, UNIX_EPOCH),
+        );
+        assert!(matches!(result[0], Err(Failed::PriceDeviationExceeded(3))));
+    }
+
+    #[test]
+    fn risk_rejections_before_ids() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.enable_risk(RiskChecks::new(RiskLimits {
+            max_order_qty: Some(5.0),
+            max_position: Some(3.0),
+            max_price_deviation: Some(0.1),
+            ..Default::default()
+        }));
+        orderbook.enable_journal();
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 1.0));
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 1.0));
+
+        // rejections are the only events and use no IDs
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 6.0));
+        assert!(matches!(result[..], [Err(Failed::MaxQtyExceeded(0))]));
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 12.0, 1.0));
+        assert!(matches!(result[..], [Err(Failed::PriceDeviationExceeded(0))]));
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 10.0, 3.0));
+        assert!(matches!(result[..], [Err(Failed::PositionLimitExceeded(0))]));
+        assert_eq!(orderbook.journal_seq(), 2);
+
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 9.5, 1.0));
+        assert!(matches!(result[0], Ok(Success::Accepted { id: 3, .. })));
+
+        let result = orderbook.process_order(
+            orders::amend_order_request(2, 3, OrderSide::Bid, 9.5, 6.0, UNIX_EPOCH),
+        );
+        assert!(matches!(result[..], [Err(Failed::MaxQtyExceeded(3))]));
+        assert_eq!(orderbook.journal_seq(), 3);
+    }
+
+    #[test]
+    fn throttle_by_engine_clock() {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let mut orderbook = Orderbook::with_clock(Asset::BTC, Asset::USD, clock.clone());
+        orderbook.enable_throttle(Throttle::new(ThrottleConfig {
+            new_orders: Some(RateLimit { burst: 1.0, per_second: 1.0 }),
+            cancels: Some(RateLimit { burst: 1.0, per_second: 0.5 }),
+            ..Default::default()
+        }));
+        orderbook.enable_journal();
+
+        orderbook.process_order(owner_limit(1, OrderSide::Bid, 10.0, 1.0));
+        let result = orderbook.process_order(owner_limit(1, OrderSide::Bid, 10.0, 1.0));
+        assert!(matches!(result[0], Err(Failed::Throttled(1))));
+        // rejected requests are not journaled
+        assert_eq!(orderbook.journal_seq(), 1);
+
+        clock.advance(Duration::from_secs(1));
+        let result = orderbook.process_order(owner_limit(1, OrderSide::Bid, 10.0, 1.0));
+        assert!(matches!(result[0], Ok(Success::Accepted { id: 2, .. })));
+
+        orderbook.process_order(orders::limit_order_cancel_request(1, 1, OrderSide::Bid));
+        let cancel = orders::limit_order_cancel_request(1, 2, OrderSide::Bid);
+        assert!(matches!(orderbook.process_order(cancel)[0], Err(Failed::Throttled(1))));
+    }
+
+    #[test]
+    fn kill_switch() {
+        let mut orderbook = funded_orderbook();
+        orderbook.enable_journal();
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 9.0, 1.0));
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 11.0, 1.0));
+        orderbook.process_order(owner_limit(2, OrderSide::Bid, 9.5, 1.0));
+
+        let killed = orderbook.kill(2);
+        assert_eq!(killed.len(), 2);
+        assert!(matches!(killed[0], Ok(Success::Cancelled { id: 1, .. })));
+        assert!(matches!(killed[1], Ok(Success::Cancelled { id: 3, .. })));
+        // cancels are repeated by the journaled kill
+        assert_eq!(orderbook.journal_seq(), 4);
+        let balance = orderbook.accounts().unwrap().balance(2, Asset::USD);
+        assert_eq!(balance, Balance { available: 100.0, locked: 0.0 });
+
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 9.0, 1.0));
+        assert!(matches!(result[0], Err(Failed::OwnerKilled(2))));
+
+        orderbook.kill_all();
+        let result = orderbook.process_order(owner_limit(1, OrderSide::Ask, 12.0, 1.0));
+        assert!(matches!(result[0], Err(Failed::TradingHalted)));
+        let cancel = orders::limit_order_cancel_request(1, 2, OrderSide::Ask);
+        assert!(matches!(orderbook.process_order(cancel)[0], Ok(Success::Cancelled { id: 2, .. })));
+
+        // and replay
+        let (replayed, replay_results) = Orderbook::replay(orderbook.journal().unwrap());
+        assert_eq!(replay_results.len(), 6);
+        assert_eq!(format!("{:?}", replay_results[3]), format!("{:?}", killed));
+        assert!(replayed.is_halted() && replayed.is_killed(2) && !replayed.is_killed(1));
+        assert_eq!(replayed.depth(usize::MAX), orderbook.depth(usize::MAX));
+
+        // kill switch survives restore
+        let mut restored = Orderbook::<Asset>::restore(&orderbook.snapshot()).unwrap();
+        assert!(restored.is_halted() && restored.is_killed(2) && !restored.is_killed(1));
+        restored.revive_all();
+        restored.revive(2);
+        let result = restored.process_order(owner_limit(2, OrderSide::Bid, 9.0, 1.0));
+        assert!(matches!(result[0], Ok(Success::Accepted { .. })));
+    }
+
+    #[test]
+    fn positions_marked_to_market() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        orderbook.enable_positions();
+        orderbook.process_order(owner_limit(1, OrderSide::Bid, 9.0, 2.0));
+        orderbook.process_order(owner_limit(2, OrderSide::Ask, 11.0, 2.0));
+        assert_eq!(orderbook.mark_price(), Some(10.0));
+        assert_eq!(orderbook.unrealized_pnl(1), Some(0.0));
+
+        orderbook.process_order(owner_limit(3, OrderSide::Ask, 9.0, 1.0));
+        orderbook.process_order(owner_limit(3, OrderSide::Bid, 11.0, 1.0));
+        assert_eq!(orderbook.mark_price(), Some(11.0));
+
+        let position = orderbook.position(3).unwrap();
+        assert_eq!((position.qty, position.realized_pnl), (0.0, -2.0));
+        assert_eq!(orderbook.unrealized_pnl(1), Some(2.0));
+        assert_eq!(orderbook.unrealized_pnl(2), Some(0.0));
+    }
+
+    #[test]
+    fn trade_history_from_fills() {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let mut orderbook = Orderbook::with_clock(Asset::BTC, Asset::USD, clock.clone());
+        orderbook.enable_trade_history(TradeHistory::default());
+
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 10.0, 1.0));
+        orderbook.process_order(owner_limit(1, OrderSide::Ask, 11.0, 1.0));
+        clock.advance(Duration::from_secs(90));
+        let result = orderbook.process_order(owner_limit(2, OrderSide::Bid, 11.0, 2.0));
+
+        let history = orderbook.trade_history_mut().unwrap();
+        let trades: Vec<Trade> = history.trades().iter().cloned().collect();
+        assert_eq!(trades, market_data::trades(&result));
+
+        let candles = history.candles(Duration::from_secs(60)).unwrap();
+        assert_eq!(candles.len(), 1);
+        assert_eq!((candles[0].open, candles[0].close, candles[0].volume), (10.0, 11.0, 2.0));
+        assert_eq!(candles[0].start, UNIX_EPOCH + Duration::from_secs(60));
+
+        let stats = history.stats(UNIX_EPOCH + Duration::from_secs(90));
+        assert_eq!((stats.last_price, stats.vwap), (Some(11.0), Some(10.5)));
+    }
+
+    #[test]
+    fn deltas_of_touched_levels() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let market = |side, qty| {
+            orders::new_market_order_request(1, Asset::BTC, Asset::USD, side, qty, UNIX_EPOCH)
+        };
+        let requests = vec![
+            owner_limit(1, OrderSide::Bid, 9.0, 1.0),
+            owner_limit(1, OrderSide::Bid, 9.0, 2.0),
+            owner_limit(1, OrderSide::Bid, 8.5, 1.0),
+            owner_limit(2, OrderSide::Ask, 10.0, 1.0),
+            owner_limit(2, OrderSide::Ask, 10.5, 2.0),
+            // amend to the new level, then back, same quantity
+            orders::amend_order_request(1, 2, OrderSide::Bid, 8.0, 2.0, UNIX_EPOCH),
+            orders::amend_order_request(1, 2, OrderSide::Bid, 9.0, 2.0, UNIX_EPOCH),
+            orders::limit_order_cancel_request(1, 1, OrderSide::Bid),
+            // sweeps two levels and rests
+            owner_limit(3, OrderSide::Bid, 10.5, 4.0),
+            market(OrderSide::Ask, 2.5),
+            // rejected and unmatched requests change nothing
+            orders::limit_order_cancel_request(1, 1, OrderSide::Bid),
+            market(OrderSide::Ask, 10.0),
+        ];
+
+        for request in requests {
+            let before = orderbook.depth(usize::MAX);
+            let (_, deltas) = orderbook.process_order_with_deltas(request);
+            let after = orderbook.depth(usize::MAX);
+            assert_eq!(deltas, market_data::depth_deltas(&before, &after));
+        }
+        assert_eq!(orderbook.depth(usize::MAX), Depth::default());
+    }
 }
@@ -2,40 +2,51 @@
 use std::time::SystemTime;
 use std::fmt::Debug;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use super::domain::OrderSide;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OrderRequest<Asset>
 where
     Asset: Debug + Clone,
 {
     NewMarketOrder {
+        owner: u64,
         order_asset: Asset,
         price_asset: Asset,
         side: OrderSide,
         qty: f64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
         ts: SystemTime,
     },
 
     NewLimitOrder {
+        owner: u64,
         order_asset: Asset,
         price_asset: Asset,
         side: OrderSide,
         price: f64,
         qty: f64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
         ts: SystemTime,
     },
 
     AmendOrder {
+        owner: u64,
         id: u64,
         side: OrderSide,
         price: f64,
         qty: f64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
         ts: SystemTime,
     },
 
     CancelOrder {
+        owner: u64,
         id: u64,
         side: OrderSide,
         //ts: SystemTime,
@@ -48,6 +59,7 @@ where
 
 /// Create request for the new market order
 pub fn new_market_order_request<Asset>(
+    owner: u64,
     order_asset: Asset,
     price_asset: Asset,
     side: OrderSide,
@@ -59,6 +71,7 @@ where
 {
 
     OrderRequest::NewMarketOrder {
+        owner,
         order_asset,
         price_asset,
         qty,
@@ -70,6 +83,7 @@ where
 
 /// Create request for the new limit order
 pub fn new_limit_order_request<Asset>(
+    owner: u64,
     order_asset: Asset,
     price_asset: Asset,
     side: OrderSide,
@@ -82,6 +96,7 @@ where
 {
 
     OrderRequest::NewLimitOrder {
+        owner,
         order_asset,
         price_asset,
         side,
@@ -97,6 +112,7 @@ where
 /// Note: do not change order side!
 /// Instead cancel existing order and create a new one.
 pub fn amend_order_request<Asset>(
+    owner: u64,
     id: u64,
     side: OrderSide,
     price: f64,
@@ -108,6 +124,7 @@ where
 {
 
     OrderRequest::AmendOrder {
+        owner,
         id,
         side,
         price,
@@ -118,9 +135,17 @@ where
 
 
 /// Create request for cancelling active limit order
-pub fn limit_order_cancel_request<Asset>(order_id: u64, side: OrderSide) -> OrderRequest<Asset>
+pub fn limit_order_cancel_request<Asset>(
+    owner: u64,
+    order_id: u64,
+    side: OrderSide,
+) -> OrderRequest<Asset>
 where
     Asset: Debug + Clone,
 {
-    OrderRequest::CancelOrder { id: order_id, side }
+    OrderRequest::CancelOrder {
+        owner,
+        id: order_id,
+        side,
+    }
 }
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+
+/// Net position of owner in the order asset
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Position {
+    /// Bought minus sold, negative for short position
+    pub qty: f64,
+    /// Average price of the open position
+    pub avg_price: f64,
+    /// Profit of the closed part of position, in price asset
+    pub realized_pnl: f64,
+}
+
+
+impl Position {
+    /// Profit of the open position, if closed at `mark_price`
+    pub fn unrealized_pnl(&self, mark_price: f64) -> f64 {
+        (mark_price - self.avg_price) * self.qty
+    }
+
+
+    /// Apply fill of signed quantity: positive for buy, negative for sell
+    fn apply(&mut self, price: f64, qty: f64) {
+        if self.qty == 0.0 || self.qty.signum() == qty.signum() {
+            // open or extend position
+            let total = self.qty + qty;
+            self.avg_price = (self.avg_price * self.qty + price * qty) / total;
+            self.qty = total;
+            return;
+        }
+
+        // reduce position, reversing it if fill is larger
+        let closed = qty.abs().min(self.qty.abs()) * self.qty.signum();
+        self.realized_pnl += (price - self.avg_price) * closed;
+        self.qty += qty;
+
+        if self.qty == 0.0 {
+            self.avg_price = 0.0;
+        } else if self.qty.signum() == qty.signum() {
+            self.avg_price = price;
+        }
+    }
+}
+
+
+/// Positions of all owners, updated from fills
+#[derive(Debug, Clone, Default)]
+pub struct Positions {
+    positions: HashMap<u64, Position>,
+    last_price: Option<f64>,
+}
+
+
+impl Positions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+
+    pub fn position(&self, owner: u64) -> Position {
+        self.positions.get(&owner).cloned().unwrap_or_default()
+    }
+
+
+    /// Owners with any trading history
+    pub fn owners(&self) -> Vec<u64> {
+        let mut owners: Vec<u64> = self.positions.keys().cloned().collect();
+        owners.sort();
+        owners
+    }
+
+
+    /// Price of the last deal
+    pub fn last_price(&self) -> Option<f64> {
+        self.last_price
+    }
+
+
+    /// Update buyer and seller positions with the deal
+    pub fn fill(&mut self, buyer: u64, seller: u64, price: f64, qty: f64) {
+        self.positions.entry(buyer).or_default().apply(price, qty);
+        self.positions.entry(seller).or_default().apply(price, -qty);
+        self.last_price = Some(price);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_price_and_pnl() {
+        let mut positions = Positions::new();
+        positions.fill(1, 2, 10.0, 1.0);
+        positions.fill(1, 2, 12.0, 1.0);
+
+        let position = positions.position(1);
+        assert_eq!((position.qty, position.avg_price), (2.0, 11.0));
+        assert_eq!(position.unrealized_pnl(12.0), 2.0);
+        assert_eq!(positions.position(2).unrealized_pnl(12.0), -2.0);
+
+        // sell more than held reverses the position
+        positions.fill(2, 1, 13.0, 3.0);
+        let position = positions.position(1);
+        assert_eq!((position.qty, position.avg_price), (-1.0, 13.0));
+        assert_eq!(position.realized_pnl, 4.0);
+
+        let position = positions.position(2);
+        assert_eq!((position.qty, position.avg_price), (1.0, 13.0));
+        assert_eq!(position.realized_pnl, -4.0);
+        assert_eq!(positions.owners(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::domain::OrderSide;
+use super::orderbook::Failed;
+use super::positions::Positions;
+
+
+/// Pre-trade limits, `None` disables the check
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RiskLimits {
+    /// Max quantity of a single order
+    pub max_order_qty: Option<f64>,
+    /// Max price * qty of a single order
+    pub max_notional: Option<f64>,
+    /// Max number of resting orders per owner
+    pub max_open_orders: Option<usize>,
+    /// Max absolute net position, if the order is fully filled
+    pub max_position: Option<f64>,
+    /// Max relative distance of limit price from the last trade price
+    pub max_price_deviation: Option<f64>,
+}
+
+
+/// Risk layer of the orderbook, evaluated before matching.
+///
+/// Keeps open orders of owners, net positions and the last
+/// trade price are read from the orderbook `Positions`.
+#[derive(Debug, Clone, Default)]
+pub struct RiskChecks {
+    limits: RiskLimits,
+    owner_limits: HashMap<u64, RiskLimits>,
+    open_orders: HashMap<u64, usize>,
+}
+
+
+impl RiskChecks {
+    /// Risk checks with limits applied to every owner
+    pub fn new(limits: RiskLimits) -> Self {
+        RiskChecks {
+            limits,
+            ..Default::default()
+        }
+    }
+
+
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+
+
+    pub fn set_limits(&mut self, limits: RiskLimits) {
+        self.limits = limits;
+    }
+
+
+    /// Limits for the owner, replacing common ones
+    pub fn set_owner_limits(&mut self, owner: u64, limits: RiskLimits) {
+        self.owner_limits.insert(owner, limits);
+    }
+
+
+    pub fn remove_owner_limits(&mut self, owner: u64) {
+        self.owner_limits.remove(&owner);
+    }
+
+
+    pub fn open_orders(&self, owner: u64) -> usize {
+        self.open_orders.get(&owner).cloned().unwrap_or(0)
+    }
+
+
+    /// Check new order, `price` is `None` for market orders.
+    ///
+    /// Orderbook runs the check before the order gets an ID, `order_id` is 0 then.
+    pub fn check_new(
+        &self,
+        positions: &Positions,
+        order_id: u64,
+        owner: u64,
+        side: OrderSide,
+        price: Option<f64>,
+        qty: f64,
+    ) -> Result<(), Failed> {
+        let limits = self.owner_limits.get(&owner).unwrap_or(&self.limits);
+        // market orders never rest in the book
+        let opens_order = price.is_some();
+        if opens_order && limits.max_open_orders.is_some_and(|max| self.open_orders(owner) >= max) {
+            return Err(Failed::MaxOpenOrdersExceeded(order_id));
+        }
+        self.check_order(limits, positions, order_id, owner, side, price, qty)
+    }
+
+
+    /// Check new price and quantity of the resting order
+    pub fn check_amend(
+        &self,
+        positions: &Positions,
+        order_id: u64,
+        owner: u64,
+        side: OrderSide,
+        price: f64,
+        qty: f64,
+    ) -> Result<(), Failed> {
+        let limits = self.owner_limits.get(&owner).unwrap_or(&self.limits);
+        self.check_order(limits, positions, order_id, owner, side, Some(price), qty)
+    }
+
+
+    #[allow(clippy::too_many_arguments)]
+    fn check_order(
+        &self,
+        limits: &RiskLimits,
+        positions: &Positions,
+        order_id: u64,
+        owner: u64,
+        side: OrderSide,
+        price: Option<f64>,
+        qty: f64,
+    ) -> Result<(), Failed> {
+        if limits.max_order_qty.is_some_and(|max| qty > max) {
+            return Err(Failed::MaxQtyExceeded(order_id));
+        }
+
+        // market orders are valued at the last trade price
+        let last_price = positions.last_price();
+        let notional = price.or(last_price).map(|price| price * qty);
+        if let (Some(max), Some(notional)) = (limits.max_notional, notional) {
+            if notional > max {
+                return Err(Failed::MaxNotionalExceeded(order_id));
+            }
+        }
+
+        if let Some(max) = limits.max_position {
+            let position = positions.position(owner).qty;
+            let position = match side {
+                OrderSide::Bid => position + qty,
+                OrderSide::Ask => position - qty,
+            };
+            if position.abs() > max {
+                return Err(Failed::PositionLimitExceeded(order_id));
+            }
+        }
+
+        if let (Some(max), Some(price), Some(last)) =
+            (limits.max_price_deviation, price, last_price)
+        {
+            if ((price - last) / last).abs() > max {
+                return Err(Failed::PriceDeviationExceeded(order_id));
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /// Order of the owner was placed into the book
+    pub fn order_opened(&mut self, owner: u64) {
+        *self.open_orders.entry(owner).or_insert(0) += 1;
+    }
+
+
+    /// Order of the owner left the book, being filled or cancelled
+    pub fn order_closed(&mut self, owner: u64) {
+        if let Some(count) = self.open_orders.get_mut(&owner) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinct_violations() {
+        let mut risk = RiskChecks::new(RiskLimits {
+            max_order_qty: Some(10.0),
+            max_notional: Some(50.0),
+            max_open_orders: Some(1),
+            max_position: Some(8.0),
+            max_price_deviation: Some(0.1),
+        });
+        let mut positions = Positions::new();
+
+        assert!(matches!(
+            risk.check_new(&positions, 1, 1, OrderSide::Bid, Some(1.0), 11.0),
+            Err(Failed::MaxQtyExceeded(1))
+        ));
+        assert!(matches!(
+            risk.check_new(&positions, 2, 1, OrderSide::Bid, Some(10.0), 6.0),
+            Err(Failed::MaxNotionalExceeded(2))
+        ));
+        assert!(matches!(
+            risk.check_new(&positions, 3, 1, OrderSide::Ask, Some(1.0), 9.0),
+            Err(Failed::PositionLimitExceeded(3))
+        ));
+
+        positions.fill(1, 2, 6.0, 2.0);
+        assert!(matches!(
+            risk.check_new(&positions, 4, 1, OrderSide::Bid, Some(6.7), 1.0),
+            Err(Failed::PriceDeviationExceeded(4))
+        ));
+        // market order is valued by the last trade
+        assert!(matches!(
+            risk.check_new(&positions, 5, 1, OrderSide::Bid, None, 10.0),
+            Err(Failed::MaxNotionalExceeded(5))
+        ));
+
+        risk.order_opened(1);
+        assert!(matches!(
+            risk.check_new(&positions, 6, 1, OrderSide::Bid, Some(6.0), 1.0),
+            Err(Failed::MaxOpenOrdersExceeded(6))
+        ));
+        // limits could be relaxed for owner
+        risk.set_owner_limits(1, RiskLimits::default());
+        assert!(risk.check_new(&positions, 7, 1, OrderSide::Bid, Some(6.0), 100.0).is_ok());
+        risk.order_closed(1);
+        assert_eq!(risk.open_orders(1), 0);
+    }
+}
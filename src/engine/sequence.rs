@@ -20,6 +20,12 @@ impl TradeSequence {
 
         next_id
     }
+
+
+    /// Boundaries and current position: (min, max, current)
+    pub fn state(&self) -> (u64, u64, u64) {
+        (self.min_id, self.max_id, self.current_idx)
+    }
 }
 
 
@@ -32,6 +38,16 @@ pub fn new_sequence_gen(min: u64, max: u64) -> TradeSequence {
 }
 
 
+/// Continue sequence from given position
+pub fn restore_sequence_gen(min: u64, max: u64, current: u64) -> TradeSequence {
+    TradeSequence {
+        min_id: min,
+        max_id: max,
+        current_idx: current,
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,96 @@
+//! Serde helpers encoding `SystemTime` as nanoseconds since Unix epoch
+//!
+//! Use as `#[serde(with = "crate::engine::serde_ts")]` on timestamp fields.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::Error;
+use serde::ser::Error as SerError;
+
+
+pub fn serialize<S>(ts: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let since_epoch = ts.duration_since(UNIX_EPOCH).map_err(S::Error::custom)?;
+    let nanos = since_epoch.as_secs() as u128 * 1_000_000_000 + u128::from(since_epoch.subsec_nanos());
+    if nanos > u128::from(u64::MAX) {
+        return Err(S::Error::custom("timestamp does not fit into 64 bits of nanoseconds"));
+    }
+    serializer.serialize_u64(nanos as u64)
+}
+
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let nanos = u64::deserialize(deserializer)?;
+    UNIX_EPOCH
+        .checked_add(Duration::from_nanos(nanos))
+        .ok_or_else(|| D::Error::custom("timestamp out of range"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::engine::domain::{Order, OrderSide, OrderType};
+    use crate::engine::orderbook::{Failed, Success};
+    use crate::engine::orders::{self, OrderRequest};
+
+    #[test]
+    fn events_as_json() {
+        let ts = UNIX_EPOCH + Duration::new(1_516_040_690, 860_016_000);
+        let event: Result<Success, Failed> = Ok(Success::Accepted {
+            id: 1,
+            order_type: OrderType::Limit,
+            ts,
+        });
+
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Ok":{"Accepted":{"id":1,"order_type":"Limit","ts":1516040690860016000}}}"#
+        );
+
+        let decoded: Result<Success, Failed> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", event));
+
+        let failed = serde_json::to_string(&Failed::NoMatch(3)).unwrap();
+        assert_eq!(failed, r#"{"NoMatch":3}"#);
+    }
+
+    #[test]
+    fn requests_and_orders_roundtrip() {
+        let request = orders::new_limit_order_request(
+            1,
+            "BTC".to_string(),
+            "USD".to_string(),
+            OrderSide::Ask,
+            1.02,
+            0.5,
+            UNIX_EPOCH + Duration::from_nanos(42),
+        );
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains(r#""ts":42"#));
+
+        let decoded: OrderRequest<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", request));
+
+        let order = Order {
+            order_id: 7,
+            owner: 3,
+            order_asset: "BTC".to_string(),
+            price_asset: "USD".to_string(),
+            side: OrderSide::Bid,
+            price: 0.98,
+            qty: 5.0,
+        };
+        let decoded: Order<String> = serde_json::from_str(&serde_json::to_string(&order).unwrap())
+            .unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", order));
+    }
+}
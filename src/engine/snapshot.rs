@@ -0,0 +1,186 @@
+use std::fmt;
+
+
+/// Snapshot format marker and version
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"OBSN";
+pub const SNAPSHOT_VERSION: u16 = 3;
+/// Oldest version reader could still handle
+pub const MIN_SNAPSHOT_VERSION: u16 = 1;
+
+
+/// Compact numeric representation of asset, used in snapshots
+pub trait AssetCode: Sized {
+    fn to_code(&self) -> u32;
+    fn from_code(code: u32) -> Option<Self>;
+}
+
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnknownAsset(u32),
+    Truncated,
+    Corrupted(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::BadMagic => write!(f, "not an orderbook snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+            SnapshotError::UnknownAsset(code) => write!(f, "unknown asset code {}", code),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Corrupted(reason) => write!(f, "snapshot is corrupted: {}", reason),
+        }
+    }
+}
+
+
+/* Binary encoding, all numbers are little-endian */
+
+pub struct SnapshotWriter {
+    buf: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    pub fn new() -> Self {
+        let mut writer = SnapshotWriter { buf: Vec::with_capacity(256) };
+        writer.buf.extend_from_slice(SNAPSHOT_MAGIC);
+        writer.put_u16(SNAPSHOT_VERSION);
+        writer
+    }
+
+    pub fn put_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_f64(&mut self, value: f64) {
+        self.buf.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl Default for SnapshotWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+pub struct SnapshotReader<'a> {
+    buf: &'a [u8],
+    version: u16,
+}
+
+impl<'a> SnapshotReader<'a> {
+    /// Verify snapshot header and start reading
+    pub fn new(buf: &'a [u8]) -> Result<Self, SnapshotError> {
+        if buf.len() < SNAPSHOT_MAGIC.len() {
+            return Err(SnapshotError::Truncated);
+        }
+        if &buf[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let mut reader = SnapshotReader { buf: &buf[SNAPSHOT_MAGIC.len()..], version: 0 };
+        let version = reader.get_u16()?;
+        if !(MIN_SNAPSHOT_VERSION..=SNAPSHOT_VERSION).contains(&version) {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        reader.version = version;
+        Ok(reader)
+    }
+
+    /// Format version of the snapshot being read
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16, SnapshotError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, SnapshotError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn get_f64(&mut self) -> Result<f64, SnapshotError> {
+        Ok(f64::from_bits(self.get_u64()?))
+    }
+
+    pub fn get_asset<Asset: AssetCode>(&mut self) -> Result<Asset, SnapshotError> {
+        let code = self.get_u32()?;
+        Asset::from_code(code).ok_or(SnapshotError::UnknownAsset(code))
+    }
+
+    /// Make sure the whole snapshot was consumed
+    pub fn finish(self) -> Result<(), SnapshotError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(SnapshotError::Corrupted("trailing bytes"))
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.buf.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_values() {
+        let mut writer = SnapshotWriter::new();
+        writer.put_u32(42);
+        writer.put_u64(u64::MAX);
+        writer.put_f64(1.015);
+        let bytes = writer.into_bytes();
+
+        let mut reader = SnapshotReader::new(&bytes).unwrap();
+        assert_eq!(reader.get_u32(), Ok(42));
+        assert_eq!(reader.get_u64(), Ok(u64::MAX));
+        assert_eq!(reader.get_f64(), Ok(1.015));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[test]
+    fn bad_header() {
+        assert_eq!(SnapshotReader::new(b"OB").err(), Some(SnapshotError::Truncated));
+        assert_eq!(SnapshotReader::new(b"NOPE\x01\x00").err(), Some(SnapshotError::BadMagic));
+        assert_eq!(
+            SnapshotReader::new(b"OBSN\xff\x00").err(),
+            Some(SnapshotError::UnsupportedVersion(255))
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+
+/// Token bucket parameters: up to `burst` requests at once,
+/// refilled by `per_second` requests every second
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RateLimit {
+    pub burst: f64,
+    pub per_second: f64,
+}
+
+
+/// Limits for every kind of request, `None` means unlimited
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ThrottleConfig {
+    pub new_orders: Option<RateLimit>,
+    pub amends: Option<RateLimit>,
+    pub cancels: Option<RateLimit>,
+}
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RequestKind {
+    NewOrder,
+    Amend,
+    Cancel,
+}
+
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    updated: SystemTime,
+}
+
+
+/// Per-owner rate limiter of orderbook requests.
+///
+/// Buckets are refilled by the engine time, so throttling
+/// follows the clock orderbook is created with.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    config: ThrottleConfig,
+    buckets: HashMap<(u64, RequestKind), TokenBucket>,
+}
+
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        Throttle {
+            config,
+            buckets: HashMap::new(),
+        }
+    }
+
+
+    pub fn config(&self) -> &ThrottleConfig {
+        &self.config
+    }
+
+
+    /// Change limits, buckets keep their current tokens
+    pub fn set_config(&mut self, config: ThrottleConfig) {
+        self.config = config;
+    }
+
+
+    /// Take a token for owner's request, returns false if bucket is empty
+    pub fn allow(&mut self, owner: u64, kind: RequestKind, now: SystemTime) -> bool {
+        let limit = match kind {
+            RequestKind::NewOrder => self.config.new_orders,
+            RequestKind::Amend => self.config.amends,
+            RequestKind::Cancel => self.config.cancels,
+        };
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+
+        let bucket = self.buckets.entry((owner, kind)).or_insert(TokenBucket {
+            tokens: limit.burst,
+            updated: now,
+        });
+
+        // time going backwards does not refill the bucket
+        if let Ok(elapsed) = now.duration_since(bucket.updated) {
+            bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * limit.per_second)
+                .min(limit.burst);
+            bucket.updated = now;
+        }
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn token_bucket_refill() {
+        let mut throttle = Throttle::new(ThrottleConfig {
+            new_orders: Some(RateLimit { burst: 2.0, per_second: 1.0 }),
+            ..Default::default()
+        });
+        let now = UNIX_EPOCH + Duration::from_secs(100);
+
+        assert!(throttle.allow(1, RequestKind::NewOrder, now));
+        assert!(throttle.allow(1, RequestKind::NewOrder, now));
+        assert!(!throttle.allow(1, RequestKind::NewOrder, now));
+        // other owners and kinds have their own buckets
+        assert!(throttle.allow(2, RequestKind::NewOrder, now));
+        assert!(throttle.allow(1, RequestKind::Cancel, now));
+
+        assert!(!throttle.allow(1, RequestKind::NewOrder, now + Duration::from_millis(500)));
+        assert!(throttle.allow(1, RequestKind::NewOrder, now + Duration::from_secs(1)));
+    }
+}
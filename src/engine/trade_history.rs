@@ -0,0 +1,343 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::market_data::Trade;
+
+
+pub const DEFAULT_CANDLE_INTERVALS: [Duration; 3] = [
+    Duration::from_secs(1),
+    Duration::from_secs(60),
+    Duration::from_secs(60 * 60),
+];
+const DEFAULT_MAX_TRADES: usize = 10_000;
+const DEFAULT_MAX_CANDLES: usize = 1_000;
+const STATS_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+
+/// Open, high, low, close prices and volume of one time bucket
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Candle {
+    #[cfg_attr(feature = "serde", serde(with = "crate::engine::serde_ts"))]
+    pub start: SystemTime,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub trades: usize,
+}
+
+
+/// Summary of the recorded trades
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TradeStats {
+    pub last_price: Option<f64>,
+    /// Volume weighted average price of the last 24 hours
+    pub vwap: Option<f64>,
+    pub high_24h: Option<f64>,
+    pub low_24h: Option<f64>,
+    pub volume_24h: f64,
+}
+
+
+/// Candles of a single interval, oldest first
+#[derive(Debug, Clone)]
+struct CandleSeries {
+    interval: Duration,
+    candles: VecDeque<Candle>,
+}
+
+
+/// Trades of the book with aggregations updated on every trade.
+///
+/// Only the latest trades and candles are kept, older ones are dropped.
+/// Trades timestamped before the previous one are aggregated at the time
+/// of the previous one, so candles and stats only move forward.
+#[derive(Debug, Clone)]
+pub struct TradeHistory {
+    trades: VecDeque<Trade>,
+    max_trades: usize,
+    series: Vec<CandleSeries>,
+    max_candles: usize,
+    // aggregation time of the last trade
+    last_ts: Option<SystemTime>,
+    // trades within the stats window: (ts, price, qty), and monotonic deques of (ts, price)
+    window: VecDeque<(SystemTime, f64, f64)>,
+    window_volume: f64,
+    window_notional: f64,
+    window_highs: VecDeque<(SystemTime, f64)>,
+    window_lows: VecDeque<(SystemTime, f64)>,
+}
+
+
+impl TradeHistory {
+    /// History aggregating candles of the given intervals
+    pub fn new(intervals: &[Duration]) -> Self {
+        TradeHistory {
+            trades: VecDeque::new(),
+            max_trades: DEFAULT_MAX_TRADES,
+            series: intervals
+                .iter()
+                .filter(|interval| !interval.is_zero())
+                .map(|&interval| CandleSeries { interval, candles: VecDeque::new() })
+                .collect(),
+            max_candles: DEFAULT_MAX_CANDLES,
+            last_ts: None,
+            window: VecDeque::new(),
+            window_volume: 0.0,
+            window_notional: 0.0,
+            window_highs: VecDeque::new(),
+            window_lows: VecDeque::new(),
+        }
+    }
+
+
+    /// Number of trades kept in history
+    pub fn set_max_trades(&mut self, max_trades: usize) {
+        self.max_trades = max_trades;
+        drop_oldest(&mut self.trades, max_trades);
+    }
+
+
+    /// Number of candles kept for every interval
+    pub fn set_max_candles(&mut self, max_candles: usize) {
+        self.max_candles = max_candles;
+        for series in &mut self.series {
+            drop_oldest(&mut series.candles, max_candles);
+        }
+    }
+
+
+    pub fn record(&mut self, trade: Trade) {
+        let (price, qty) = (trade.price, trade.qty);
+        // out of order timestamp would break bucketing and the window deques
+        let ts = self.last_ts.map_or(trade.ts, |last_ts| trade.ts.max(last_ts));
+        self.last_ts = Some(ts);
+
+        for series in &mut self.series {
+            let start = bucket_start(ts, series.interval);
+            match series.candles.back_mut() {
+                Some(candle) if candle.start == start => {
+                    candle.high = candle.high.max(price);
+                    candle.low = candle.low.min(price);
+                    candle.close = price;
+                    candle.volume += qty;
+                    candle.trades += 1;
+                    continue;
+                }
+                _ => (),
+            }
+            series.candles.push_back(Candle {
+                start,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: qty,
+                trades: 1,
+            });
+            drop_oldest(&mut series.candles, self.max_candles);
+        }
+
+        // window is bounded even if stats are never requested
+        self.expire_window(ts);
+        self.window.push_back((ts, price, qty));
+        self.window_volume += qty;
+        self.window_notional += price * qty;
+        while self.window_highs.back().is_some_and(|&(_, high)| high <= price) {
+            self.window_highs.pop_back();
+        }
+        self.window_highs.push_back((ts, price));
+        while self.window_lows.back().is_some_and(|&(_, low)| low >= price) {
+            self.window_lows.pop_back();
+        }
+        self.window_lows.push_back((ts, price));
+
+        self.trades.push_back(trade);
+        drop_oldest(&mut self.trades, self.max_trades);
+    }
+
+
+    /// Recorded trades, oldest first
+    pub fn trades(&self) -> &VecDeque<Trade> {
+        &self.trades
+    }
+
+
+    /// Candles of the interval, oldest first
+    pub fn candles(&self, interval: Duration) -> Option<&VecDeque<Candle>> {
+        self.series
+            .iter()
+            .find(|series| series.interval == interval)
+            .map(|series| &series.candles)
+    }
+
+
+    /// Statistics for the 24 hours before `now`
+    pub fn stats(&mut self, now: SystemTime) -> TradeStats {
+        self.expire_window(now);
+
+        TradeStats {
+            last_price: self.trades.back().map(|trade| trade.price),
+            vwap: if self.window_volume > 0.0 {
+                Some(self.window_notional / self.window_volume)
+            } else {
+                None
+            },
+            high_24h: self.window_highs.front().map(|&(_, price)| price),
+            low_24h: self.window_lows.front().map(|&(_, price)| price),
+            volume_24h: self.window_volume,
+        }
+    }
+
+
+    /// Drop trades older than the stats window before `now`
+    fn expire_window(&mut self, now: SystemTime) {
+        if let Some(since) = now.checked_sub(STATS_WINDOW) {
+            while let Some(&(ts, price, qty)) = self.window.front() {
+                if ts >= since {
+                    break;
+                }
+                self.window.pop_front();
+                self.window_volume -= qty;
+                self.window_notional -= price * qty;
+            }
+            while self.window_highs.front().is_some_and(|&(ts, _)| ts < since) {
+                self.window_highs.pop_front();
+            }
+            while self.window_lows.front().is_some_and(|&(ts, _)| ts < since) {
+                self.window_lows.pop_front();
+            }
+        }
+        if self.window.is_empty() {
+            self.window_volume = 0.0;
+            self.window_notional = 0.0;
+        }
+    }
+}
+
+
+impl Default for TradeHistory {
+    fn default() -> Self {
+        Self::new(&DEFAULT_CANDLE_INTERVALS)
+    }
+}
+
+
+/// Drop the oldest items over the limit
+fn drop_oldest<T>(items: &mut VecDeque<T>, len: usize) {
+    while items.len() > len {
+        items.pop_front();
+    }
+}
+
+
+fn bucket_start(ts: SystemTime, interval: Duration) -> SystemTime {
+    let since_epoch = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let interval = interval.as_nanos();
+    let start = since_epoch - since_epoch % interval;
+    UNIX_EPOCH + Duration::from_nanos(start as u64)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::domain::OrderSide;
+
+    fn trade(secs: u64, price: f64, qty: f64) -> Trade {
+        Trade {
+            taker_order_id: 2,
+            maker_order_id: 1,
+            taker_side: OrderSide::Bid,
+            price,
+            qty,
+            taker_fee: 0.0,
+            maker_fee: 0.0,
+            ts: UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn candles_and_stats() {
+        let minute = Duration::from_secs(60);
+        let mut history = TradeHistory::new(&[minute]);
+        history.record(trade(10, 10.0, 1.0));
+        history.record(trade(20, 12.0, 1.0));
+        history.record(trade(50, 9.0, 2.0));
+        history.record(trade(70, 11.0, 1.0));
+
+        let candles = history.candles(minute).unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0], Candle {
+            start: UNIX_EPOCH,
+            open: 10.0,
+            high: 12.0,
+            low: 9.0,
+            close: 9.0,
+            volume: 4.0,
+            trades: 3,
+        });
+        assert_eq!(candles[1].start, UNIX_EPOCH + minute);
+        assert!(history.candles(Duration::from_secs(1)).is_none());
+
+        let stats = history.stats(UNIX_EPOCH + minute * 2);
+        assert_eq!(stats, TradeStats {
+            last_price: Some(11.0),
+            vwap: Some(10.2),
+            high_24h: Some(12.0),
+            low_24h: Some(9.0),
+            volume_24h: 5.0,
+        });
+
+        // only the last trade is within the window
+        let stats = history.stats(UNIX_EPOCH + STATS_WINDOW + Duration::from_secs(60));
+        assert_eq!((stats.high_24h, stats.low_24h, stats.volume_24h), (Some(11.0), Some(11.0), 1.0));
+        assert_eq!((stats.last_price, stats.vwap), (Some(11.0), Some(11.0)));
+
+        let stats = history.stats(UNIX_EPOCH + STATS_WINDOW * 2);
+        assert_eq!((stats.vwap, stats.volume_24h), (None, 0.0));
+    }
+
+    #[test]
+    fn window_trimmed_on_record() {
+        let mut history = TradeHistory::new(&[]);
+        let hour = 60 * 60;
+        for n in 0..48 {
+            history.record(trade(n * hour, 10.0 + n as f64, 1.0));
+        }
+        // trades of the last 24 hours, stats were never requested
+        assert_eq!(history.window.len(), 25);
+        assert_eq!(history.window_lows.front(), Some(&(UNIX_EPOCH + Duration::from_secs(23 * hour), 33.0)));
+        assert_eq!(history.window_highs.len(), 1);
+    }
+
+    #[test]
+    fn late_trade_aggregated_at_last_time() {
+        let minute = Duration::from_secs(60);
+        let mut history = TradeHistory::new(&[minute]);
+        history.record(trade(70, 10.0, 1.0));
+        history.record(trade(10, 12.0, 1.0));
+        history.record(trade(80, 11.0, 2.0));
+
+        let candles = history.candles(minute).unwrap();
+        assert_eq!(candles.len(), 1);
+        assert_eq!((candles[0].start, candles[0].high, candles[0].trades), (UNIX_EPOCH + minute, 12.0, 3));
+        assert_eq!(history.trades()[1].ts, UNIX_EPOCH + Duration::from_secs(10));
+
+        // late trade leaves the window with the previous one
+        let stats = history.stats(UNIX_EPOCH + STATS_WINDOW + Duration::from_secs(75));
+        assert_eq!(stats, TradeStats {
+            last_price: Some(11.0),
+            vwap: Some(11.0),
+            high_24h: Some(11.0),
+            low_24h: Some(11.0),
+            volume_24h: 2.0,
+        });
+    }
+}
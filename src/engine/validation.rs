@@ -43,6 +43,7 @@ where
     pub fn validate(&self, request: &OrderRequest<Asset>) -> Result<(), &str> {
         match *request {
             OrderRequest::NewMarketOrder {
+                owner: _owner,
                 order_asset,
                 price_asset,
                 side: _side,
@@ -51,6 +52,7 @@ where
             } => self.validate_market(order_asset, price_asset, qty),
 
             OrderRequest::NewLimitOrder {
+                owner: _owner,
                 order_asset,
                 price_asset,
                 side: _side,
@@ -60,6 +62,7 @@ where
             } => self.validate_limit(order_asset, price_asset, price, qty),
 
             OrderRequest::AmendOrder {
+                owner: _owner,
                 id,
                 price,
                 side: _side,
@@ -67,7 +70,7 @@ where
                 ts: _ts,
             } => self.validate_amend(id, price, qty),
 
-            OrderRequest::CancelOrder { id, side: _side } => self.validate_cancel(id),
+            OrderRequest::CancelOrder { id, .. } => self.validate_cancel(id),
         }
     }
 
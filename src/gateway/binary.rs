@@ -0,0 +1,533 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::engine::domain::OrderType;
+use crate::engine::orderbook::{Failed, Orderbook, OrderProcessingResult, Success};
+use crate::engine::orders::{self, OrderRequest};
+use super::itch::{self, ItchMessage};
+use super::ouch::{CancelReason, Inbound, Outbound, RejectReason};
+use super::outbox::{Outbox, OUTBOX_CAPACITY};
+
+
+// keeps UDP packets within common MTU
+const MAX_PACKET_MESSAGES: usize = 32;
+
+
+struct Multicast {
+    socket: UdpSocket,
+    group: SocketAddr,
+}
+
+
+struct State<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    orderbook: Orderbook<Asset>,
+    sessions: HashMap<u64, Outbox>,
+    next_owner: u64,
+    // owners of open orders, to route executions of resting orders
+    order_owners: HashMap<u64, u64>,
+    next_match: u64,
+    feed_subscribers: Vec<Outbox>,
+    multicast: Option<Multicast>,
+    // sequence number of the next multicast message
+    feed_seq: u64,
+}
+
+
+/// Binary order entry and market data gateway in front of a single orderbook.
+///
+/// Order entry sessions use the OUCH-like protocol over TCP, every connection
+/// gets its own owner ID. Book changes are published with the ITCH-like protocol
+/// to TCP subscribers and optionally to UDP multicast group.
+/// Connections falling behind by `OUTBOX_CAPACITY` writes are dropped.
+pub struct BinaryGateway<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    state: Arc<Mutex<State<Asset>>>,
+}
+
+
+impl<Asset> Clone for BinaryGateway<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    fn clone(&self) -> Self {
+        BinaryGateway { state: self.state.clone() }
+    }
+}
+
+
+impl<Asset> BinaryGateway<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + 'static,
+{
+    pub fn new(orderbook: Orderbook<Asset>) -> Self {
+        BinaryGateway {
+            state: Arc::new(Mutex::new(State {
+                orderbook,
+                sessions: HashMap::new(),
+                next_owner: 1,
+                order_owners: HashMap::new(),
+                next_match: 1,
+                feed_subscribers: Vec::new(),
+                multicast: None,
+                feed_seq: 1,
+            })),
+        }
+    }
+
+
+    /// Publish market data to the multicast group as well
+    pub fn publish_multicast(&self, socket: UdpSocket, group: SocketAddr) {
+        self.lock().multicast = Some(Multicast { socket, group });
+    }
+
+
+    /// Access orderbook between requests
+    pub fn with_orderbook<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Orderbook<Asset>) -> R,
+    {
+        f(&mut self.lock().orderbook)
+    }
+
+
+    /// Accept order entry connections forever, serving each one on its own thread
+    pub fn serve_orders(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let gateway = self.clone();
+            thread::spawn(move || {
+                let _ = gateway.handle_connection(stream);
+            });
+        }
+        Ok(())
+    }
+
+
+    /// Accept market data subscribers forever, they receive messages
+    /// published after connection
+    pub fn serve_market_data(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            self.subscribe_market_data(stream?)?;
+        }
+        Ok(())
+    }
+
+
+    /// Send market data published from now on to the stream
+    pub fn subscribe_market_data(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let outbox = Outbox::spawn(stream, OUTBOX_CAPACITY)?;
+        self.lock().feed_subscribers.push(outbox);
+        Ok(())
+    }
+
+
+    /// Kill switch: cancel resting orders of the owner and block their new ones.
+    ///
+    /// Cancels are reported to the owner and published as order deletes.
+    pub fn kill(&self, owner: u64) {
+        self.lock().kill(owner);
+    }
+
+
+    /// Serve order entry session until disconnect
+    pub fn handle_connection(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let owner = {
+            let mut state = self.lock();
+            let owner = state.next_owner;
+            state.next_owner += 1;
+            state.sessions.insert(owner, Outbox::spawn(stream.try_clone()?, OUTBOX_CAPACITY)?);
+            owner
+        };
+
+        let result = self.read_requests(owner, &mut stream);
+        self.lock().sessions.remove(&owner);
+        let _ = stream.shutdown(Shutdown::Both);
+        result
+    }
+
+
+    fn read_requests(&self, owner: u64, stream: &mut TcpStream) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(4096);
+        let mut chunk = [0; 4096];
+        loop {
+            let len = stream.read(&mut chunk)?;
+            if len == 0 {
+                return Ok(());
+            }
+            buf.extend_from_slice(&chunk[..len]);
+
+            let mut pos = 0;
+            loop {
+                match Inbound::decode(&buf[pos..]) {
+                    Ok(Some((message, len))) => {
+                        self.lock().handle_message(owner, message);
+                        pos += len;
+                    }
+                    Ok(None) => break,
+                    // framing is lost, nothing else can be read
+                    Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+                }
+            }
+            buf.drain(..pos);
+        }
+    }
+
+
+    fn lock(&self) -> MutexGuard<'_, State<Asset>> {
+        self.state.lock().unwrap()
+    }
+}
+
+
+/// UDP socket sending multicast from the interface, loopback delivery enabled
+pub fn multicast_socket(interface: Ipv4Addr) -> io::Result<UdpSocket> {
+    // source address selects the outgoing interface
+    let socket = UdpSocket::bind((interface, 0))?;
+    socket.set_multicast_loop_v4(true)?;
+    Ok(socket)
+}
+
+
+impl<Asset> State<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    fn handle_message(&mut self, owner: u64, message: Inbound) {
+        let order_asset = self.orderbook.order_asset();
+        let price_asset = self.orderbook.price_asset();
+        let ts = SystemTime::now();
+
+        let (token, request) = match message {
+            Inbound::EnterOrder { token, side, order_type, price, qty } => {
+                let request = match order_type {
+                    OrderType::Limit => {
+                        orders::new_limit_order_request(owner, order_asset, price_asset, side, price, qty, ts)
+                    }
+                    OrderType::Market => {
+                        orders::new_market_order_request(owner, order_asset, price_asset, side, qty, ts)
+                    }
+                };
+                (token, request)
+            }
+            Inbound::CancelOrder { order_id, side } => {
+                (0, orders::limit_order_cancel_request(owner, order_id, side))
+            }
+            Inbound::ReplaceOrder { order_id, side, price, qty } => {
+                (0, orders::amend_order_request(owner, order_id, side, price, qty, ts))
+            }
+        };
+
+        let results = self.orderbook.process_order(request.clone());
+        let first_match = self.next_match;
+        let market_data = itch::book_messages(&request, &results, &mut self.next_match);
+
+        self.report(owner, token, &request, &results, first_match);
+        self.publish(&market_data);
+    }
+
+
+    fn kill(&mut self, owner: u64) {
+        let results = self.orderbook.kill(owner);
+        let mut market_data = Vec::with_capacity(results.len());
+        for event in &results {
+            if let Ok(Success::Cancelled { id, ts }) = *event {
+                self.order_owners.remove(&id);
+                self.send(owner, &Outbound::Canceled { order_id: id, reason: CancelReason::Halted, ts });
+                market_data.push(ItchMessage::OrderDelete { ts, order_id: id });
+            }
+        }
+        self.publish(&market_data);
+    }
+
+
+    /// Send order entry responses to owners of the affected orders
+    fn report(
+        &mut self,
+        owner: u64,
+        token: u32,
+        request: &OrderRequest<Asset>,
+        results: &OrderProcessingResult,
+        first_match: u64,
+    ) {
+        let mut fills = 0;
+        for event in results {
+            let (order_id, message) = match *event {
+                Ok(Success::Accepted { id, ts, .. }) => {
+                    self.order_owners.insert(id, owner);
+                    (id, Outbound::Accepted { token, order_id: id, ts })
+                }
+
+                Ok(Success::Filled { order_id, price, qty, fee, ts, .. }) |
+                Ok(Success::PartiallyFilled { order_id, price, qty, fee, ts, .. }) => {
+                    // fills go in pairs: taker, maker
+                    let match_number = first_match + fills / 2;
+                    fills += 1;
+                    (order_id, Outbound::Executed { order_id, price, qty, fee, match_number, ts })
+                }
+
+                Ok(Success::Cancelled { id, ts }) => {
+                    let reason = CancelReason::UserRequested;
+                    (id, Outbound::Canceled { order_id: id, reason, ts })
+                }
+
+                Ok(Success::Amended { id, price, qty, ts }) => {
+                    (id, Outbound::Replaced { order_id: id, price, qty, ts })
+                }
+
+                Err(Failed::NoMatch(id)) => {
+                    let reason = CancelReason::NoLiquidity;
+                    (id, Outbound::Canceled { order_id: id, reason, ts: SystemTime::now() })
+                }
+
+                Err(ref failed) => {
+                    let order_id = failed_order_id(failed, request).unwrap_or(0);
+                    // rejected new order is closed, cancel and replace do not touch the order
+                    if is_new_order(request) {
+                        self.order_owners.remove(&order_id);
+                    }
+                    let reason = RejectReason::from(failed);
+                    self.send(owner, &Outbound::Rejected { token, order_id, reason });
+                    continue;
+                }
+            };
+
+            let recipient = match self.order_owners.get(&order_id) {
+                Some(&recipient) => recipient,
+                None => continue,
+            };
+            let order_closed = matches!(
+                *event,
+                Ok(Success::Filled { .. }) | Ok(Success::Cancelled { .. }) | Err(Failed::NoMatch(_))
+            );
+            if order_closed {
+                self.order_owners.remove(&order_id);
+            }
+            self.send(recipient, &message);
+        }
+    }
+
+
+    fn send(&mut self, owner: u64, message: &Outbound) {
+        let mut buf = Vec::with_capacity(64);
+        message.encode(&mut buf);
+        let failed = match self.sessions.get(&owner) {
+            Some(outbox) => !outbox.push(buf.into()),
+            None => false,
+        };
+        if failed {
+            self.sessions.remove(&owner);
+        }
+    }
+
+
+    fn publish(&mut self, messages: &[ItchMessage]) {
+        if messages.is_empty() {
+            return;
+        }
+
+        if !self.feed_subscribers.is_empty() {
+            let mut buf = Vec::with_capacity(messages.len() * 34);
+            for message in messages {
+                message.encode(&mut buf);
+            }
+            // subscribers with full outbox or gone are dropped
+            let buf: Arc<[u8]> = buf.into();
+            self.feed_subscribers.retain(|outbox| outbox.push(buf.clone()));
+        }
+
+        if let Some(ref multicast) = self.multicast {
+            for packet in messages.chunks(MAX_PACKET_MESSAGES) {
+                let datagram = itch::encode_packet(self.feed_seq, packet);
+                let _ = multicast.socket.send_to(&datagram, multicast.group);
+                self.feed_seq += packet.len() as u64;
+            }
+        }
+    }
+}
+
+
+fn is_new_order<Asset>(request: &OrderRequest<Asset>) -> bool
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    matches!(*request, OrderRequest::NewMarketOrder { .. } | OrderRequest::NewLimitOrder { .. })
+}
+
+
+/// ID of the order failure refers to
+fn failed_order_id<Asset>(failed: &Failed, request: &OrderRequest<Asset>) -> Option<u64>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    match *failed {
+        Failed::DuplicateOrderID(id) |
+        Failed::NoMatch(id) |
+        Failed::OrderNotFound(id) => Some(id),
+        // failed before order ID was assigned
+        Failed::InsufficientFunds(_) |
+        Failed::MaxQtyExceeded(_) |
+        Failed::MaxNotionalExceeded(_) |
+        Failed::MaxOpenOrdersExceeded(_) |
+        Failed::PositionLimitExceeded(_) |
+        Failed::PriceDeviationExceeded(_) |
+        Failed::ValidationFailed(_) |
+        Failed::Throttled(_) |
+        Failed::OwnerKilled(_) |
+        Failed::TradingHalted => match *request {
+            OrderRequest::AmendOrder { id, .. } | OrderRequest::CancelOrder { id, .. } => Some(id),
+            _ => None,
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::domain::OrderSide;
+    use std::io::Write;
+    use std::net::SocketAddrV4;
+    use std::time::Duration;
+    use crate::engine::orderbook::test::Asset;
+
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn connect(addr: SocketAddr) -> Self {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            Client { stream }
+        }
+
+        fn send(&mut self, message: Inbound) {
+            let mut buf = Vec::new();
+            message.encode(&mut buf);
+            self.stream.write_all(&buf).unwrap();
+        }
+
+        fn recv(&mut self) -> Outbound {
+            let mut buf = vec![0; 1];
+            self.stream.read_exact(&mut buf).unwrap();
+            buf.resize(Outbound::len(buf[0]).unwrap(), 0);
+            self.stream.read_exact(&mut buf[1..]).unwrap();
+            Outbound::decode(&buf).unwrap().unwrap().0
+        }
+    }
+
+    fn read_feed(stream: &mut TcpStream) -> ItchMessage {
+        let mut buf = vec![0; 1];
+        stream.read_exact(&mut buf).unwrap();
+        buf.resize(ItchMessage::len(buf[0]).unwrap(), 0);
+        stream.read_exact(&mut buf[1..]).unwrap();
+        ItchMessage::decode(&buf).unwrap().unwrap().0
+    }
+
+    fn limit(token: u32, side: OrderSide, price: f64, qty: f64) -> Inbound {
+        Inbound::EnterOrder { token, side, order_type: OrderType::Limit, price, qty }
+    }
+
+    #[test]
+    fn order_entry_and_market_data() {
+        let gateway = BinaryGateway::new(Orderbook::new(Asset::BTC, Asset::USD));
+
+        let group = Ipv4Addr::new(239, 255, 42, 1);
+        let feed_udp = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        feed_udp.join_multicast_v4(&group, &Ipv4Addr::LOCALHOST).unwrap();
+        feed_udp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let group = SocketAddrV4::new(group, feed_udp.local_addr().unwrap().port());
+        gateway.publish_multicast(multicast_socket(Ipv4Addr::LOCALHOST).unwrap(), group.into());
+
+        let feed_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut feed_tcp = TcpStream::connect(feed_listener.local_addr().unwrap()).unwrap();
+        feed_tcp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        gateway.subscribe_market_data(feed_listener.accept().unwrap().0).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = gateway.clone();
+        thread::spawn(move || server.serve_orders(listener));
+        let mut maker = Client::connect(addr);
+        let mut taker = Client::connect(addr);
+
+        maker.send(limit(1, OrderSide::Ask, 10.5, 2.0));
+        assert!(matches!(maker.recv(), Outbound::Accepted { token: 1, order_id: 1, .. }));
+
+        // market order sweeps the book, the rest is cancelled
+        let order_type = OrderType::Market;
+        taker.send(Inbound::EnterOrder { token: 7, side: OrderSide::Bid, order_type, price: 0.0, qty: 3.0 });
+        assert!(matches!(taker.recv(), Outbound::Accepted { token: 7, order_id: 2, .. }));
+        assert!(matches!(taker.recv(), Outbound::Executed { order_id: 2, qty, match_number: 1, .. }
+            if qty == 2.0));
+        assert!(matches!(taker.recv(), Outbound::Canceled { order_id: 2, reason: CancelReason::NoLiquidity, .. }));
+        assert!(matches!(maker.recv(), Outbound::Executed { order_id: 1, price, match_number: 1, .. }
+            if price == 10.5));
+
+        maker.send(limit(2, OrderSide::Bid, 10.0, 1.0));
+        assert!(matches!(maker.recv(), Outbound::Accepted { token: 2, order_id: 3, .. }));
+        maker.send(Inbound::ReplaceOrder { order_id: 3, side: OrderSide::Bid, price: 10.1, qty: 1.5 });
+        assert!(matches!(maker.recv(), Outbound::Replaced { order_id: 3, .. }));
+        taker.send(Inbound::CancelOrder { order_id: 3, side: OrderSide::Bid });
+        let reason = RejectReason::UnknownOrder;
+        assert_eq!(taker.recv(), Outbound::Rejected { token: 0, order_id: 3, reason });
+        maker.send(Inbound::CancelOrder { order_id: 3, side: OrderSide::Bid });
+        let canceled = maker.recv();
+        assert!(matches!(canceled, Outbound::Canceled { order_id: 3, reason: CancelReason::UserRequested, .. }));
+
+        let feed: Vec<ItchMessage> = (0..6).map(|_| read_feed(&mut feed_tcp)).collect();
+        assert!(matches!(feed[0], ItchMessage::AddOrder { order_id: 1, side: OrderSide::Ask, qty, .. }
+            if qty == 2.0));
+        assert!(matches!(feed[1], ItchMessage::OrderExecuted { order_id: 1, match_number: 1, .. }));
+        assert!(matches!(feed[2], ItchMessage::Trade { match_number: 1, taker_side: OrderSide::Bid, .. }));
+        assert!(matches!(feed[3], ItchMessage::AddOrder { order_id: 3, side: OrderSide::Bid, .. }));
+        assert!(matches!(feed[4], ItchMessage::OrderReplace { order_id: 3, price, .. } if price == 10.1));
+        assert!(matches!(feed[5], ItchMessage::OrderDelete { order_id: 3, .. }));
+
+        // multicast carries the same messages with sequence numbers
+        let mut multicast = Vec::new();
+        let mut buf = [0; 2048];
+        while multicast.len() < feed.len() {
+            let len = feed_udp.recv(&mut buf).unwrap();
+            let (seq, messages) = itch::decode_packet(&buf[..len]).unwrap();
+            assert_eq!(seq, multicast.len() as u64 + 1);
+            multicast.extend(messages);
+        }
+        assert_eq!(multicast, feed);
+    }
+
+    #[test]
+    fn kill_switch_published() {
+        let gateway = BinaryGateway::new(Orderbook::new(Asset::BTC, Asset::USD));
+        let feed_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut feed_tcp = TcpStream::connect(feed_listener.local_addr().unwrap()).unwrap();
+        feed_tcp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        gateway.subscribe_market_data(feed_listener.accept().unwrap().0).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = gateway.clone();
+        thread::spawn(move || server.serve_orders(listener));
+        let mut maker = Client::connect(addr);
+
+        maker.send(limit(1, OrderSide::Ask, 10.5, 2.0));
+        assert!(matches!(maker.recv(), Outbound::Accepted { order_id: 1, .. }));
+        gateway.kill(1);
+        assert!(matches!(maker.recv(), Outbound::Canceled { order_id: 1, reason: CancelReason::Halted, .. }));
+
+        assert!(matches!(read_feed(&mut feed_tcp), ItchMessage::AddOrder { order_id: 1, .. }));
+        assert!(matches!(read_feed(&mut feed_tcp), ItchMessage::OrderDelete { order_id: 1, .. }));
+    }
+}
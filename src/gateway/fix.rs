@@ -0,0 +1,1358 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::io::{self, Read};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::engine::domain::{OrderSide, OrderType};
+use crate::engine::orderbook::{Failed, Orderbook, OrderProcessingResult, Success};
+use crate::engine::orders;
+use super::outbox::{Outbox, OUTBOX_CAPACITY};
+
+
+pub const BEGIN_STRING: &str = "FIX.4.4";
+const SOH: u8 = 0x01;
+const DEFAULT_HEARTBEAT_SECS: u64 = 30;
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Longer messages are rejected before being buffered whole
+pub const MAX_MESSAGE_LEN: usize = 64 * 1024;
+// received ahead of a sequence gap, waiting for resend
+const MAX_QUEUED_MESSAGES: usize = 1024;
+
+type Field = (u32, String);
+
+
+/// Tags used by the gateway
+pub mod tags {
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECKSUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const COMMISSION: u32 = 12;
+    pub const CUM_QTY: u32 = 14;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+}
+
+
+/// Values of MsgType(35) field
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+    pub const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+}
+
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FixError {
+    Garbled(&'static str),
+    UnsupportedVersion(String),
+    BadChecksum,
+    /// Message is longer than `MAX_MESSAGE_LEN`
+    TooLong,
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FixError::Garbled(reason) => write!(f, "garbled message: {}", reason),
+            FixError::UnsupportedVersion(ref version) => write!(f, "unsupported version {}", version),
+            FixError::BadChecksum => write!(f, "checksum mismatch"),
+            FixError::TooLong => write!(f, "message longer than {} bytes", MAX_MESSAGE_LEN),
+        }
+    }
+}
+
+
+/// FIX message as ordered list of fields, without BeginString,
+/// BodyLength and CheckSum which are added on encoding
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixMessage {
+    fields: Vec<Field>,
+}
+
+
+impl FixMessage {
+    pub fn new(msg_type: &str) -> Self {
+        FixMessage { fields: vec![(tags::MSG_TYPE, msg_type.to_string())] }
+    }
+
+
+    /// Append field, builder style
+    pub fn with<T: ToString>(mut self, tag: u32, value: T) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+
+    /// Replace field value or append the field
+    pub fn set<T: ToString>(&mut self, tag: u32, value: T) {
+        match self.fields.iter_mut().find(|field| field.0 == tag) {
+            Some(field) => field.1 = value.to_string(),
+            None => self.fields.push((tag, value.to_string())),
+        }
+    }
+
+
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.0 == tag)
+            .map(|field| field.1.as_str())
+    }
+
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tags::MSG_TYPE).unwrap_or("")
+    }
+
+
+    pub fn seq_num(&self) -> Option<u64> {
+        self.get(tags::MSG_SEQ_NUM).and_then(|seq| seq.parse().ok())
+    }
+
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+
+    /// Encode message with standard header and trailer, MsgType goes first
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(128);
+        let msg_type = self.fields.iter().filter(|field| field.0 == tags::MSG_TYPE);
+        let rest = self.fields.iter().filter(|field| field.0 != tags::MSG_TYPE);
+        for &(tag, ref value) in msg_type.chain(rest) {
+            body.extend_from_slice(format!("{}={}", tag, value).as_bytes());
+            body.push(SOH);
+        }
+
+        let mut buf = format!("8={}\x019={}\x01", BEGIN_STRING, body.len()).into_bytes();
+        buf.extend_from_slice(&body);
+        let checksum = checksum(&buf);
+        buf.extend_from_slice(format!("10={:03}\x01", checksum).as_bytes());
+        buf
+    }
+
+
+    /// Decode the first message in buffer.
+    ///
+    /// Returns message with number of consumed bytes,
+    /// or `None` if the message is not complete yet.
+    pub fn decode(buf: &[u8]) -> Result<Option<(FixMessage, usize)>, FixError> {
+        let decoded = Self::decode_first(buf)?;
+        // complete message always fits the limit
+        if decoded.is_none() && buf.len() >= MAX_MESSAGE_LEN {
+            return Err(FixError::TooLong);
+        }
+        Ok(decoded)
+    }
+
+
+    fn decode_first(buf: &[u8]) -> Result<Option<(FixMessage, usize)>, FixError> {
+        let (begin_string, pos) = match read_field(buf, 0)? {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        if begin_string.0 != tags::BEGIN_STRING {
+            return Err(FixError::Garbled("BeginString expected"));
+        }
+        if begin_string.1 != BEGIN_STRING {
+            return Err(FixError::UnsupportedVersion(begin_string.1));
+        }
+
+        let (body_length, body_start) = match read_field(buf, pos)? {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        if body_length.0 != tags::BODY_LENGTH {
+            return Err(FixError::Garbled("BodyLength expected"));
+        }
+        // BodyLength comes from the peer, check it before waiting for the body
+        let body_len = body_length.1
+            .parse::<usize>()
+            .map_err(|_| FixError::Garbled("bad BodyLength"))?;
+        let body_end = match body_start.checked_add(body_len) {
+            Some(body_end) if body_end <= MAX_MESSAGE_LEN => body_end,
+            _ => return Err(FixError::TooLong),
+        };
+        if buf.len() < body_end {
+            return Ok(None);
+        }
+
+        let (trailer, end) = match read_field(buf, body_end)? {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        if trailer.0 != tags::CHECKSUM {
+            return Err(FixError::Garbled("CheckSum expected"));
+        }
+        if end > MAX_MESSAGE_LEN {
+            return Err(FixError::TooLong);
+        }
+        if trailer.1.parse::<u8>().ok() != Some(checksum(&buf[..body_end])) {
+            return Err(FixError::BadChecksum);
+        }
+
+        let mut fields = Vec::new();
+        let mut pos = body_start;
+        while pos < body_end {
+            match read_field(&buf[..body_end], pos)? {
+                Some((field, next)) => {
+                    fields.push(field);
+                    pos = next;
+                }
+                None => return Err(FixError::Garbled("field crosses body end")),
+            }
+        }
+        if fields.first().map(|field| field.0) != Some(tags::MSG_TYPE) {
+            return Err(FixError::Garbled("MsgType expected"));
+        }
+
+        Ok(Some((FixMessage { fields }, end)))
+    }
+}
+
+
+/// Read "tag=value<SOH>" starting at `pos`, returns the field and position after it
+fn read_field(buf: &[u8], pos: usize) -> Result<Option<(Field, usize)>, FixError> {
+    let end = match buf[pos..].iter().position(|&b| b == SOH) {
+        Some(len) => pos + len,
+        None => return Ok(None),
+    };
+    let field = std::str::from_utf8(&buf[pos..end]).map_err(|_| FixError::Garbled("not UTF-8"))?;
+    let mut parts = field.splitn(2, '=');
+    let tag = parts
+        .next()
+        .and_then(|tag| tag.parse::<u32>().ok())
+        .ok_or(FixError::Garbled("bad tag"))?;
+    let value = parts.next().ok_or(FixError::Garbled("missing value"))?;
+    Ok(Some(((tag, value.to_string()), end + 1)))
+}
+
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+
+/// UTC time in FIX format: YYYYMMDD-HH:MM:SS.sss
+pub fn utc_timestamp(ts: SystemTime) -> String {
+    let since_epoch = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, day_secs) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // civil date from days since epoch
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        day_secs / 3_600,
+        day_secs % 3_600 / 60,
+        day_secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+
+/* Gateway */
+
+
+#[derive(Debug, Clone)]
+pub struct FixConfig {
+    /// CompID of the gateway, expected as TargetCompID from clients
+    pub comp_id: String,
+    /// Symbol(55) of the orderbook instrument
+    pub symbol: String,
+}
+
+
+/// Outgoing side of the client session
+struct SessionWriter {
+    outbox: Outbox,
+    sender_comp_id: String,
+    target_comp_id: String,
+    next_seq: u64,
+    last_sent: Instant,
+}
+
+impl SessionWriter {
+    fn send(&mut self, mut message: FixMessage) -> io::Result<()> {
+        let header = vec![
+            (tags::SENDER_COMP_ID, self.sender_comp_id.clone()),
+            (tags::TARGET_COMP_ID, self.target_comp_id.clone()),
+            (tags::MSG_SEQ_NUM, self.next_seq.to_string()),
+            (tags::SENDING_TIME, utc_timestamp(SystemTime::now())),
+        ];
+        message.fields.splice(1..1, header);
+
+        if !self.outbox.push(message.encode().into()) {
+            // too slow to keep up, dropped rather than blocking the gateway
+            self.outbox.abort();
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "session outbox full"));
+        }
+        self.next_seq += 1;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+}
+
+
+/// Owner, writer and heartbeat interval of logged on session
+type Session = (u64, Arc<Mutex<SessionWriter>>, Duration);
+
+
+/// Client's order, as seen by FIX
+#[derive(Debug, Clone)]
+struct OrderInfo {
+    owner: u64,
+    cl_ord_id: String,
+    side: OrderSide,
+    order_type: OrderType,
+    price: Option<f64>,
+    // total quantity, including filled part
+    qty: f64,
+    cum_qty: f64,
+    notional: f64,
+}
+
+
+/// Request being translated into FIX responses
+enum Origin {
+    New(OrderInfo),
+    Cancel { owner: u64, cl_ord_id: String, orig_cl_ord_id: String },
+    Replace { owner: u64, cl_ord_id: String, orig_cl_ord_id: String, qty: f64 },
+}
+
+
+struct State<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    orderbook: Orderbook<Asset>,
+    // owners are assigned to SenderCompIDs on the first logon
+    owners: HashMap<String, u64>,
+    sessions: HashMap<u64, Arc<Mutex<SessionWriter>>>,
+    orders: HashMap<u64, OrderInfo>,
+    cl_ord_ids: HashMap<(u64, String), u64>,
+    next_exec_id: u64,
+}
+
+
+/// FIX 4.4 order entry gateway in front of a single orderbook.
+///
+/// Every connection is served by its own thread, requests are processed
+/// one at a time and execution reports are routed to sessions of order owners.
+/// Sessions falling behind by `OUTBOX_CAPACITY` messages are dropped.
+pub struct FixGateway<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    state: Arc<Mutex<State<Asset>>>,
+    config: Arc<FixConfig>,
+}
+
+
+impl<Asset> Clone for FixGateway<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    fn clone(&self) -> Self {
+        FixGateway {
+            state: self.state.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+
+impl<Asset> FixGateway<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + 'static,
+{
+    pub fn new(orderbook: Orderbook<Asset>, config: FixConfig) -> Self {
+        FixGateway {
+            state: Arc::new(Mutex::new(State {
+                orderbook,
+                owners: HashMap::new(),
+                sessions: HashMap::new(),
+                orders: HashMap::new(),
+                cl_ord_ids: HashMap::new(),
+                next_exec_id: 1,
+            })),
+            config: Arc::new(config),
+        }
+    }
+
+
+    /// Owner ID assigned to the client with given CompID
+    pub fn owner(&self, comp_id: &str) -> Option<u64> {
+        self.lock().owners.get(comp_id).cloned()
+    }
+
+
+    /// Access orderbook between requests
+    pub fn with_orderbook<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Orderbook<Asset>) -> R,
+    {
+        f(&mut self.lock().orderbook)
+    }
+
+
+    /// Accept connections forever, serving each one on its own thread
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let gateway = self.clone();
+            thread::spawn(move || {
+                let _ = gateway.handle_connection(stream);
+            });
+        }
+        Ok(())
+    }
+
+
+    /// Run FIX session over the connection until logout or disconnect
+    pub fn handle_connection(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut reader = MessageReader::new(stream.try_clone()?);
+
+        // session starts with logon
+        let logon = loop {
+            match reader.next_message()? {
+                Some(message) => break message,
+                None if reader.idle_for() > Duration::from_secs(DEFAULT_HEARTBEAT_SECS) => {
+                    return stream.shutdown(Shutdown::Both);
+                }
+                None => continue,
+            }
+        };
+        let (owner, writer, heartbeat) = match self.logon(&logon, &stream)? {
+            Some(session) => session,
+            // writer closes the connection after logout is sent
+            None => return Ok(()),
+        };
+
+        let result = self.run_session(owner, &writer, &mut reader, heartbeat, logon.seq_num());
+
+        let mut state = self.lock();
+        if state.sessions.get(&owner).is_some_and(|session| Arc::ptr_eq(session, &writer)) {
+            state.sessions.remove(&owner);
+        }
+        drop(state);
+        writer.lock().unwrap().outbox.close();
+        result
+    }
+
+
+    /* Session level */
+
+
+    fn logon(
+        &self,
+        logon: &FixMessage,
+        stream: &TcpStream,
+    ) -> io::Result<Option<Session>> {
+        let sender = logon.get(tags::SENDER_COMP_ID).unwrap_or("").to_string();
+        let mut writer = SessionWriter {
+            outbox: Outbox::spawn(stream.try_clone()?, OUTBOX_CAPACITY)?,
+            sender_comp_id: self.config.comp_id.clone(),
+            target_comp_id: sender.clone(),
+            next_seq: 1,
+            last_sent: Instant::now(),
+        };
+
+        let reason = if logon.msg_type() != msg_type::LOGON {
+            Some("Logon expected")
+        } else if sender.is_empty() {
+            Some("SenderCompID required")
+        } else if logon.get(tags::TARGET_COMP_ID) != Some(self.config.comp_id.as_str()) {
+            Some("unknown TargetCompID")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            writer.send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, reason))?;
+            writer.outbox.close();
+            return Ok(None);
+        }
+
+        let heartbeat = logon
+            .get(tags::HEART_BT_INT)
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .filter(|&secs| secs > 0)
+            .unwrap_or(DEFAULT_HEARTBEAT_SECS);
+
+        let mut state = self.lock();
+        let next_owner = state.owners.len() as u64 + 1;
+        let owner = *state.owners.entry(sender).or_insert(next_owner);
+        if state.sessions.contains_key(&owner) {
+            writer.send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, "session already active"))?;
+            writer.outbox.close();
+            return Ok(None);
+        }
+
+        writer.send(
+            FixMessage::new(msg_type::LOGON)
+                .with(tags::ENCRYPT_METHOD, 0)
+                .with(tags::HEART_BT_INT, heartbeat),
+        )?;
+        let writer = Arc::new(Mutex::new(writer));
+        state.sessions.insert(owner, writer.clone());
+        Ok(Some((owner, writer, Duration::from_secs(heartbeat))))
+    }
+
+
+    fn run_session(
+        &self,
+        owner: u64,
+        writer: &Arc<Mutex<SessionWriter>>,
+        reader: &mut MessageReader,
+        heartbeat: Duration,
+        logon_seq: Option<u64>,
+    ) -> io::Result<()> {
+        let send = |message: FixMessage| writer.lock().unwrap().send(message);
+        let mut expected_seq = logon_seq.unwrap_or(1) + 1;
+        // messages received ahead of a sequence gap, by MsgSeqNum
+        let mut queued: BTreeMap<u64, FixMessage> = BTreeMap::new();
+        let mut test_request_sent = false;
+
+        loop {
+            let message = match reader.next_message() {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    // keep the session alive
+                    if writer.lock().unwrap().last_sent.elapsed() >= heartbeat {
+                        send(FixMessage::new(msg_type::HEARTBEAT))?;
+                    }
+                    let idle = reader.idle_for();
+                    if idle > heartbeat * 2 {
+                        send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, "heartbeat timeout"))?;
+                        return Ok(());
+                    }
+                    if idle > heartbeat + heartbeat / 5 && !test_request_sent {
+                        send(FixMessage::new(msg_type::TEST_REQUEST).with(tags::TEST_REQ_ID, "ALIVE"))?;
+                        test_request_sent = true;
+                    }
+                    continue;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {
+                    send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, err.to_string()))?;
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            };
+            test_request_sent = false;
+
+            // sequence numbers
+            let seq = match message.seq_num() {
+                Some(seq) => seq,
+                None => {
+                    send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, "MsgSeqNum missing"))?;
+                    return Ok(());
+                }
+            };
+            let poss_dup = message.get(tags::POSS_DUP_FLAG) == Some("Y");
+            // reset mode of SequenceReset ignores MsgSeqNum
+            let reset = message.msg_type() == msg_type::SEQUENCE_RESET &&
+                message.get(tags::GAP_FILL_FLAG) != Some("Y");
+            if seq < expected_seq && !reset {
+                if poss_dup {
+                    continue;
+                }
+                let text = format!("MsgSeqNum too low, expected {}", expected_seq);
+                send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, text))?;
+                return Ok(());
+            }
+            if seq > expected_seq && !reset {
+                // processed in order once the gap is filled by resent messages
+                if queued.is_empty() {
+                    send(
+                        FixMessage::new(msg_type::RESEND_REQUEST)
+                            .with(tags::BEGIN_SEQ_NO, expected_seq)
+                            .with(tags::END_SEQ_NO, 0),
+                    )?;
+                }
+                if queued.len() >= MAX_QUEUED_MESSAGES {
+                    send(FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, "sequence gap not filled"))?;
+                    return Ok(());
+                }
+                queued.insert(seq, message);
+                continue;
+            }
+
+            let mut next = Some((seq, message));
+            while let Some((seq, message)) = next {
+                if !self.process_message(owner, writer, seq, &message, &mut expected_seq)? {
+                    return Ok(());
+                }
+                // sequence reset could skip queued messages
+                queued = queued.split_off(&expected_seq);
+                next = queued.remove(&expected_seq).map(|message| (expected_seq, message));
+            }
+        }
+    }
+
+
+    /// Process in-sequence message, returns `false` if the session is over
+    fn process_message(
+        &self,
+        owner: u64,
+        writer: &Arc<Mutex<SessionWriter>>,
+        seq: u64,
+        message: &FixMessage,
+        expected_seq: &mut u64,
+    ) -> io::Result<bool> {
+        let send = |message: FixMessage| writer.lock().unwrap().send(message);
+        *expected_seq = seq + 1;
+
+        match message.msg_type() {
+            msg_type::HEARTBEAT => (),
+            msg_type::TEST_REQUEST => {
+                let mut reply = FixMessage::new(msg_type::HEARTBEAT);
+                if let Some(id) = message.get(tags::TEST_REQ_ID) {
+                    reply.set(tags::TEST_REQ_ID, id);
+                }
+                send(reply)?;
+            }
+            msg_type::RESEND_REQUEST => {
+                // messages are not stored, skip the whole gap
+                let mut writer = writer.lock().unwrap();
+                let next_seq = writer.next_seq + 1;
+                writer.send(
+                    FixMessage::new(msg_type::SEQUENCE_RESET)
+                        .with(tags::GAP_FILL_FLAG, "N")
+                        .with(tags::NEW_SEQ_NO, next_seq),
+                )?;
+            }
+            msg_type::SEQUENCE_RESET => {
+                if let Some(new_seq) = message.get(tags::NEW_SEQ_NO).and_then(|s| s.parse().ok()) {
+                    *expected_seq = new_seq;
+                }
+            }
+            msg_type::LOGOUT => {
+                send(FixMessage::new(msg_type::LOGOUT))?;
+                return Ok(false);
+            }
+            msg_type::NEW_ORDER_SINGLE => self.new_order(owner, message),
+            msg_type::ORDER_CANCEL_REQUEST => self.cancel_order(owner, message, false),
+            msg_type::ORDER_CANCEL_REPLACE_REQUEST => self.cancel_order(owner, message, true),
+            _ => {
+                send(
+                    FixMessage::new(msg_type::REJECT)
+                        .with(tags::REF_SEQ_NUM, seq)
+                        .with(tags::TEXT, "unsupported MsgType"),
+                )?;
+            }
+        }
+        Ok(true)
+    }
+
+
+    /* Application level */
+
+
+    fn new_order(&self, owner: u64, message: &FixMessage) {
+        let mut state = self.lock();
+        let cl_ord_id = message.get(tags::CL_ORD_ID).unwrap_or("").to_string();
+
+        let parsed = parse_new_order(message, &self.config.symbol);
+        let mut info = OrderInfo {
+            owner,
+            cl_ord_id: cl_ord_id.clone(),
+            side: parsed.as_ref().map(|order| order.0).unwrap_or(OrderSide::Bid),
+            order_type: parsed.as_ref().map(|order| order.1).unwrap_or(OrderType::Limit),
+            price: parsed.as_ref().ok().and_then(|order| order.2),
+            qty: parsed.as_ref().map(|order| order.3).unwrap_or(0.0),
+            cum_qty: 0.0,
+            notional: 0.0,
+        };
+
+        let rejection = match parsed {
+            Err(reason) => Some(reason.to_string()),
+            Ok(_) if cl_ord_id.is_empty() => Some("ClOrdID required".to_string()),
+            Ok(_) if state.cl_ord_ids.contains_key(&(owner, cl_ord_id.clone())) => {
+                Some("duplicate ClOrdID".to_string())
+            }
+            Ok(_) => None,
+        };
+        if let Some(reason) = rejection {
+            let report = state.execution_report(None, &info, "8", "8").with(tags::TEXT, reason);
+            state.send(owner, report);
+            return;
+        }
+
+        let order_asset = state.orderbook.order_asset();
+        let price_asset = state.orderbook.price_asset();
+        let ts = SystemTime::now();
+        let request = match info.price {
+            Some(price) => orders::new_limit_order_request(
+                owner,
+                order_asset,
+                price_asset,
+                info.side,
+                price,
+                info.qty,
+                ts,
+            ),
+            None => orders::new_market_order_request(
+                owner,
+                order_asset,
+                price_asset,
+                info.side,
+                info.qty,
+                ts,
+            ),
+        };
+
+        let results = state.orderbook.process_order(request);
+        info.cl_ord_id = cl_ord_id;
+        state.report(&results, Origin::New(info));
+    }
+
+
+    fn cancel_order(&self, owner: u64, message: &FixMessage, replace: bool) {
+        let mut state = self.lock();
+        let cl_ord_id = message.get(tags::CL_ORD_ID).unwrap_or("").to_string();
+        let orig_cl_ord_id = message.get(tags::ORIG_CL_ORD_ID).unwrap_or("").to_string();
+
+        let order = state.cl_ord_ids
+            .get(&(owner, orig_cl_ord_id.clone()))
+            .and_then(|&id| state.orders.get(&id).map(|info| (id, info.clone())));
+        let (order_id, info) = match order {
+            Some(order) => order,
+            None => {
+                let text = "unknown order";
+                let reject = cancel_reject(None, &cl_ord_id, &orig_cl_ord_id, replace, text);
+                state.send(owner, reject);
+                return;
+            }
+        };
+
+        if !replace {
+            let request = orders::limit_order_cancel_request(owner, order_id, info.side);
+            let results = state.orderbook.process_order(request);
+            state.report(&results, Origin::Cancel { owner, cl_ord_id, orig_cl_ord_id });
+            return;
+        }
+
+        let qty = message.get(tags::ORDER_QTY).and_then(|qty| qty.parse::<f64>().ok());
+        let price = message
+            .get(tags::PRICE)
+            .and_then(|price| price.parse::<f64>().ok())
+            .or(info.price);
+        let (qty, price) = match (qty, price) {
+            (Some(qty), Some(price)) if qty > info.cum_qty => (qty, price),
+            _ => {
+                let text = "bad OrderQty or Price";
+                let reject = cancel_reject(Some(order_id), &cl_ord_id, &orig_cl_ord_id, true, text);
+                state.send(owner, reject);
+                return;
+            }
+        };
+
+        // engine keeps only the open quantity
+        let open_qty = qty - info.cum_qty;
+        let request = orders::amend_order_request(owner, order_id, info.side, price, open_qty, SystemTime::now());
+        let results = state.orderbook.process_order(request);
+        state.report(&results, Origin::Replace { owner, cl_ord_id, orig_cl_ord_id, qty });
+    }
+
+
+    fn lock(&self) -> MutexGuard<'_, State<Asset>> {
+        self.state.lock().unwrap()
+    }
+}
+
+
+impl<Asset> State<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Turn engine events into execution reports for all involved owners
+    fn report(&mut self, results: &OrderProcessingResult, origin: Origin) {
+        for event in results {
+            match *event {
+                Ok(Success::Accepted { id, .. }) => {
+                    if let Origin::New(ref info) = origin {
+                        self.cl_ord_ids.insert((info.owner, info.cl_ord_id.clone()), id);
+                        self.orders.insert(id, info.clone());
+                        let report = self.execution_report(Some(id), info, "0", "0");
+                        self.send(info.owner, report);
+                    }
+                }
+
+                Ok(Success::Filled { order_id, price, qty, fee, .. }) |
+                Ok(Success::PartiallyFilled { order_id, price, qty, fee, .. }) => {
+                    let mut info = match self.orders.get_mut(&order_id) {
+                        Some(info) => {
+                            info.cum_qty += qty;
+                            info.notional += price * qty;
+                            info.clone()
+                        }
+                        None => continue,
+                    };
+                    let filled = matches!(*event, Ok(Success::Filled { .. }));
+                    if filled {
+                        // order is done, rounding leftovers are not reported
+                        info.cum_qty = info.qty;
+                        self.remove_order(order_id);
+                    }
+                    let status = if filled { "2" } else { "1" };
+                    let report = self.execution_report(Some(order_id), &info, "F", status)
+                        .with(tags::LAST_PX, price)
+                        .with(tags::LAST_QTY, qty)
+                        .with(tags::COMMISSION, fee);
+                    self.send(info.owner, report);
+                }
+
+                Ok(Success::Cancelled { id, .. }) => {
+                    if let Origin::Cancel { ref cl_ord_id, ref orig_cl_ord_id, .. } = origin {
+                        if let Some(mut info) = self.remove_order(id) {
+                            info.cl_ord_id = cl_ord_id.clone();
+                            let report = self.execution_report(Some(id), &info, "4", "4")
+                                .with(tags::ORIG_CL_ORD_ID, orig_cl_ord_id);
+                            self.send(info.owner, report);
+                        }
+                    }
+                }
+
+                Ok(Success::Amended { id, price, .. }) => {
+                    if let Origin::Replace { owner, ref cl_ord_id, ref orig_cl_ord_id, qty } = origin {
+                        self.cl_ord_ids.remove(&(owner, orig_cl_ord_id.clone()));
+                        self.cl_ord_ids.insert((owner, cl_ord_id.clone()), id);
+                        let info = match self.orders.get_mut(&id) {
+                            Some(info) => {
+                                info.cl_ord_id = cl_ord_id.clone();
+                                info.price = Some(price);
+                                info.qty = qty;
+                                info.clone()
+                            }
+                            None => continue,
+                        };
+                        let status = if info.cum_qty > 0.0 { "1" } else { "0" };
+                        let report = self.execution_report(Some(id), &info, "5", status)
+                            .with(tags::ORIG_CL_ORD_ID, orig_cl_ord_id);
+                        self.send(owner, report);
+                    }
+                }
+
+                Err(ref failed) => self.report_failure(failed, &origin),
+            }
+        }
+    }
+
+
+    fn report_failure(&mut self, failed: &Failed, origin: &Origin) {
+        let text = format!("{:?}", failed);
+        match *origin {
+            Origin::New(ref info) => {
+                let order_id = failed_order_id(failed);
+                let open = order_id.and_then(|id| self.remove_order(id));
+                let info = open.unwrap_or_else(|| info.clone());
+                // unmatched rest of the market order is cancelled
+                let (exec_type, status) = match *failed {
+                    Failed::NoMatch(_) => ("4", "4"),
+                    _ => ("8", "8"),
+                };
+                let report = self.execution_report(order_id, &info, exec_type, status)
+                    .with(tags::TEXT, text);
+                self.send(info.owner, report);
+            }
+            Origin::Cancel { owner, ref cl_ord_id, ref orig_cl_ord_id } |
+            Origin::Replace { owner, ref cl_ord_id, ref orig_cl_ord_id, .. } => {
+                let replace = matches!(*origin, Origin::Replace { .. });
+                let order_id = self.cl_ord_ids.get(&(owner, orig_cl_ord_id.clone())).cloned();
+                let reject = cancel_reject(order_id, cl_ord_id, orig_cl_ord_id, replace, &text);
+                self.send(owner, reject);
+            }
+        }
+    }
+
+
+    fn execution_report(
+        &mut self,
+        order_id: Option<u64>,
+        info: &OrderInfo,
+        exec_type: &str,
+        status: &str,
+    ) -> FixMessage {
+        let exec_id = self.next_exec_id;
+        self.next_exec_id += 1;
+
+        let mut report = FixMessage::new(msg_type::EXECUTION_REPORT)
+            .with(tags::ORDER_ID, order_id.map_or("NONE".to_string(), |id| id.to_string()))
+            .with(tags::CL_ORD_ID, &info.cl_ord_id)
+            .with(tags::EXEC_ID, exec_id)
+            .with(tags::EXEC_TYPE, exec_type)
+            .with(tags::ORD_STATUS, status)
+            .with(tags::SIDE, side_code(info.side))
+            .with(tags::ORD_TYPE, if info.order_type == OrderType::Market { "1" } else { "2" })
+            .with(tags::ORDER_QTY, info.qty);
+        if let Some(price) = info.price {
+            report.set(tags::PRICE, price);
+        }
+
+        let open = status == "0" || status == "1";
+        let leaves_qty = if open { info.qty - info.cum_qty } else { 0.0 };
+        let avg_px = if info.cum_qty > 0.0 { info.notional / info.cum_qty } else { 0.0 };
+        report
+            .with(tags::LEAVES_QTY, leaves_qty)
+            .with(tags::CUM_QTY, info.cum_qty)
+            .with(tags::AVG_PX, avg_px)
+            .with(tags::TRANSACT_TIME, utc_timestamp(SystemTime::now()))
+    }
+
+
+    fn remove_order(&mut self, order_id: u64) -> Option<OrderInfo> {
+        let info = self.orders.remove(&order_id)?;
+        self.cl_ord_ids.remove(&(info.owner, info.cl_ord_id.clone()));
+        Some(info)
+    }
+
+
+    /// Send message to the owner's session, if one is connected
+    fn send(&mut self, owner: u64, message: FixMessage) {
+        let failed = match self.sessions.get(&owner) {
+            Some(session) => session.lock().unwrap().send(message).is_err(),
+            None => false,
+        };
+        if failed {
+            self.sessions.remove(&owner);
+        }
+    }
+}
+
+
+/// Side, order type, price and quantity of NewOrderSingle
+fn parse_new_order(
+    message: &FixMessage,
+    symbol: &str,
+) -> Result<(OrderSide, OrderType, Option<f64>, f64), &'static str> {
+    if message.get(tags::SYMBOL) != Some(symbol) {
+        return Err("unknown Symbol");
+    }
+    let side = match message.get(tags::SIDE) {
+        Some("1") => OrderSide::Bid,
+        Some("2") => OrderSide::Ask,
+        _ => return Err("unsupported Side"),
+    };
+    let qty = message
+        .get(tags::ORDER_QTY)
+        .and_then(|qty| qty.parse::<f64>().ok())
+        .ok_or("bad OrderQty")?;
+    match message.get(tags::ORD_TYPE) {
+        Some("1") => Ok((side, OrderType::Market, None, qty)),
+        Some("2") => {
+            let price = message
+                .get(tags::PRICE)
+                .and_then(|price| price.parse::<f64>().ok())
+                .ok_or("bad Price")?;
+            Ok((side, OrderType::Limit, Some(price), qty))
+        }
+        _ => Err("unsupported OrdType"),
+    }
+}
+
+
+fn cancel_reject(
+    order_id: Option<u64>,
+    cl_ord_id: &str,
+    orig_cl_ord_id: &str,
+    replace: bool,
+    text: &str,
+) -> FixMessage {
+    FixMessage::new(msg_type::ORDER_CANCEL_REJECT)
+        .with(tags::ORDER_ID, order_id.map_or("NONE".to_string(), |id| id.to_string()))
+        .with(tags::CL_ORD_ID, cl_ord_id)
+        .with(tags::ORIG_CL_ORD_ID, orig_cl_ord_id)
+        .with(tags::ORD_STATUS, if order_id.is_some() { "0" } else { "8" })
+        .with(tags::CXL_REJ_RESPONSE_TO, if replace { "2" } else { "1" })
+        .with(tags::TEXT, text)
+}
+
+
+fn side_code(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Bid => "1",
+        OrderSide::Ask => "2",
+    }
+}
+
+
+/// Order the failure refers to, if it was accepted by the engine
+fn failed_order_id(failed: &Failed) -> Option<u64> {
+    match *failed {
+        Failed::DuplicateOrderID(id) |
+        Failed::NoMatch(id) |
+        Failed::OrderNotFound(id) => Some(id),
+        // new orders failing admission are rejected before getting an ID
+        Failed::InsufficientFunds(_) |
+        Failed::MaxQtyExceeded(_) |
+        Failed::MaxNotionalExceeded(_) |
+        Failed::MaxOpenOrdersExceeded(_) |
+        Failed::PositionLimitExceeded(_) |
+        Failed::PriceDeviationExceeded(_) |
+        Failed::ValidationFailed(_) |
+        Failed::Throttled(_) |
+        Failed::OwnerKilled(_) |
+        Failed::TradingHalted => None,
+    }
+}
+
+
+/// Incoming side of the session, splits the stream into messages
+pub struct MessageReader {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    last_received: Instant,
+}
+
+impl MessageReader {
+    /// Reader expects the stream to have read timeout set
+    pub fn new(stream: TcpStream) -> Self {
+        MessageReader {
+            stream,
+            buf: Vec::with_capacity(4096),
+            last_received: Instant::now(),
+        }
+    }
+
+
+    /// Time since the last received message
+    pub fn idle_for(&self) -> Duration {
+        self.last_received.elapsed()
+    }
+
+
+    /// Next message, or `None` if nothing arrived before read timeout.
+    ///
+    /// Buffers at most `MAX_MESSAGE_LEN` bytes of incomplete message.
+    pub fn next_message(&mut self) -> io::Result<Option<FixMessage>> {
+        loop {
+            match FixMessage::decode(&self.buf) {
+                Ok(Some((message, len))) => {
+                    self.buf.drain(..len);
+                    self.last_received = Instant::now();
+                    return Ok(Some(message));
+                }
+                Ok(None) => (),
+                Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+            }
+
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Ok(None)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::engine::orderbook::test::Asset;
+
+    #[test]
+    fn encode_and_decode() {
+        let message = FixMessage::new(msg_type::HEARTBEAT)
+            .with(tags::SENDER_COMP_ID, "CLIENT")
+            .with(tags::MSG_SEQ_NUM, 1);
+        let mut bytes = message.encode();
+        assert!(bytes.starts_with(b"8=FIX.4.4\x019=20\x0135=0\x01"));
+
+        assert_eq!(FixMessage::decode(&bytes[..bytes.len() - 1]), Ok(None));
+        let extra = bytes.len();
+        bytes.extend_from_slice(b"8=FIX");
+        assert_eq!(FixMessage::decode(&bytes), Ok(Some((message, extra))));
+
+        bytes[18] = b'1';
+        assert_eq!(FixMessage::decode(&bytes), Err(FixError::BadChecksum));
+        assert_eq!(utc_timestamp(UNIX_EPOCH + Duration::from_millis(951_827_696_007)), "20000229-12:34:56.007");
+    }
+
+    #[test]
+    fn decode_length_limits() {
+        // peer-controlled BodyLength must not overflow
+        let huge = format!("8=FIX.4.4\x019={}\x0135=0\x01", usize::MAX);
+        assert_eq!(FixMessage::decode(huge.as_bytes()), Err(FixError::TooLong));
+        let overflow = "8=FIX.4.4\x019=99999999999999999999999\x0135=0\x01";
+        assert_eq!(FixMessage::decode(overflow.as_bytes()), Err(FixError::Garbled("bad BodyLength")));
+
+        let long = format!("8=FIX.4.4\x019={}\x0135=0\x01", MAX_MESSAGE_LEN);
+        assert_eq!(FixMessage::decode(long.as_bytes()), Err(FixError::TooLong));
+
+        // unterminated field is not buffered forever
+        let mut garbage = b"8=FIX.4.4\x019=".to_vec();
+        garbage.resize(MAX_MESSAGE_LEN, b'1');
+        assert_eq!(FixMessage::decode(&garbage[..MAX_MESSAGE_LEN - 1]), Ok(None));
+        assert_eq!(FixMessage::decode(&garbage), Err(FixError::TooLong));
+    }
+
+
+    /// Minimal FIX client for loopback tests
+    struct Client {
+        comp_id: String,
+        writer: TcpStream,
+        reader: MessageReader,
+        next_seq: u64,
+    }
+
+    impl Client {
+        fn logon(addr: ::std::net::SocketAddr, comp_id: &str) -> Self {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+            let mut client = Client {
+                comp_id: comp_id.to_string(),
+                writer: stream.try_clone().unwrap(),
+                reader: MessageReader::new(stream),
+                next_seq: 1,
+            };
+            client.send(FixMessage::new(msg_type::LOGON).with(tags::ENCRYPT_METHOD, 0).with(tags::HEART_BT_INT, 30));
+            assert_eq!(client.recv().msg_type(), msg_type::LOGON);
+            client
+        }
+
+        fn send(&mut self, message: FixMessage) {
+            self.send_seq(message, self.next_seq);
+            self.next_seq += 1;
+        }
+
+        fn send_seq(&mut self, message: FixMessage, seq: u64) {
+            let message = message
+                .with(tags::SENDER_COMP_ID, &self.comp_id)
+                .with(tags::TARGET_COMP_ID, "BOOK")
+                .with(tags::MSG_SEQ_NUM, seq)
+                .with(tags::SENDING_TIME, utc_timestamp(SystemTime::now()));
+            self.writer.write_all(&message.encode()).unwrap();
+        }
+
+        fn recv(&mut self) -> FixMessage {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if let Some(message) = self.reader.next_message().unwrap() {
+                    return message;
+                }
+            }
+            panic!("no message received");
+        }
+
+        fn order(&mut self, cl_ord_id: &str, side: &str, qty: f64, price: Option<f64>) {
+            let mut message = FixMessage::new(msg_type::NEW_ORDER_SINGLE)
+                .with(tags::CL_ORD_ID, cl_ord_id)
+                .with(tags::SYMBOL, "BTC/USD")
+                .with(tags::SIDE, side)
+                .with(tags::ORDER_QTY, qty)
+                .with(tags::ORD_TYPE, if price.is_some() { "2" } else { "1" });
+            if let Some(price) = price {
+                message.set(tags::PRICE, price);
+            }
+            self.send(message);
+        }
+    }
+
+    fn fields(message: &FixMessage, tags: &[u32]) -> Vec<String> {
+        tags.iter().map(|&tag| message.get(tag).unwrap_or("").to_string()).collect()
+    }
+
+    #[test]
+    fn loopback_session() {
+        let gateway = FixGateway::new(Orderbook::new(Asset::BTC, Asset::USD), FixConfig {
+            comp_id: "BOOK".to_string(),
+            symbol: "BTC/USD".to_string(),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = gateway.clone();
+        thread::spawn(move || server.serve(listener));
+
+        let mut maker = Client::logon(addr, "MAKER");
+        let mut taker = Client::logon(addr, "TAKER");
+        assert_eq!((gateway.owner("MAKER"), gateway.owner("TAKER")), (Some(1), Some(2)));
+
+        let report_tags = [tags::ORDER_ID, tags::CL_ORD_ID, tags::EXEC_TYPE, tags::ORD_STATUS, tags::LEAVES_QTY, tags::CUM_QTY];
+
+        maker.order("m1", "2", 2.0, Some(10.5));
+        let report = maker.recv();
+        assert_eq!(report.msg_type(), msg_type::EXECUTION_REPORT);
+        assert_eq!(report.seq_num(), Some(2));
+        assert_eq!(fields(&report, &report_tags), ["1", "m1", "0", "0", "2", "0"]);
+
+        taker.order("t1", "1", 1.5, None);
+        assert_eq!(fields(&taker.recv(), &report_tags), ["2", "t1", "0", "0", "1.5", "0"]);
+        let fill = taker.recv();
+        assert_eq!(fields(&fill, &report_tags), ["2", "t1", "F", "2", "0", "1.5"]);
+        assert_eq!(fields(&fill, &[tags::LAST_PX, tags::AVG_PX]), ["10.5", "10.5"]);
+        assert_eq!(fields(&maker.recv(), &report_tags), ["1", "m1", "F", "1", "0.5", "1.5"]);
+
+        // replace total quantity, filled part included
+        maker.send(
+            FixMessage::new(msg_type::ORDER_CANCEL_REPLACE_REQUEST)
+                .with(tags::CL_ORD_ID, "m2")
+                .with(tags::ORIG_CL_ORD_ID, "m1")
+                .with(tags::SYMBOL, "BTC/USD")
+                .with(tags::SIDE, "2")
+                .with(tags::ORDER_QTY, 3)
+                .with(tags::ORD_TYPE, "2")
+                .with(tags::PRICE, 10.4),
+        );
+        assert_eq!(fields(&maker.recv(), &report_tags), ["1", "m2", "5", "1", "1.5", "1.5"]);
+        assert_eq!(gateway.with_orderbook(|book| book.depth(1)).asks[0].qty, 1.5);
+
+        maker.send(FixMessage::new(msg_type::TEST_REQUEST).with(tags::TEST_REQ_ID, "T1"));
+        let heartbeat = maker.recv();
+        assert_eq!((heartbeat.msg_type(), heartbeat.get(tags::TEST_REQ_ID)), (msg_type::HEARTBEAT, Some("T1")));
+
+        let cancel = |cl_ord_id: &str, orig: &str| {
+            FixMessage::new(msg_type::ORDER_CANCEL_REQUEST)
+                .with(tags::CL_ORD_ID, cl_ord_id)
+                .with(tags::ORIG_CL_ORD_ID, orig)
+                .with(tags::SYMBOL, "BTC/USD")
+                .with(tags::SIDE, "2")
+        };
+        maker.send(cancel("m3", "m2"));
+        assert_eq!(fields(&maker.recv(), &report_tags), ["1", "m3", "4", "4", "0", "1.5"]);
+        maker.send(cancel("m4", "m2"));
+        let reject = maker.recv();
+        assert_eq!(reject.msg_type(), msg_type::ORDER_CANCEL_REJECT);
+        assert_eq!(reject.get(tags::CXL_REJ_RESPONSE_TO), Some("1"));
+
+        // engine validation
+        taker.order("t2", "1", -1.0, Some(10.0));
+        let report = taker.recv();
+        assert_eq!(fields(&report, &[tags::ORDER_ID, tags::EXEC_TYPE]), ["NONE", "8"]);
+
+        maker.send(FixMessage::new(msg_type::LOGOUT));
+        assert_eq!(maker.recv().msg_type(), msg_type::LOGOUT);
+    }
+
+    #[test]
+    fn sequence_gap_filled_by_resend() {
+        let gateway = FixGateway::new(Orderbook::new(Asset::BTC, Asset::USD), FixConfig {
+            comp_id: "BOOK".to_string(),
+            symbol: "BTC/USD".to_string(),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = gateway.clone();
+        thread::spawn(move || server.serve(listener));
+
+        let mut client = Client::logon(addr, "CLIENT");
+        let test_request = |id: &str| FixMessage::new(msg_type::TEST_REQUEST).with(tags::TEST_REQ_ID, id);
+        let reply = |client: &mut Client| {
+            let message = client.recv();
+            (message.msg_type().to_string(), message.get(tags::TEST_REQ_ID).map(String::from))
+        };
+
+        // message 2 is lost, 3 waits for it
+        client.send_seq(test_request("T3"), 3);
+        let resend = client.recv();
+        assert_eq!(resend.msg_type(), msg_type::RESEND_REQUEST);
+        assert_eq!(resend.get(tags::BEGIN_SEQ_NO), Some("2"));
+
+        client.send_seq(test_request("T2").with(tags::POSS_DUP_FLAG, "Y"), 2);
+        assert_eq!(reply(&mut client), ("0".to_string(), Some("T2".to_string())));
+        assert_eq!(reply(&mut client), ("0".to_string(), Some("T3".to_string())));
+
+        // duplicate of the processed message is dropped
+        client.send_seq(test_request("T3").with(tags::POSS_DUP_FLAG, "Y"), 3);
+        client.send_seq(test_request("T4"), 4);
+        assert_eq!(reply(&mut client), ("0".to_string(), Some("T4".to_string())));
+    }
+
+    #[test]
+    fn stalled_session_dropped() {
+        let gateway = FixGateway::new(Orderbook::new(Asset::BTC, Asset::USD), FixConfig {
+            comp_id: "BOOK".to_string(),
+            symbol: "BTC/USD".to_string(),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = gateway.clone();
+        thread::spawn(move || server.serve(listener));
+
+        // rejects are reported to the client, which never reads them
+        let mut stalled = Client::logon(addr, "STALLED");
+        let order = FixMessage::new(msg_type::NEW_ORDER_SINGLE)
+            .with(tags::CL_ORD_ID, "s1")
+            .with(tags::SYMBOL, "BTC/USD")
+            .with(tags::SIDE, "1")
+            .with(tags::ORDER_QTY, -1)
+            .with(tags::ORD_TYPE, "1")
+            .with(tags::SENDER_COMP_ID, "STALLED")
+            .with(tags::TARGET_COMP_ID, "BOOK")
+            .with(tags::SENDING_TIME, utc_timestamp(SystemTime::now()));
+        let dropped = (2..1_000_000).any(|seq| {
+            let message = order.clone().with(tags::MSG_SEQ_NUM, seq);
+            stalled.writer.write_all(&message.encode()).is_err()
+        });
+        assert!(dropped);
+
+        // other sessions and the orderbook are not blocked
+        let mut client = Client::logon(addr, "CLIENT");
+        client.order("c1", "1", 1.0, Some(10.0));
+        assert_eq!(client.recv().get(tags::ORD_STATUS), Some("0"));
+    }
+}
@@ -0,0 +1,764 @@
+//! gRPC service in front of a single orderbook
+//!
+//! Service definition for generated clients is in `proto/orderbook.proto`,
+//! the `proto` module mirrors its messages.
+
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport;
+use tonic::{Request, Response, Status};
+
+use crate::engine::domain::{OrderSide, OrderType};
+use crate::engine::market_data::{self, BookDelta, PriceLevel, Trade};
+use crate::engine::orderbook::{Failed, Orderbook, OrderProcessingResult, Success};
+use crate::engine::orders::{self, OrderRequest};
+use self::proto::matching_engine_server::{MatchingEngine, MatchingEngineServer};
+use self::proto::order_event::Event;
+
+
+// updates kept for slow stream subscribers
+const FEED_CAPACITY: usize = 1024;
+
+
+/// Messages of `proto/orderbook.proto` with generated client and server
+pub mod proto {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Side {
+        Unspecified = 0,
+        Bid = 1,
+        Ask = 2,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum OrderType {
+        Unspecified = 0,
+        Market = 1,
+        Limit = 2,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum RejectReason {
+        Unspecified = 0,
+        Invalid = 1,
+        DuplicateOrder = 2,
+        /// Rest of the market order, not matched by the book
+        NoMatch = 3,
+        UnknownOrder = 4,
+        InsufficientFunds = 5,
+        RiskLimit = 6,
+        Throttled = 7,
+        Halted = 8,
+    }
+
+    /// Price is ignored for market orders
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubmitOrderRequest {
+        #[prost(uint64, tag = "1")]
+        pub owner: u64,
+        #[prost(enumeration = "Side", tag = "2")]
+        pub side: i32,
+        #[prost(enumeration = "OrderType", tag = "3")]
+        pub order_type: i32,
+        #[prost(double, tag = "4")]
+        pub price: f64,
+        #[prost(double, tag = "5")]
+        pub qty: f64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CancelOrderRequest {
+        #[prost(uint64, tag = "1")]
+        pub owner: u64,
+        #[prost(uint64, tag = "2")]
+        pub order_id: u64,
+        #[prost(enumeration = "Side", tag = "3")]
+        pub side: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AmendOrderRequest {
+        #[prost(uint64, tag = "1")]
+        pub owner: u64,
+        #[prost(uint64, tag = "2")]
+        pub order_id: u64,
+        #[prost(enumeration = "Side", tag = "3")]
+        pub side: i32,
+        #[prost(double, tag = "4")]
+        pub price: f64,
+        #[prost(double, tag = "5")]
+        pub qty: f64,
+    }
+
+    /// Processing events of one request, in engine order
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct OrderEvents {
+        #[prost(message, repeated, tag = "1")]
+        pub events: Vec<OrderEvent>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct OrderEvent {
+        #[prost(oneof = "order_event::Event", tags = "1, 2, 3, 4, 5, 6")]
+        pub event: Option<order_event::Event>,
+    }
+
+    pub mod order_event {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Event {
+            #[prost(message, tag = "1")]
+            Accepted(super::Accepted),
+            #[prost(message, tag = "2")]
+            Filled(super::Fill),
+            #[prost(message, tag = "3")]
+            PartiallyFilled(super::Fill),
+            #[prost(message, tag = "4")]
+            Amended(super::Amended),
+            #[prost(message, tag = "5")]
+            Cancelled(super::Cancelled),
+            #[prost(message, tag = "6")]
+            Rejected(super::Rejected),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Accepted {
+        #[prost(uint64, tag = "1")]
+        pub order_id: u64,
+        #[prost(enumeration = "OrderType", tag = "2")]
+        pub order_type: i32,
+        #[prost(uint64, tag = "3")]
+        pub timestamp_ns: u64,
+    }
+
+    /// Fee is negative for rebate
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Fill {
+        #[prost(uint64, tag = "1")]
+        pub order_id: u64,
+        #[prost(enumeration = "Side", tag = "2")]
+        pub side: i32,
+        #[prost(enumeration = "OrderType", tag = "3")]
+        pub order_type: i32,
+        #[prost(double, tag = "4")]
+        pub price: f64,
+        #[prost(double, tag = "5")]
+        pub qty: f64,
+        #[prost(double, tag = "6")]
+        pub fee: f64,
+        #[prost(uint64, tag = "7")]
+        pub timestamp_ns: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Amended {
+        #[prost(uint64, tag = "1")]
+        pub order_id: u64,
+        #[prost(double, tag = "2")]
+        pub price: f64,
+        #[prost(double, tag = "3")]
+        pub qty: f64,
+        #[prost(uint64, tag = "4")]
+        pub timestamp_ns: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Cancelled {
+        #[prost(uint64, tag = "1")]
+        pub order_id: u64,
+        #[prost(uint64, tag = "2")]
+        pub timestamp_ns: u64,
+    }
+
+    /// Order ID is zero if the request did not refer to an order
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Rejected {
+        #[prost(enumeration = "RejectReason", tag = "1")]
+        pub reason: i32,
+        #[prost(uint64, tag = "2")]
+        pub order_id: u64,
+        #[prost(string, tag = "3")]
+        pub message: String,
+    }
+
+    /// Zero levels means full depth
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DepthRequest {
+        #[prost(uint32, tag = "1")]
+        pub max_levels: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PriceLevel {
+        #[prost(double, tag = "1")]
+        pub price: f64,
+        #[prost(double, tag = "2")]
+        pub qty: f64,
+        #[prost(uint32, tag = "3")]
+        pub orders: u32,
+    }
+
+    /// Best levels first, `seq` is the number of the last book update
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Depth {
+        #[prost(uint64, tag = "1")]
+        pub seq: u64,
+        #[prost(message, repeated, tag = "2")]
+        pub bids: Vec<PriceLevel>,
+        #[prost(message, repeated, tag = "3")]
+        pub asks: Vec<PriceLevel>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamTradesRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Trade {
+        #[prost(uint64, tag = "1")]
+        pub taker_order_id: u64,
+        #[prost(uint64, tag = "2")]
+        pub maker_order_id: u64,
+        #[prost(enumeration = "Side", tag = "3")]
+        pub taker_side: i32,
+        #[prost(double, tag = "4")]
+        pub price: f64,
+        #[prost(double, tag = "5")]
+        pub qty: f64,
+        #[prost(double, tag = "6")]
+        pub taker_fee: f64,
+        #[prost(double, tag = "7")]
+        pub maker_fee: f64,
+        #[prost(uint64, tag = "8")]
+        pub timestamp_ns: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamBookRequest {}
+
+    /// Snapshot replaces the whole book. Otherwise levels are changes made by one request,
+    /// zero quantity removes the level and order counts are not set.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BookUpdate {
+        #[prost(uint64, tag = "1")]
+        pub seq: u64,
+        #[prost(bool, tag = "2")]
+        pub snapshot: bool,
+        #[prost(message, repeated, tag = "3")]
+        pub bids: Vec<PriceLevel>,
+        #[prost(message, repeated, tag = "4")]
+        pub asks: Vec<PriceLevel>,
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/orderbook.MatchingEngine.rs"));
+}
+
+
+/// Server side stream of trades or book updates
+pub type FeedStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+
+struct State<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    orderbook: Orderbook<Asset>,
+    book_seq: u64,
+}
+
+
+/// gRPC server in front of a single orderbook.
+///
+/// Orders are placed, canceled and amended on behalf of the owner given in
+/// request. Clients are not authenticated, so any client can act for any owner:
+/// serve trusted clients only or put the server behind an authenticating proxy.
+pub struct GrpcServer<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    state: Arc<Mutex<State<Asset>>>,
+    order_asset: Asset,
+    price_asset: Asset,
+    trades: broadcast::Sender<proto::Trade>,
+    book: broadcast::Sender<proto::BookUpdate>,
+}
+
+
+impl<Asset> Clone for GrpcServer<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    fn clone(&self) -> Self {
+        GrpcServer {
+            state: self.state.clone(),
+            order_asset: self.order_asset,
+            price_asset: self.price_asset,
+            trades: self.trades.clone(),
+            book: self.book.clone(),
+        }
+    }
+}
+
+
+impl<Asset> GrpcServer<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + Sync + 'static,
+{
+    pub fn new(orderbook: Orderbook<Asset>) -> Self {
+        GrpcServer {
+            order_asset: orderbook.order_asset(),
+            price_asset: orderbook.price_asset(),
+            state: Arc::new(Mutex::new(State { orderbook, book_seq: 0 })),
+            trades: broadcast::channel(FEED_CAPACITY).0,
+            book: broadcast::channel(FEED_CAPACITY).0,
+        }
+    }
+
+
+    /// Access orderbook between requests
+    pub fn with_orderbook<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Orderbook<Asset>) -> R,
+    {
+        f(&mut self.lock().orderbook)
+    }
+
+
+    /// Tonic service, to be added to a server together with other services
+    pub fn into_service(self) -> MatchingEngineServer<Self> {
+        MatchingEngineServer::new(self)
+    }
+
+
+    /// Accept connections until the returned future is dropped
+    pub async fn serve(self, listener: TcpListener) -> Result<(), transport::Error> {
+        transport::Server::builder()
+            .add_service(self.into_service())
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+    }
+
+
+    /// Process request and publish market data
+    fn process(&self, request: OrderRequest<Asset>) -> proto::OrderEvents {
+        let mut state = self.lock();
+        // diffed even without subscribers, `seq` of depth counts every change
+        let (results, deltas) = state.orderbook.process_order_with_deltas(request);
+        for trade in market_data::trades(&results) {
+            let _ = self.trades.send(trade.into());
+        }
+        if !deltas.is_empty() {
+            state.book_seq += 1;
+            let _ = self.book.send(book_changes(state.book_seq, &deltas));
+        }
+        order_events(results)
+    }
+
+
+    fn lock(&self) -> MutexGuard<'_, State<Asset>> {
+        self.state.lock().unwrap()
+    }
+}
+
+
+#[tonic::async_trait]
+impl<Asset> MatchingEngine for GrpcServer<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + Sync + 'static,
+{
+    type StreamTradesStream = FeedStream<proto::Trade>;
+    type StreamBookUpdatesStream = FeedStream<proto::BookUpdate>;
+
+
+    async fn submit_order(
+        &self,
+        request: Request<proto::SubmitOrderRequest>,
+    ) -> Result<Response<proto::OrderEvents>, Status> {
+        let request = request.into_inner();
+        let side = order_side(request.side)?;
+        let ts = SystemTime::now();
+        let order = match proto::OrderType::try_from(request.order_type) {
+            Ok(proto::OrderType::Market) => orders::new_market_order_request(
+                request.owner,
+                self.order_asset,
+                self.price_asset,
+                side,
+                request.qty,
+                ts,
+            ),
+            Ok(proto::OrderType::Limit) => orders::new_limit_order_request(
+                request.owner,
+                self.order_asset,
+                self.price_asset,
+                side,
+                request.price,
+                request.qty,
+                ts,
+            ),
+            _ => return Err(Status::invalid_argument("order type is not set")),
+        };
+        Ok(Response::new(self.process(order)))
+    }
+
+
+    async fn cancel_order(
+        &self,
+        request: Request<proto::CancelOrderRequest>,
+    ) -> Result<Response<proto::OrderEvents>, Status> {
+        let request = request.into_inner();
+        let side = order_side(request.side)?;
+        let order = orders::limit_order_cancel_request(request.owner, request.order_id, side);
+        Ok(Response::new(self.process(order)))
+    }
+
+
+    async fn amend_order(
+        &self,
+        request: Request<proto::AmendOrderRequest>,
+    ) -> Result<Response<proto::OrderEvents>, Status> {
+        let request = request.into_inner();
+        let side = order_side(request.side)?;
+        let order = orders::amend_order_request(
+            request.owner,
+            request.order_id,
+            side,
+            request.price,
+            request.qty,
+            SystemTime::now(),
+        );
+        Ok(Response::new(self.process(order)))
+    }
+
+
+    async fn get_depth(
+        &self,
+        request: Request<proto::DepthRequest>,
+    ) -> Result<Response<proto::Depth>, Status> {
+        let max_levels = match request.into_inner().max_levels {
+            0 => usize::MAX,
+            levels => levels as usize,
+        };
+        let state = self.lock();
+        let depth = state.orderbook.depth(max_levels);
+        Ok(Response::new(proto::Depth {
+            seq: state.book_seq,
+            bids: depth.bids.iter().map(price_level).collect(),
+            asks: depth.asks.iter().map(price_level).collect(),
+        }))
+    }
+
+
+    async fn stream_trades(
+        &self,
+        _request: Request<proto::StreamTradesRequest>,
+    ) -> Result<Response<Self::StreamTradesStream>, Status> {
+        Ok(Response::new(feed_stream(self.trades.subscribe())))
+    }
+
+
+    async fn stream_book_updates(
+        &self,
+        _request: Request<proto::StreamBookRequest>,
+    ) -> Result<Response<Self::StreamBookUpdatesStream>, Status> {
+        // subscribe under the lock, so no update is missed or applied twice
+        let (snapshot, updates) = {
+            let state = self.lock();
+            let depth = state.orderbook.depth(usize::MAX);
+            let snapshot = proto::BookUpdate {
+                seq: state.book_seq,
+                snapshot: true,
+                bids: depth.bids.iter().map(price_level).collect(),
+                asks: depth.asks.iter().map(price_level).collect(),
+            };
+            (snapshot, self.book.subscribe())
+        };
+        let stream = tokio_stream::once(Ok(snapshot)).chain(feed_stream(updates));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+
+/// Lagging subscriber gets `DATA_LOSS` and has to subscribe again
+#[allow(clippy::result_large_err)]
+fn feed_stream<T>(updates: broadcast::Receiver<T>) -> FeedStream<T>
+where
+    T: Clone + Send + 'static,
+{
+    Box::pin(BroadcastStream::new(updates).map(|update| {
+        update.map_err(|BroadcastStreamRecvError::Lagged(missed)| {
+            Status::data_loss(format!("{} updates missed, subscribe again", missed))
+        })
+    }))
+}
+
+
+#[allow(clippy::result_large_err)]
+fn order_side(side: i32) -> Result<OrderSide, Status> {
+    match proto::Side::try_from(side) {
+        Ok(proto::Side::Bid) => Ok(OrderSide::Bid),
+        Ok(proto::Side::Ask) => Ok(OrderSide::Ask),
+        _ => Err(Status::invalid_argument("side is not set")),
+    }
+}
+
+
+fn order_events(results: OrderProcessingResult) -> proto::OrderEvents {
+    let events = results
+        .into_iter()
+        .map(|result| proto::OrderEvent {
+            event: Some(match result {
+                Ok(success) => success.into(),
+                Err(failed) => Event::Rejected(rejected(&failed)),
+            }),
+        })
+        .collect();
+    proto::OrderEvents { events }
+}
+
+
+fn rejected(failed: &Failed) -> proto::Rejected {
+    let (reason, order_id) = match *failed {
+        Failed::ValidationFailed(_) => (proto::RejectReason::Invalid, 0),
+        Failed::DuplicateOrderID(id) => (proto::RejectReason::DuplicateOrder, id),
+        Failed::NoMatch(id) => (proto::RejectReason::NoMatch, id),
+        Failed::OrderNotFound(id) => (proto::RejectReason::UnknownOrder, id),
+        Failed::InsufficientFunds(id) => (proto::RejectReason::InsufficientFunds, id),
+        Failed::MaxQtyExceeded(id) |
+        Failed::MaxNotionalExceeded(id) |
+        Failed::MaxOpenOrdersExceeded(id) |
+        Failed::PositionLimitExceeded(id) |
+        Failed::PriceDeviationExceeded(id) => (proto::RejectReason::RiskLimit, id),
+        // these carry owner, not order
+        Failed::Throttled(_) => (proto::RejectReason::Throttled, 0),
+        Failed::OwnerKilled(_) | Failed::TradingHalted => (proto::RejectReason::Halted, 0),
+    };
+    let message = match *failed {
+        Failed::ValidationFailed(ref message) => message.clone(),
+        ref failed => format!("{:?}", failed),
+    };
+    proto::Rejected { reason: reason as i32, order_id, message }
+}
+
+
+fn book_changes(seq: u64, deltas: &[BookDelta]) -> proto::BookUpdate {
+    let level = |delta: &BookDelta| proto::PriceLevel { price: delta.price, qty: delta.qty, orders: 0 };
+    proto::BookUpdate {
+        seq,
+        snapshot: false,
+        bids: deltas.iter().filter(|delta| delta.side == OrderSide::Bid).map(level).collect(),
+        asks: deltas.iter().filter(|delta| delta.side == OrderSide::Ask).map(level).collect(),
+    }
+}
+
+
+fn price_level(level: &PriceLevel) -> proto::PriceLevel {
+    proto::PriceLevel {
+        price: level.price,
+        qty: level.qty,
+        orders: level.orders as u32,
+    }
+}
+
+
+/// Nanoseconds since Unix epoch
+fn timestamp_ns(ts: SystemTime) -> u64 {
+    let nanos = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    nanos.min(u128::from(u64::MAX)) as u64
+}
+
+
+impl From<OrderSide> for proto::Side {
+    fn from(side: OrderSide) -> Self {
+        match side {
+            OrderSide::Bid => proto::Side::Bid,
+            OrderSide::Ask => proto::Side::Ask,
+        }
+    }
+}
+
+
+impl From<OrderType> for proto::OrderType {
+    fn from(order_type: OrderType) -> Self {
+        match order_type {
+            OrderType::Market => proto::OrderType::Market,
+            OrderType::Limit => proto::OrderType::Limit,
+        }
+    }
+}
+
+
+impl From<Success> for Event {
+    fn from(success: Success) -> Self {
+        match success {
+            Success::Accepted { id, order_type, ts } => Event::Accepted(proto::Accepted {
+                order_id: id,
+                order_type: proto::OrderType::from(order_type) as i32,
+                timestamp_ns: timestamp_ns(ts),
+            }),
+            Success::Filled { order_id, side, order_type, price, qty, fee, ts } => {
+                Event::Filled(fill(order_id, side, order_type, price, qty, fee, ts))
+            }
+            Success::PartiallyFilled { order_id, side, order_type, price, qty, fee, ts } => {
+                Event::PartiallyFilled(fill(order_id, side, order_type, price, qty, fee, ts))
+            }
+            Success::Amended { id, price, qty, ts } => Event::Amended(proto::Amended {
+                order_id: id,
+                price,
+                qty,
+                timestamp_ns: timestamp_ns(ts),
+            }),
+            Success::Cancelled { id, ts } => Event::Cancelled(proto::Cancelled {
+                order_id: id,
+                timestamp_ns: timestamp_ns(ts),
+            }),
+        }
+    }
+}
+
+
+fn fill(
+    order_id: u64,
+    side: OrderSide,
+    order_type: OrderType,
+    price: f64,
+    qty: f64,
+    fee: f64,
+    ts: SystemTime,
+) -> proto::Fill {
+    proto::Fill {
+        order_id,
+        side: proto::Side::from(side) as i32,
+        order_type: proto::OrderType::from(order_type) as i32,
+        price,
+        qty,
+        fee,
+        timestamp_ns: timestamp_ns(ts),
+    }
+}
+
+
+impl From<Trade> for proto::Trade {
+    fn from(trade: Trade) -> Self {
+        proto::Trade {
+            taker_order_id: trade.taker_order_id,
+            maker_order_id: trade.maker_order_id,
+            taker_side: proto::Side::from(trade.taker_side) as i32,
+            price: trade.price,
+            qty: trade.qty,
+            taker_fee: trade.taker_fee,
+            maker_fee: trade.maker_fee,
+            timestamp_ns: timestamp_ns(trade.ts),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::proto::matching_engine_client::MatchingEngineClient;
+    use tokio::runtime;
+    use tonic::Code;
+    use crate::engine::orderbook::test::Asset;
+
+    fn limit(owner: u64, side: proto::Side, price: f64, qty: f64) -> proto::SubmitOrderRequest {
+        proto::SubmitOrderRequest {
+            owner,
+            side: side as i32,
+            order_type: proto::OrderType::Limit as i32,
+            price,
+            qty,
+        }
+    }
+
+    #[test]
+    fn orders_and_streams() {
+        let rt = runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        let server = GrpcServer::new(Orderbook::new(Asset::BTC, Asset::USD));
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let addr = listener.local_addr().unwrap();
+        rt.spawn(server.clone().serve(listener));
+
+        let mut client = rt.block_on(MatchingEngineClient::connect(format!("http://{}", addr))).unwrap();
+        let trades = rt.block_on(client.stream_trades(proto::StreamTradesRequest {}));
+        let mut trades = trades.unwrap().into_inner();
+        let book = rt.block_on(client.stream_book_updates(proto::StreamBookRequest {}));
+        let mut book = book.unwrap().into_inner();
+        let snapshot = rt.block_on(book.message()).unwrap().unwrap();
+        assert!(snapshot.snapshot && snapshot.bids.is_empty() && snapshot.asks.is_empty());
+        assert_eq!(snapshot.seq, 0);
+
+        let events = rt.block_on(client.submit_order(limit(1, proto::Side::Ask, 10.5, 2.0)));
+        let events = events.unwrap().into_inner().events;
+        assert!(matches!(events[..], [proto::OrderEvent { event: Some(Event::Accepted(ref accepted)) }]
+            if accepted.order_id == 1 && accepted.order_type == proto::OrderType::Limit as i32));
+
+        let market = proto::SubmitOrderRequest {
+            owner: 2,
+            side: proto::Side::Bid as i32,
+            order_type: proto::OrderType::Market as i32,
+            price: 0.0,
+            qty: 0.5,
+        };
+        let events = rt.block_on(client.submit_order(market)).unwrap().into_inner().events;
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[1].event, Some(Event::Filled(ref fill)) if fill.order_id == 2));
+        assert!(matches!(events[2].event, Some(Event::PartiallyFilled(ref fill))
+            if fill.order_id == 1 && fill.price == 10.5 && fill.qty == 0.5));
+
+        let trade = rt.block_on(trades.message()).unwrap().unwrap();
+        assert_eq!((trade.taker_order_id, trade.maker_order_id), (2, 1));
+        assert_eq!((trade.taker_side, trade.price, trade.qty), (proto::Side::Bid as i32, 10.5, 0.5));
+
+        let added = rt.block_on(book.message()).unwrap().unwrap();
+        assert_eq!((added.seq, added.snapshot), (1, false));
+        assert_eq!(added.asks, [proto::PriceLevel { price: 10.5, qty: 2.0, orders: 0 }]);
+        let matched = rt.block_on(book.message()).unwrap().unwrap();
+        assert_eq!(matched.seq, 2);
+        assert_eq!(matched.asks, [proto::PriceLevel { price: 10.5, qty: 1.5, orders: 0 }]);
+
+        let cancel = proto::CancelOrderRequest { owner: 1, order_id: 9, side: proto::Side::Ask as i32 };
+        let events = rt.block_on(client.cancel_order(cancel)).unwrap().into_inner().events;
+        assert!(matches!(events[..], [proto::OrderEvent { event: Some(Event::Rejected(ref rejected)) }]
+            if rejected.reason == proto::RejectReason::UnknownOrder as i32 && rejected.order_id == 9));
+
+        let amend = proto::AmendOrderRequest { owner: 1, order_id: 1, side: 0, price: 10.4, qty: 1.0 };
+        let status = rt.block_on(client.amend_order(amend)).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let depth = rt.block_on(client.get_depth(proto::DepthRequest { max_levels: 1 }));
+        let depth = depth.unwrap().into_inner();
+        assert_eq!(depth.seq, 2);
+        assert_eq!(depth.asks, [proto::PriceLevel { price: 10.5, qty: 1.5, orders: 1 }]);
+        assert_eq!(server.with_orderbook(|orderbook| orderbook.current_spread()), None);
+    }
+
+    #[test]
+    fn depth_seq_without_subscribers() {
+        let rt = runtime::Builder::new_current_thread().build().unwrap();
+        let server = GrpcServer::new(Orderbook::new(Asset::BTC, Asset::USD));
+        let depth_seq = || {
+            let depth = rt.block_on(server.get_depth(Request::new(proto::DepthRequest { max_levels: 0 })));
+            depth.unwrap().into_inner().seq
+        };
+
+        assert_eq!(depth_seq(), 0);
+        rt.block_on(server.submit_order(Request::new(limit(1, proto::Side::Ask, 10.5, 2.0)))).unwrap();
+        assert_eq!(depth_seq(), 1);
+        let cancel = proto::CancelOrderRequest { owner: 1, order_id: 1, side: proto::Side::Ask as i32 };
+        rt.block_on(server.cancel_order(Request::new(cancel.clone()))).unwrap();
+        assert_eq!(depth_seq(), 2);
+        // rejected cancel leaves the book as it was
+        rt.block_on(server.cancel_order(Request::new(cancel))).unwrap();
+        assert_eq!(depth_seq(), 2);
+    }
+}
@@ -0,0 +1,286 @@
+//! Binary market data protocol modeled on ITCH
+//!
+//! Messages have the same layout rules as the order entry protocol.
+//! Over UDP messages are sent in packets, prefixed with the sequence number
+//! of the first message (u64) and the message count (u16).
+
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+use crate::engine::domain::OrderSide;
+use crate::engine::market_data;
+use crate::engine::orderbook::{OrderProcessingResult, Success};
+use crate::engine::orders::OrderRequest;
+use super::wire::{Reader, Writer};
+
+pub use super::wire::DecodeError;
+
+
+pub const PACKET_HEADER_LEN: usize = 10;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItchMessage {
+    /// `A`, order rests in the book
+    AddOrder {
+        ts: SystemTime,
+        order_id: u64,
+        side: OrderSide,
+        price: f64,
+        qty: f64,
+    },
+    /// `E`, resting order is executed, removed when fully filled
+    OrderExecuted {
+        ts: SystemTime,
+        order_id: u64,
+        qty: f64,
+        match_number: u64,
+    },
+    /// `D`, order is removed from the book
+    OrderDelete { ts: SystemTime, order_id: u64 },
+    /// `U`, order keeps its ID, quantity is the new open quantity
+    OrderReplace {
+        ts: SystemTime,
+        order_id: u64,
+        price: f64,
+        qty: f64,
+    },
+    /// `P`, trade print with the side of incoming order
+    Trade {
+        ts: SystemTime,
+        match_number: u64,
+        taker_side: OrderSide,
+        price: f64,
+        qty: f64,
+    },
+}
+
+
+impl ItchMessage {
+    /// Length of the message with the type byte
+    pub fn len(msg_type: u8) -> Option<usize> {
+        match msg_type {
+            b'A' => Some(34),
+            b'E' => Some(33),
+            b'D' => Some(17),
+            b'U' => Some(33),
+            b'P' => Some(34),
+            _ => None,
+        }
+    }
+
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match *self {
+            ItchMessage::AddOrder { ts, order_id, side, price, qty } => {
+                Writer::new(buf, b'A').ts(ts).u64(order_id).side(side).f64(price).f64(qty);
+            }
+            ItchMessage::OrderExecuted { ts, order_id, qty, match_number } => {
+                Writer::new(buf, b'E').ts(ts).u64(order_id).f64(qty).u64(match_number);
+            }
+            ItchMessage::OrderDelete { ts, order_id } => {
+                Writer::new(buf, b'D').ts(ts).u64(order_id);
+            }
+            ItchMessage::OrderReplace { ts, order_id, price, qty } => {
+                Writer::new(buf, b'U').ts(ts).u64(order_id).f64(price).f64(qty);
+            }
+            ItchMessage::Trade { ts, match_number, taker_side, price, qty } => {
+                Writer::new(buf, b'P').ts(ts).u64(match_number).side(taker_side).f64(price).f64(qty);
+            }
+        }
+    }
+
+
+    /// Decode the first message in buffer with its length,
+    /// or `None` if the message is not complete yet
+    pub fn decode(buf: &[u8]) -> Result<Option<(ItchMessage, usize)>, DecodeError> {
+        let msg_type = match buf.first() {
+            Some(&msg_type) => msg_type,
+            None => return Ok(None),
+        };
+        let len = ItchMessage::len(msg_type).ok_or(DecodeError::UnknownType(msg_type))?;
+        if buf.len() < len {
+            return Ok(None);
+        }
+
+        let mut reader = Reader::new(&buf[..len]);
+        let message = match msg_type {
+            b'A' => ItchMessage::AddOrder {
+                ts: reader.ts(),
+                order_id: reader.u64(),
+                side: reader.side()?,
+                price: reader.f64(),
+                qty: reader.f64(),
+            },
+            b'E' => ItchMessage::OrderExecuted {
+                ts: reader.ts(),
+                order_id: reader.u64(),
+                qty: reader.f64(),
+                match_number: reader.u64(),
+            },
+            b'D' => ItchMessage::OrderDelete {
+                ts: reader.ts(),
+                order_id: reader.u64(),
+            },
+            b'U' => ItchMessage::OrderReplace {
+                ts: reader.ts(),
+                order_id: reader.u64(),
+                price: reader.f64(),
+                qty: reader.f64(),
+            },
+            _ => ItchMessage::Trade {
+                ts: reader.ts(),
+                match_number: reader.u64(),
+                taker_side: reader.side()?,
+                price: reader.f64(),
+                qty: reader.f64(),
+            },
+        };
+        Ok(Some((message, len)))
+    }
+}
+
+
+/// Encode UDP packet with sequence number of the first message
+pub fn encode_packet(seq: u64, messages: &[ItchMessage]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(PACKET_HEADER_LEN + messages.len() * 34);
+    buf.extend_from_slice(&seq.to_be_bytes());
+    buf.extend_from_slice(&(messages.len() as u16).to_be_bytes());
+    for message in messages {
+        message.encode(&mut buf);
+    }
+    buf
+}
+
+
+/// Decode UDP packet into sequence number of the first message and messages
+pub fn decode_packet(buf: &[u8]) -> Result<(u64, Vec<ItchMessage>), DecodeError> {
+    if buf.len() < PACKET_HEADER_LEN {
+        return Err(DecodeError::BadField("packet header"));
+    }
+    let mut seq = [0; 8];
+    seq.copy_from_slice(&buf[..8]);
+    let count = u16::from_be_bytes([buf[8], buf[9]]) as usize;
+
+    let mut messages = Vec::with_capacity(count);
+    let mut pos = PACKET_HEADER_LEN;
+    while messages.len() < count {
+        match ItchMessage::decode(&buf[pos..])? {
+            Some((message, len)) => {
+                messages.push(message);
+                pos += len;
+            }
+            None => return Err(DecodeError::BadField("message count")),
+        }
+    }
+    Ok((u64::from_be_bytes(seq), messages))
+}
+
+
+/// Book changes made by the processed request.
+///
+/// Every trade is reported by execution of the resting order followed by trade print,
+/// numbered from `next_match`. Rest of the incoming limit order is added after trades.
+pub fn book_messages<Asset>(
+    request: &OrderRequest<Asset>,
+    results: &OrderProcessingResult,
+    next_match: &mut u64,
+) -> Vec<ItchMessage>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    let mut messages = Vec::new();
+    for trade in market_data::trades(results) {
+        messages.push(ItchMessage::OrderExecuted {
+            ts: trade.ts,
+            order_id: trade.maker_order_id,
+            qty: trade.qty,
+            match_number: *next_match,
+        });
+        messages.push(ItchMessage::Trade {
+            ts: trade.ts,
+            match_number: *next_match,
+            taker_side: trade.taker_side,
+            price: trade.price,
+            qty: trade.qty,
+        });
+        *next_match += 1;
+    }
+
+    if let OrderRequest::NewLimitOrder { side, price, qty, .. } = *request {
+        if let Some(message) = resting_order(side, price, qty, results) {
+            messages.push(message);
+        }
+    }
+
+    for event in results {
+        match *event {
+            Ok(Success::Cancelled { id, ts }) => {
+                messages.push(ItchMessage::OrderDelete { ts, order_id: id });
+            }
+            Ok(Success::Amended { id, price, qty, ts }) => {
+                messages.push(ItchMessage::OrderReplace { ts, order_id: id, price, qty });
+            }
+            _ => (),
+        }
+    }
+    messages
+}
+
+
+/// Part of the new limit order left in the book
+fn resting_order(
+    side: OrderSide,
+    price: f64,
+    qty: f64,
+    results: &OrderProcessingResult,
+) -> Option<ItchMessage> {
+    let (order_id, ts) = match results.first() {
+        Some(&Ok(Success::Accepted { id, ts, .. })) => (id, ts),
+        _ => return None,
+    };
+
+    let mut open_qty = qty;
+    for event in results {
+        match *event {
+            Err(_) => return None,
+            Ok(Success::Filled { order_id: id, .. }) if id == order_id => return None,
+            Ok(Success::PartiallyFilled { order_id: id, qty, .. }) if id == order_id => open_qty -= qty,
+            _ => (),
+        }
+    }
+    Some(ItchMessage::AddOrder { ts, order_id, side, price, qty: open_qty })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::orderbook::Orderbook;
+    use crate::engine::orders;
+    use crate::engine::orderbook::test::Asset;
+
+    #[test]
+    fn partially_filled_order_rests() {
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        let mut next_match = 5;
+        let ts = SystemTime::now();
+        let ask = orders::new_limit_order_request(1, Asset::BTC, Asset::USD, OrderSide::Ask, 10.0, 1.0, ts);
+        let results = orderbook.process_order(ask.clone());
+        let messages = book_messages(&ask, &results, &mut next_match);
+        assert!(matches!(messages[..], [ItchMessage::AddOrder { order_id: 1, qty, .. }] if qty == 1.0));
+
+        let bid = orders::new_limit_order_request(2, Asset::BTC, Asset::USD, OrderSide::Bid, 10.5, 3.0, ts);
+        let results = orderbook.process_order(bid.clone());
+        let messages = book_messages(&bid, &results, &mut next_match);
+        assert_eq!(next_match, 6);
+        assert!(matches!(messages[0], ItchMessage::OrderExecuted { order_id: 1, match_number: 5, .. }));
+        assert!(matches!(messages[1], ItchMessage::Trade { price, qty, .. } if price == 10.0 && qty == 1.0));
+        assert!(matches!(messages[2], ItchMessage::AddOrder { order_id: 2, price, qty, .. }
+            if price == 10.5 && qty == 2.0));
+
+        let packet = encode_packet(42, &messages);
+        assert_eq!(decode_packet(&packet), Ok((42, messages)));
+        assert_eq!(decode_packet(&packet[..packet.len() - 1]), Err(DecodeError::BadField("message count")));
+    }
+}
@@ -0,0 +1,10 @@
+pub mod binary;
+pub mod fix;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod itch;
+pub mod ouch;
+mod outbox;
+mod wire;
+#[cfg(feature = "ws")]
+pub mod ws;
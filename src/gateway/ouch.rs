@@ -0,0 +1,375 @@
+//! Fixed-length binary order entry protocol modeled on OUCH
+//!
+//! Every message starts with a type byte and has a fixed length for its type.
+//! Integers are big-endian, prices and quantities are IEEE 754 doubles,
+//! timestamps are nanoseconds since Unix epoch.
+
+use std::time::SystemTime;
+
+use crate::engine::domain::{OrderSide, OrderType};
+use crate::engine::orderbook::Failed;
+use super::wire::{Reader, Writer};
+
+pub use super::wire::DecodeError;
+
+
+/// Message from client
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inbound {
+    /// `O`, price is ignored for market orders
+    EnterOrder {
+        token: u32,
+        side: OrderSide,
+        order_type: OrderType,
+        price: f64,
+        qty: f64,
+    },
+    /// `X`
+    CancelOrder { order_id: u64, side: OrderSide },
+    /// `U`
+    ReplaceOrder {
+        order_id: u64,
+        side: OrderSide,
+        price: f64,
+        qty: f64,
+    },
+}
+
+
+/// Why request was rejected
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RejectReason {
+    Invalid,
+    DuplicateOrder,
+    UnknownOrder,
+    InsufficientFunds,
+    RiskLimit,
+    Throttled,
+    Halted,
+}
+
+
+/// Why order was cancelled
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CancelReason {
+    UserRequested,
+    /// Rest of the market order, not matched by the book
+    NoLiquidity,
+    /// Owner was stopped by kill switch
+    Halted,
+}
+
+
+/// Message to client
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outbound {
+    /// `A`
+    Accepted {
+        token: u32,
+        order_id: u64,
+        ts: SystemTime,
+    },
+    /// `J`, token is zero for cancel and replace requests
+    Rejected {
+        token: u32,
+        order_id: u64,
+        reason: RejectReason,
+    },
+    /// `E`, fee is negative for rebate
+    Executed {
+        order_id: u64,
+        price: f64,
+        qty: f64,
+        fee: f64,
+        match_number: u64,
+        ts: SystemTime,
+    },
+    /// `C`
+    Canceled {
+        order_id: u64,
+        reason: CancelReason,
+        ts: SystemTime,
+    },
+    /// `U`
+    Replaced {
+        order_id: u64,
+        price: f64,
+        qty: f64,
+        ts: SystemTime,
+    },
+}
+
+
+impl Inbound {
+    /// Length of the message with the type byte
+    pub fn len(msg_type: u8) -> Option<usize> {
+        match msg_type {
+            b'O' => Some(23),
+            b'X' => Some(10),
+            b'U' => Some(26),
+            _ => None,
+        }
+    }
+
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match *self {
+            Inbound::EnterOrder { token, side, order_type, price, qty } => {
+                Writer::new(buf, b'O').u32(token).side(side).order_type(order_type).f64(price).f64(qty);
+            }
+            Inbound::CancelOrder { order_id, side } => {
+                Writer::new(buf, b'X').u64(order_id).side(side);
+            }
+            Inbound::ReplaceOrder { order_id, side, price, qty } => {
+                Writer::new(buf, b'U').u64(order_id).side(side).f64(price).f64(qty);
+            }
+        }
+    }
+
+
+    /// Decode the first message in buffer with its length,
+    /// or `None` if the message is not complete yet
+    pub fn decode(buf: &[u8]) -> Result<Option<(Inbound, usize)>, DecodeError> {
+        let msg_type = match buf.first() {
+            Some(&msg_type) => msg_type,
+            None => return Ok(None),
+        };
+        let len = Inbound::len(msg_type).ok_or(DecodeError::UnknownType(msg_type))?;
+        if buf.len() < len {
+            return Ok(None);
+        }
+
+        let mut reader = Reader::new(&buf[..len]);
+        let message = match msg_type {
+            b'O' => Inbound::EnterOrder {
+                token: reader.u32(),
+                side: reader.side()?,
+                order_type: reader.order_type()?,
+                price: reader.f64(),
+                qty: reader.f64(),
+            },
+            b'X' => Inbound::CancelOrder {
+                order_id: reader.u64(),
+                side: reader.side()?,
+            },
+            _ => Inbound::ReplaceOrder {
+                order_id: reader.u64(),
+                side: reader.side()?,
+                price: reader.f64(),
+                qty: reader.f64(),
+            },
+        };
+        Ok(Some((message, len)))
+    }
+}
+
+
+impl Outbound {
+    /// Length of the message with the type byte
+    pub fn len(msg_type: u8) -> Option<usize> {
+        match msg_type {
+            b'A' => Some(21),
+            b'J' => Some(14),
+            b'E' => Some(49),
+            b'C' => Some(18),
+            b'U' => Some(33),
+            _ => None,
+        }
+    }
+
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match *self {
+            Outbound::Accepted { token, order_id, ts } => {
+                Writer::new(buf, b'A').u32(token).u64(order_id).ts(ts);
+            }
+            Outbound::Rejected { token, order_id, reason } => {
+                Writer::new(buf, b'J').u32(token).u64(order_id).u8(reason.code());
+            }
+            Outbound::Executed { order_id, price, qty, fee, match_number, ts } => {
+                Writer::new(buf, b'E').u64(order_id).f64(price).f64(qty).f64(fee).u64(match_number).ts(ts);
+            }
+            Outbound::Canceled { order_id, reason, ts } => {
+                Writer::new(buf, b'C').u64(order_id).u8(reason.code()).ts(ts);
+            }
+            Outbound::Replaced { order_id, price, qty, ts } => {
+                Writer::new(buf, b'U').u64(order_id).f64(price).f64(qty).ts(ts);
+            }
+        }
+    }
+
+
+    /// Decode the first message in buffer with its length,
+    /// or `None` if the message is not complete yet
+    pub fn decode(buf: &[u8]) -> Result<Option<(Outbound, usize)>, DecodeError> {
+        let msg_type = match buf.first() {
+            Some(&msg_type) => msg_type,
+            None => return Ok(None),
+        };
+        let len = Outbound::len(msg_type).ok_or(DecodeError::UnknownType(msg_type))?;
+        if buf.len() < len {
+            return Ok(None);
+        }
+
+        let mut reader = Reader::new(&buf[..len]);
+        let message = match msg_type {
+            b'A' => Outbound::Accepted {
+                token: reader.u32(),
+                order_id: reader.u64(),
+                ts: reader.ts(),
+            },
+            b'J' => Outbound::Rejected {
+                token: reader.u32(),
+                order_id: reader.u64(),
+                reason: RejectReason::from_code(reader.u8()).ok_or(DecodeError::BadField("reject reason"))?,
+            },
+            b'E' => Outbound::Executed {
+                order_id: reader.u64(),
+                price: reader.f64(),
+                qty: reader.f64(),
+                fee: reader.f64(),
+                match_number: reader.u64(),
+                ts: reader.ts(),
+            },
+            b'C' => Outbound::Canceled {
+                order_id: reader.u64(),
+                reason: CancelReason::from_code(reader.u8()).ok_or(DecodeError::BadField("cancel reason"))?,
+                ts: reader.ts(),
+            },
+            _ => Outbound::Replaced {
+                order_id: reader.u64(),
+                price: reader.f64(),
+                qty: reader.f64(),
+                ts: reader.ts(),
+            },
+        };
+        Ok(Some((message, len)))
+    }
+}
+
+
+impl RejectReason {
+    pub fn code(self) -> u8 {
+        match self {
+            RejectReason::Invalid => b'V',
+            RejectReason::DuplicateOrder => b'D',
+            RejectReason::UnknownOrder => b'N',
+            RejectReason::InsufficientFunds => b'F',
+            RejectReason::RiskLimit => b'R',
+            RejectReason::Throttled => b'T',
+            RejectReason::Halted => b'H',
+        }
+    }
+
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            b'V' => Some(RejectReason::Invalid),
+            b'D' => Some(RejectReason::DuplicateOrder),
+            b'N' => Some(RejectReason::UnknownOrder),
+            b'F' => Some(RejectReason::InsufficientFunds),
+            b'R' => Some(RejectReason::RiskLimit),
+            b'T' => Some(RejectReason::Throttled),
+            b'H' => Some(RejectReason::Halted),
+            _ => None,
+        }
+    }
+}
+
+
+impl From<&Failed> for RejectReason {
+    fn from(failed: &Failed) -> Self {
+        match *failed {
+            Failed::ValidationFailed(_) | Failed::NoMatch(_) => RejectReason::Invalid,
+            Failed::DuplicateOrderID(_) => RejectReason::DuplicateOrder,
+            Failed::OrderNotFound(_) => RejectReason::UnknownOrder,
+            Failed::InsufficientFunds(_) => RejectReason::InsufficientFunds,
+            Failed::MaxQtyExceeded(_) |
+            Failed::MaxNotionalExceeded(_) |
+            Failed::MaxOpenOrdersExceeded(_) |
+            Failed::PositionLimitExceeded(_) |
+            Failed::PriceDeviationExceeded(_) => RejectReason::RiskLimit,
+            Failed::Throttled(_) => RejectReason::Throttled,
+            Failed::OwnerKilled(_) | Failed::TradingHalted => RejectReason::Halted,
+        }
+    }
+}
+
+
+impl CancelReason {
+    pub fn code(self) -> u8 {
+        match self {
+            CancelReason::UserRequested => b'U',
+            CancelReason::NoLiquidity => b'L',
+            CancelReason::Halted => b'H',
+        }
+    }
+
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            b'U' => Some(CancelReason::UserRequested),
+            b'L' => Some(CancelReason::NoLiquidity),
+            b'H' => Some(CancelReason::Halted),
+            _ => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn fixed_length_roundtrip() {
+        let ts = UNIX_EPOCH + Duration::from_nanos(1_516_040_690_859_954_000);
+        let inbound = [
+            Inbound::EnterOrder {
+                token: 7,
+                side: OrderSide::Bid,
+                order_type: OrderType::Limit,
+                price: 0.98,
+                qty: 5.0,
+            },
+            Inbound::CancelOrder { order_id: 3, side: OrderSide::Ask },
+            Inbound::ReplaceOrder { order_id: 4, side: OrderSide::Ask, price: 1.01, qty: 3.0 },
+        ];
+        let outbound = [
+            Outbound::Accepted { token: 7, order_id: 1, ts },
+            Outbound::Rejected { token: 0, order_id: 3, reason: RejectReason::UnknownOrder },
+            Outbound::Executed { order_id: 1, price: 0.98, qty: 2.5, fee: -0.01, match_number: 9, ts },
+            Outbound::Canceled { order_id: 5, reason: CancelReason::NoLiquidity, ts },
+            Outbound::Replaced { order_id: 4, price: 1.01, qty: 3.0, ts },
+        ];
+
+        let mut buf = Vec::new();
+        for message in &inbound {
+            let start = buf.len();
+            message.encode(&mut buf);
+            assert_eq!(Some(buf.len() - start), Inbound::len(buf[start]));
+        }
+        assert_eq!(Inbound::decode(&buf[..22]), Ok(None));
+        let mut pos = 0;
+        for message in &inbound {
+            let (decoded, len) = Inbound::decode(&buf[pos..]).unwrap().unwrap();
+            assert_eq!(&decoded, message);
+            pos += len;
+        }
+
+        let mut buf = Vec::new();
+        for message in &outbound {
+            let start = buf.len();
+            message.encode(&mut buf);
+            assert_eq!(Some(buf.len() - start), Outbound::len(buf[start]));
+        }
+        let mut pos = 0;
+        for message in &outbound {
+            let (decoded, len) = Outbound::decode(&buf[pos..]).unwrap().unwrap();
+            assert_eq!(&decoded, message);
+            pos += len;
+        }
+        assert_eq!(Outbound::decode(b"Z"), Err(DecodeError::UnknownType(b'Z')));
+    }
+}
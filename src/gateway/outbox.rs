@@ -0,0 +1,103 @@
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+
+// writes queued for a connection before it is dropped as too slow
+pub const OUTBOX_CAPACITY: usize = 1024;
+// peers not accepting a write for this long are dropped
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+
+/// Outgoing side of the connection, written by its own thread
+/// so that slow peers never block the gateway
+pub struct Outbox {
+    queue: Option<SyncSender<Arc<[u8]>>>,
+    stream: TcpStream,
+}
+
+
+impl Outbox {
+    pub fn spawn(stream: TcpStream, capacity: usize) -> io::Result<Self> {
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let (queue, pending) = mpsc::sync_channel::<Arc<[u8]>>(capacity);
+        let mut writer = stream.try_clone()?;
+        thread::spawn(move || {
+            for buf in pending {
+                if writer.write_all(&buf).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+        Ok(Outbox { queue: Some(queue), stream })
+    }
+
+
+    /// Queue bytes for sending, `false` if the peer is too slow or gone
+    pub fn push(&self, buf: Arc<[u8]>) -> bool {
+        self.queue.as_ref().is_some_and(|queue| queue.try_send(buf).is_ok())
+    }
+
+
+    /// Stop queueing, the connection is shut down once queued bytes are sent
+    pub fn close(&mut self) {
+        self.queue = None;
+    }
+
+
+    /// Shut the connection down, queued bytes are dropped
+    pub fn abort(&mut self) {
+        self.queue = None;
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        // unblocks writer waiting for the peer
+        if self.queue.is_some() {
+            self.abort();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn stalled_peer_fills_outbox() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        // peer never reads
+        let _peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let outbox = Outbox::spawn(listener.accept().unwrap().0, 4).unwrap();
+
+        let chunk: Arc<[u8]> = vec![0; 1 << 16].into();
+        let pushed = (0..10_000).take_while(|_| outbox.push(chunk.clone())).count();
+        assert!(pushed < 10_000);
+    }
+
+    #[test]
+    fn close_sends_queued_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut outbox = Outbox::spawn(listener.accept().unwrap().0, 4).unwrap();
+
+        assert!(outbox.push(b"bye".to_vec().into()));
+        outbox.close();
+        assert!(!outbox.push(b"late".to_vec().into()));
+        drop(outbox);
+
+        let mut received = Vec::new();
+        peer.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"bye");
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::engine::domain::{OrderSide, OrderType};
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownType(u8),
+    BadField(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnknownType(msg_type) => write!(f, "unknown message type {:#04x}", msg_type),
+            DecodeError::BadField(field) => write!(f, "bad {} field", field),
+        }
+    }
+}
+
+
+/// Big-endian writer of fixed-length fields
+pub struct Writer<'a> {
+    buf: &'a mut Vec<u8>,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buf: &'a mut Vec<u8>, msg_type: u8) -> Self {
+        buf.push(msg_type);
+        Writer { buf }
+    }
+
+    pub fn u8(self, value: u8) -> Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn u32(self, value: u32) -> Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u64(self, value: u64) -> Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn f64(self, value: f64) -> Self {
+        self.u64(value.to_bits())
+    }
+
+    pub fn side(self, side: OrderSide) -> Self {
+        self.u8(match side {
+            OrderSide::Bid => b'B',
+            OrderSide::Ask => b'S',
+        })
+    }
+
+    pub fn order_type(self, order_type: OrderType) -> Self {
+        self.u8(match order_type {
+            OrderType::Market => b'M',
+            OrderType::Limit => b'L',
+        })
+    }
+
+    /// Nanoseconds since Unix epoch
+    pub fn ts(self, ts: SystemTime) -> Self {
+        let nanos = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        self.u64(nanos.min(u128::from(u64::MAX)) as u64)
+    }
+}
+
+
+/// Big-endian reader of a message with known length
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Reader positioned after the type byte
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 1 }
+    }
+
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&self.buf[self.pos..self.pos + N]);
+        self.pos += N;
+        bytes
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_be_bytes(self.take())
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        u64::from_be_bytes(self.take())
+    }
+
+    pub fn f64(&mut self) -> f64 {
+        f64::from_bits(self.u64())
+    }
+
+    pub fn side(&mut self) -> Result<OrderSide, DecodeError> {
+        match self.u8() {
+            b'B' => Ok(OrderSide::Bid),
+            b'S' => Ok(OrderSide::Ask),
+            _ => Err(DecodeError::BadField("side")),
+        }
+    }
+
+    pub fn order_type(&mut self) -> Result<OrderType, DecodeError> {
+        match self.u8() {
+            b'M' => Ok(OrderType::Market),
+            b'L' => Ok(OrderType::Limit),
+            _ => Err(DecodeError::BadField("order type")),
+        }
+    }
+
+    pub fn ts(&mut self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_nanos(self.u64())
+    }
+}
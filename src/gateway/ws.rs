@@ -0,0 +1,564 @@
+//! JSON-over-WebSocket order entry and market data server
+//!
+//! Every message is a JSON object with a `"type"` field, see README for the schema.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tungstenite::{self, Message, WebSocket};
+
+use crate::engine::domain::OrderSide;
+use crate::engine::market_data::{self, BookDelta, PriceLevel, Trade};
+use crate::engine::orderbook::{Orderbook, OrderProcessingResult};
+use crate::engine::orders::{self, OrderRequest};
+use super::outbox::{OUTBOX_CAPACITY, WRITE_TIMEOUT};
+
+
+// how often connection thread checks for outgoing messages
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Trades,
+    Book,
+}
+
+
+/// Message sent by client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Limit {
+        side: OrderSide,
+        price: f64,
+        qty: f64,
+        #[serde(default)]
+        req_id: Option<u64>,
+    },
+    Market {
+        side: OrderSide,
+        qty: f64,
+        #[serde(default)]
+        req_id: Option<u64>,
+    },
+    Cancel {
+        id: u64,
+        side: OrderSide,
+        #[serde(default)]
+        req_id: Option<u64>,
+    },
+    Amend {
+        id: u64,
+        side: OrderSide,
+        price: f64,
+        qty: f64,
+        #[serde(default)]
+        req_id: Option<u64>,
+    },
+    Subscribe { channels: Vec<Channel> },
+    Unsubscribe { channels: Vec<Channel> },
+    /// One-off book snapshot, full depth if not limited
+    Snapshot {
+        #[serde(default)]
+        depth: Option<usize>,
+    },
+}
+
+
+/// Message sent by server
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// First message of the connection, orders are placed on behalf of `owner`
+    Welcome { owner: u64 },
+    /// Processing events of the client request
+    Result {
+        req_id: Option<u64>,
+        events: OrderProcessingResult,
+    },
+    /// Active subscriptions after subscribe or unsubscribe
+    Subscriptions { channels: Vec<Channel> },
+    Trade(Trade),
+    /// Full book state, `seq` is the number of the last applied delta
+    Snapshot {
+        seq: u64,
+        bids: Vec<PriceLevel>,
+        asks: Vec<PriceLevel>,
+    },
+    /// Level changes of one request, `seq` increments by one
+    Delta { seq: u64, deltas: Vec<BookDelta> },
+    Error { message: String },
+}
+
+
+struct Client {
+    outbox: SyncSender<String>,
+    trades: bool,
+    book: bool,
+}
+
+
+struct State<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    orderbook: Orderbook<Asset>,
+    clients: HashMap<u64, Client>,
+    next_owner: u64,
+    book_seq: u64,
+}
+
+
+/// WebSocket server in front of a single orderbook.
+///
+/// Every connection is served by its own thread and gets its own owner ID.
+/// Clients falling behind by `OUTBOX_CAPACITY` messages are dropped.
+pub struct WsServer<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    state: Arc<Mutex<State<Asset>>>,
+}
+
+
+impl<Asset> Clone for WsServer<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    fn clone(&self) -> Self {
+        WsServer { state: self.state.clone() }
+    }
+}
+
+
+impl<Asset> WsServer<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + 'static,
+{
+    pub fn new(orderbook: Orderbook<Asset>) -> Self {
+        WsServer {
+            state: Arc::new(Mutex::new(State {
+                orderbook,
+                clients: HashMap::new(),
+                next_owner: 1,
+                book_seq: 0,
+            })),
+        }
+    }
+
+
+    /// Access orderbook between requests
+    pub fn with_orderbook<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Orderbook<Asset>) -> R,
+    {
+        f(&mut self.lock().orderbook)
+    }
+
+
+    /// Accept connections forever, serving each one on its own thread
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || {
+                let _ = server.handle_connection(stream);
+            });
+        }
+        Ok(())
+    }
+
+
+    /// Run WebSocket handshake and serve the client until disconnect
+    pub fn handle_connection(&self, stream: TcpStream) -> io::Result<()> {
+        let mut socket = tungstenite::accept(stream)
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+        socket.get_ref().set_write_timeout(Some(WRITE_TIMEOUT))?;
+        socket.get_ref().set_nodelay(true)?;
+
+        let (outbox, outgoing) = mpsc::sync_channel(OUTBOX_CAPACITY);
+        let owner = {
+            let mut state = self.lock();
+            let owner = state.next_owner;
+            state.next_owner += 1;
+            state.clients.insert(owner, Client { outbox, trades: false, book: false });
+            state.send(owner, &ServerMessage::Welcome { owner });
+            owner
+        };
+
+        let result = self.run(owner, &mut socket, &outgoing);
+        self.lock().clients.remove(&owner);
+        result
+    }
+
+
+    fn run(
+        &self,
+        owner: u64,
+        socket: &mut WebSocket<TcpStream>,
+        outgoing: &mpsc::Receiver<String>,
+    ) -> io::Result<()> {
+        loop {
+            loop {
+                match outgoing.try_recv() {
+                    Ok(text) => {
+                        if let Err(err) = socket.send(Message::Text(text)) {
+                            return closed(err);
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // dropped for falling behind
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+
+            match socket.read() {
+                Ok(Message::Text(text)) => self.handle_message(owner, &text),
+                Ok(Message::Binary(_)) => {
+                    let message = "binary messages are not supported".to_string();
+                    self.lock().send(owner, &ServerMessage::Error { message });
+                }
+                // pings are answered by the socket itself
+                Ok(_) => (),
+                Err(tungstenite::Error::Io(ref err)) if timed_out(err) => (),
+                Err(err) => return closed(err),
+            }
+        }
+    }
+
+
+    fn handle_message(&self, owner: u64, text: &str) {
+        let mut state = self.lock();
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(err) => {
+                state.send(owner, &ServerMessage::Error { message: err.to_string() });
+                return;
+            }
+        };
+
+        let order_asset = state.orderbook.order_asset();
+        let price_asset = state.orderbook.price_asset();
+        let ts = SystemTime::now();
+        match message {
+            ClientMessage::Limit { side, price, qty, req_id } => {
+                let request =
+                    orders::new_limit_order_request(owner, order_asset, price_asset, side, price, qty, ts);
+                state.process(owner, req_id, request);
+            }
+            ClientMessage::Market { side, qty, req_id } => {
+                let request =
+                    orders::new_market_order_request(owner, order_asset, price_asset, side, qty, ts);
+                state.process(owner, req_id, request);
+            }
+            ClientMessage::Cancel { id, side, req_id } => {
+                let request = orders::limit_order_cancel_request(owner, id, side);
+                state.process(owner, req_id, request);
+            }
+            ClientMessage::Amend { id, side, price, qty, req_id } => {
+                let request = orders::amend_order_request(owner, id, side, price, qty, ts);
+                state.process(owner, req_id, request);
+            }
+            ClientMessage::Subscribe { channels } => state.subscribe(owner, &channels, true),
+            ClientMessage::Unsubscribe { channels } => state.subscribe(owner, &channels, false),
+            ClientMessage::Snapshot { depth } => state.send_snapshot(owner, depth.unwrap_or(usize::MAX)),
+        }
+    }
+
+
+    fn lock(&self) -> MutexGuard<'_, State<Asset>> {
+        self.state.lock().unwrap()
+    }
+}
+
+
+impl<Asset> State<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Process request, reply to the sender and publish market data
+    fn process(&mut self, owner: u64, req_id: Option<u64>, request: OrderRequest<Asset>) {
+        // skip book diffing if nobody listens
+        let book_watched = self.clients.values().any(|client| client.book);
+        let (events, deltas) = if book_watched {
+            let (events, deltas) = self.orderbook.process_order_with_deltas(request);
+            (events, Some(deltas))
+        } else {
+            (self.orderbook.process_order(request), None)
+        };
+        let trades = market_data::trades(&events);
+        self.send(owner, &ServerMessage::Result { req_id, events });
+
+        for trade in trades {
+            self.broadcast(|client| client.trades, &ServerMessage::Trade(trade));
+        }
+        if let Some(deltas) = deltas {
+            if !deltas.is_empty() {
+                self.book_seq += 1;
+                let seq = self.book_seq;
+                self.broadcast(|client| client.book, &ServerMessage::Delta { seq, deltas });
+            }
+        }
+    }
+
+
+    fn subscribe(&mut self, owner: u64, channels: &[Channel], subscribe: bool) {
+        let (channels, new_book) = match self.clients.get_mut(&owner) {
+            Some(client) => {
+                let book_before = client.book;
+                for channel in channels {
+                    match *channel {
+                        Channel::Trades => client.trades = subscribe,
+                        Channel::Book => client.book = subscribe,
+                    }
+                }
+                (subscriptions(client), client.book && !book_before)
+            }
+            None => return,
+        };
+
+        self.send(owner, &ServerMessage::Subscriptions { channels });
+        // new book subscribers start from the snapshot
+        if new_book {
+            self.send_snapshot(owner, usize::MAX);
+        }
+    }
+
+
+    fn send_snapshot(&mut self, owner: u64, max_levels: usize) {
+        let depth = self.orderbook.depth(max_levels);
+        self.send(owner, &ServerMessage::Snapshot {
+            seq: self.book_seq,
+            bids: depth.bids,
+            asks: depth.asks,
+        });
+    }
+
+
+    /// Queue message for the client, clients with full outbox are dropped
+    fn send(&mut self, owner: u64, message: &ServerMessage) {
+        let failed = match self.clients.get(&owner) {
+            Some(client) => client.outbox.try_send(encode(message)).is_err(),
+            None => false,
+        };
+        if failed {
+            self.clients.remove(&owner);
+        }
+    }
+
+
+    fn broadcast<F>(&mut self, subscribed: F, message: &ServerMessage)
+    where
+        F: Fn(&Client) -> bool,
+    {
+        let text = encode(message);
+        self.clients.retain(|_, client| !subscribed(client) || client.outbox.try_send(text.clone()).is_ok());
+    }
+}
+
+
+fn encode(message: &ServerMessage) -> String {
+    serde_json::to_string(message).expect("server messages are always serializable")
+}
+
+
+fn subscriptions(client: &Client) -> Vec<Channel> {
+    let mut channels = Vec::new();
+    if client.trades {
+        channels.push(Channel::Trades);
+    }
+    if client.book {
+        channels.push(Channel::Book);
+    }
+    channels
+}
+
+
+/// Read timeout, socket stays usable
+fn timed_out(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+}
+
+
+/// Closed connection is not an error
+fn closed(err: tungstenite::Error) -> io::Result<()> {
+    match err {
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => Ok(()),
+        tungstenite::Error::Io(err) => Err(err),
+        err => Err(io::Error::other(err.to_string())),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::orderbook::{Failed, Success};
+    use serde_json::Value;
+    use std::net::SocketAddr;
+    use std::time::Instant;
+    use crate::engine::orderbook::test::Asset;
+
+    struct TestClient {
+        socket: WebSocket<TcpStream>,
+    }
+
+    impl TestClient {
+        fn connect(addr: SocketAddr) -> Self {
+            let stream = TcpStream::connect(addr).unwrap();
+            let (socket, _) = tungstenite::client(format!("ws://{}/", addr), stream).unwrap();
+            socket.get_ref().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+            TestClient { socket }
+        }
+
+        fn send(&mut self, json: &str) {
+            self.socket.send(Message::Text(json.to_string())).unwrap();
+        }
+
+        fn recv_text(&mut self) -> String {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                match self.socket.read() {
+                    Ok(Message::Text(text)) => return text,
+                    Ok(_) => (),
+                    Err(tungstenite::Error::Io(ref err)) if timed_out(err) => (),
+                    Err(err) => panic!("{}", err),
+                }
+            }
+            panic!("no message received");
+        }
+
+        fn recv(&mut self) -> ServerMessage {
+            serde_json::from_str(&self.recv_text()).unwrap()
+        }
+    }
+
+    #[test]
+    fn orders_and_market_data() {
+        let server = WsServer::new(Orderbook::new(Asset::BTC, Asset::USD));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = server.clone();
+        thread::spawn(move || handle.serve(listener));
+
+        let mut maker = TestClient::connect(addr);
+        assert!(matches!(maker.recv(), ServerMessage::Welcome { owner: 1 }));
+        let mut taker = TestClient::connect(addr);
+        assert!(matches!(taker.recv(), ServerMessage::Welcome { owner: 2 }));
+
+        taker.send(r#"{"type": "subscribe", "channels": ["trades", "book"]}"#);
+        match taker.recv() {
+            ServerMessage::Subscriptions { channels } => {
+                assert_eq!(channels, [Channel::Trades, Channel::Book]);
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+        match taker.recv() {
+            ServerMessage::Snapshot { seq, bids, asks } => {
+                assert_eq!((seq, bids.len(), asks.len()), (0, 0, 0));
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+
+        maker.send(r#"{"type": "limit", "side": "Ask", "price": 10.5, "qty": 2.0, "req_id": 7}"#);
+        let result: Value = serde_json::from_str(&maker.recv_text()).unwrap();
+        assert_eq!(result["type"], "result");
+        assert_eq!(result["req_id"], 7);
+        assert_eq!(result["events"][0]["Ok"]["Accepted"]["id"], 1);
+        match taker.recv() {
+            ServerMessage::Delta { seq, deltas } => {
+                assert_eq!(seq, 1);
+                assert_eq!(deltas, [BookDelta { side: OrderSide::Ask, price: 10.5, qty: 2.0 }]);
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+
+        taker.send(r#"{"type": "market", "side": "Bid", "qty": 1.5}"#);
+        match taker.recv() {
+            ServerMessage::Result { req_id: None, events } => {
+                assert!(matches!(events[1], Ok(Success::Filled { order_id: 2, qty, .. }) if qty == 1.5));
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+        match taker.recv() {
+            ServerMessage::Trade(trade) => {
+                assert_eq!((trade.maker_order_id, trade.price, trade.qty), (1, 10.5, 1.5));
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+        assert!(matches!(taker.recv(), ServerMessage::Delta { seq: 2, .. }));
+
+        // orders of other owners can't be cancelled
+        taker.send(r#"{"type": "cancel", "id": 1, "side": "Ask", "req_id": 8}"#);
+        match taker.recv() {
+            ServerMessage::Result { req_id: Some(8), events } => {
+                assert!(matches!(events[0], Err(Failed::OrderNotFound(1))));
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+
+        maker.send(r#"{"type": "amend", "id": 1, "side": "Ask", "price": 10.4, "qty": 1.0}"#);
+        assert!(matches!(maker.recv(), ServerMessage::Result { .. }));
+        match taker.recv() {
+            ServerMessage::Delta { seq, deltas } => {
+                assert_eq!(seq, 3);
+                assert_eq!(deltas.len(), 2);
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+
+        taker.send(r#"{"type": "unsubscribe", "channels": ["book"]}"#);
+        match taker.recv() {
+            ServerMessage::Subscriptions { channels } => assert_eq!(channels, [Channel::Trades]),
+            message => panic!("unexpected {:?}", message),
+        }
+        taker.send(r#"{"type": "snapshot", "depth": 1}"#);
+        match taker.recv() {
+            ServerMessage::Snapshot { seq, asks, .. } => {
+                assert_eq!(seq, 3);
+                assert_eq!(asks, [PriceLevel { price: 10.4, qty: 1.0, orders: 1 }]);
+            }
+            message => panic!("unexpected {:?}", message),
+        }
+
+        taker.send(r#"{"type": "limit", "side": "Bid"}"#);
+        assert!(matches!(taker.recv(), ServerMessage::Error { .. }));
+        assert_eq!(server.with_orderbook(|book| book.depth(1)).asks[0].price, 10.4);
+    }
+
+    #[test]
+    fn stalled_client_dropped() {
+        let server = WsServer::new(Orderbook::new(Asset::BTC, Asset::USD));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = server.clone();
+        thread::spawn(move || handle.serve(listener));
+
+        // subscribes and never reads again
+        let mut client = TestClient::connect(addr);
+        let owner = match client.recv() {
+            ServerMessage::Welcome { owner } => owner,
+            message => panic!("unexpected {:?}", message),
+        };
+        client.send(r#"{"type": "subscribe", "channels": ["trades"]}"#);
+        assert!(matches!(client.recv(), ServerMessage::Subscriptions { .. }));
+
+        let message = ServerMessage::Error { message: "x".repeat(1 << 12) };
+        let dropped = (0..100_000).any(|_| {
+            let mut state = server.lock();
+            state.broadcast(|client| client.trades, &message);
+            !state.clients.contains_key(&owner)
+        });
+        assert!(dropped);
+    }
+}
@@ -1,23 +1,52 @@
-
 mod engine;
-
-pub use engine::domain::OrderSide;
+mod gateway;
+mod runtime;
+mod tools;
+
+pub use engine::accounts::{Accounts, Balance};
+pub use engine::clock::{Clock, ManualClock, RequestClock, SystemClock};
+pub use engine::domain::{Order, OrderSide, OrderType};
+pub use engine::exchange::{Exchange, ExchangeError, ExchangeResult, Instrument, SequencedEvent};
+pub use engine::fees::{FeeAsset, FeeRates, FeeSchedule, FeeTier};
+pub use engine::journal::{Journal, JournalCommand, JournalEntry};
+pub use engine::market_data::{self, BookDelta, Depth, PriceLevel, Trade};
 pub use engine::orderbook::{Orderbook, OrderProcessingResult, Success, Failed};
+pub use engine::order_queues::OrderQueue;
 pub use engine::orders;
+pub use engine::positions::{Position, Positions};
+pub use engine::risk::{RiskChecks, RiskLimits};
+pub use engine::snapshot::{AssetCode, SnapshotError};
+pub use engine::throttle::{RateLimit, RequestKind, Throttle, ThrottleConfig};
+pub use engine::trade_history::{Candle, TradeHistory, TradeStats};
+pub use gateway::{binary, fix, itch, ouch};
+#[cfg(feature = "grpc")]
+pub use gateway::grpc;
+#[cfg(feature = "ws")]
+pub use gateway::ws;
+pub use runtime::{BookRuntime, OrderSubmitter, ProcessedRequest, RuntimeConfig, SubmitError};
+pub use runtime::ring::RingBuffer;
+#[cfg(feature = "async")]
+pub use runtime::async_handle::{EngineStopped, MarketEvent, OrderbookHandle, SubmitFuture};
+#[cfg(feature = "replay")]
+pub use tools::backtest;
+#[cfg(feature = "generator")]
+pub use tools::generator;
+#[cfg(feature = "replay")]
+pub use tools::lobster;
+pub use tools::repl;
+#[cfg(feature = "replay")]
+pub use tools::replay;
 
 
 #[cfg(test)]
+#[allow(clippy::match_like_matches_macro, clippy::upper_case_acronyms)]
 mod tests {
     use super::*;
 
     const FLOAT_THRESHOLD: f64 = 1e-6;
 
     fn match_float(expected: f64, get: f64) -> bool {
-        if (expected - get).abs() < FLOAT_THRESHOLD {
-            true
-        } else {
-            false
-        }
+        (expected - get).abs() < FLOAT_THRESHOLD
     }
 
     #[derive(PartialEq, Eq, Debug, Copy, Clone)]
@@ -47,6 +76,7 @@ mod tests {
         let price_asset = parse_asset("USD").unwrap();
 
         let order1 = orders::new_market_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -80,6 +110,7 @@ mod tests {
         let price_asset = parse_asset("USD").unwrap();
 
         let order1 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -89,6 +120,7 @@ mod tests {
         );
 
         let order2 = orders::new_market_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Ask,
@@ -136,6 +168,7 @@ mod tests {
         let price_asset = parse_asset("USD").unwrap();
 
         let order1 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -145,6 +178,7 @@ mod tests {
         );
 
         let order2 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -154,6 +188,7 @@ mod tests {
         );
 
         let order3 = orders::new_market_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Ask,
@@ -220,6 +255,7 @@ mod tests {
         let price_asset = parse_asset("USD").unwrap();
 
         let order1 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -250,6 +286,7 @@ mod tests {
         let price_asset = parse_asset("USD").unwrap();
 
         let order1 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -259,6 +296,7 @@ mod tests {
         );
 
         let order2 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Ask,
@@ -307,6 +345,7 @@ mod tests {
         let price_asset = parse_asset("USD").unwrap();
 
         let order1 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -316,6 +355,7 @@ mod tests {
         );
 
         let order2 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Ask,
@@ -354,6 +394,7 @@ mod tests {
         }
 
         let order3 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Ask,
@@ -403,6 +444,7 @@ mod tests {
         let price_asset = parse_asset("USD").unwrap();
 
         let order1 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -415,6 +457,7 @@ mod tests {
         assert_eq!(orderbook.current_spread(), None);
 
         let order2 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Ask,
@@ -424,6 +467,7 @@ mod tests {
         );
 
         let order3 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Ask,
@@ -440,6 +484,7 @@ mod tests {
 
         // wider spread
         let order4 = orders::new_limit_order_request(
+            1,
             order_asset,
             price_asset,
             OrderSide::Bid,
@@ -0,0 +1,218 @@
+//! Async front-end of the orderbook
+//!
+//! Unlike a Tokio task, the orderbook is owned by a dedicated OS thread: matching
+//! never occupies runtime workers and handles can be created outside of a runtime.
+//! As the thread can stop, e.g. on a panic while processing, `submit` resolves to
+//! `Result<OrderProcessingResult, EngineStopped>` rather than the bare result.
+
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::engine::market_data::{self, BookDelta, Trade};
+use crate::engine::orderbook::{Orderbook, OrderProcessingResult};
+use crate::engine::orders::OrderRequest;
+
+
+const DEFAULT_EVENTS_CAPACITY: usize = 1024;
+
+
+/// Market data published by orderbook thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketEvent {
+    Trade(Trade),
+    BookDelta(BookDelta),
+}
+
+
+/// Orderbook thread is not running anymore
+#[derive(Debug, PartialEq, Eq)]
+pub struct EngineStopped;
+
+impl fmt::Display for EngineStopped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "orderbook thread stopped")
+    }
+}
+
+
+struct Command<Asset>
+where
+    Asset: Debug + Clone,
+{
+    request: OrderRequest<Asset>,
+    reply: oneshot::Sender<OrderProcessingResult>,
+}
+
+
+/// Async handle to the orderbook, owned by a dedicated thread.
+///
+/// Handles are cheap to clone, orderbook thread stops when all of them are dropped.
+/// Requests are processed outside of the async runtime, so the thread
+/// never blocks its workers.
+pub struct OrderbookHandle<Asset>
+where
+    Asset: Debug + Clone,
+{
+    requests: mpsc::UnboundedSender<Command<Asset>>,
+    events: broadcast::Sender<MarketEvent>,
+}
+
+
+impl<Asset> Clone for OrderbookHandle<Asset>
+where
+    Asset: Debug + Clone,
+{
+    fn clone(&self) -> Self {
+        OrderbookHandle {
+            requests: self.requests.clone(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+
+impl<Asset> OrderbookHandle<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + 'static,
+{
+    /// Move orderbook to its own thread
+    pub fn spawn(orderbook: Orderbook<Asset>) -> Self {
+        Self::spawn_with_capacity(orderbook, DEFAULT_EVENTS_CAPACITY)
+    }
+
+
+    /// Move orderbook to its own thread, keeping up to `events_capacity`
+    /// market events for slow subscribers
+    pub fn spawn_with_capacity(orderbook: Orderbook<Asset>, events_capacity: usize) -> Self {
+        let (requests, requests_rx) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(events_capacity);
+
+        let task_events = events.clone();
+        thread::spawn(move || run_book(orderbook, requests_rx, &task_events));
+
+        OrderbookHandle { requests, events }
+    }
+
+
+    /// Send request to orderbook and wait for processing result
+    pub fn submit(&self, request: OrderRequest<Asset>) -> SubmitFuture {
+        let (reply, reply_rx) = oneshot::channel();
+        match self.requests.send(Command { request, reply }) {
+            Ok(()) => SubmitFuture { reply: Some(reply_rx) },
+            Err(_) => SubmitFuture { reply: None },
+        }
+    }
+
+
+    /// Subscribe to trades and book changes, produced after subscription
+    pub fn subscribe(&self) -> broadcast::Receiver<MarketEvent> {
+        self.events.subscribe()
+    }
+}
+
+
+/// Result of the submitted request
+pub struct SubmitFuture {
+    reply: Option<oneshot::Receiver<OrderProcessingResult>>,
+}
+
+impl Future for SubmitFuture {
+    type Output = Result<OrderProcessingResult, EngineStopped>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.reply {
+            Some(ref mut reply) => Pin::new(reply).poll(cx).map(|r| r.map_err(|_| EngineStopped)),
+            None => Poll::Ready(Err(EngineStopped)),
+        }
+    }
+}
+
+
+/// Processing loop of the orderbook thread
+fn run_book<Asset>(
+    mut orderbook: Orderbook<Asset>,
+    mut requests: mpsc::UnboundedReceiver<Command<Asset>>,
+    events: &broadcast::Sender<MarketEvent>,
+) where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    while let Some(command) = requests.blocking_recv() {
+        // skip market data preparation if nobody listens
+        if events.receiver_count() == 0 {
+            let _ = command.reply.send(orderbook.process_order(command.request));
+            continue;
+        }
+
+        let (result, deltas) = orderbook.process_order_with_deltas(command.request);
+        for trade in market_data::trades(&result) {
+            let _ = events.send(MarketEvent::Trade(trade));
+        }
+        for delta in deltas {
+            let _ = events.send(MarketEvent::BookDelta(delta));
+        }
+
+        let _ = command.reply.send(result);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::domain::OrderSide;
+    use crate::engine::orderbook::Success;
+    use crate::engine::orders;
+    use std::time::SystemTime;
+    use tokio::runtime;
+    use crate::engine::orderbook::test::Asset;
+
+    #[test]
+    fn submit_and_subscribe() {
+        let rt = runtime::Builder::new_current_thread().build().unwrap();
+        let handle = OrderbookHandle::spawn(Orderbook::new(Asset::BTC, Asset::USD));
+        let mut events = handle.subscribe();
+
+        let result = rt.block_on(handle.submit(orders::new_limit_order_request(
+            1,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Ask,
+            1.02,
+            2.0,
+            SystemTime::now(),
+        ))).unwrap();
+        assert!(matches!(result[0], Ok(Success::Accepted { id: 1, .. })));
+
+        let result = rt.block_on(handle.clone().submit(orders::new_market_order_request(
+            1,
+            Asset::BTC,
+            Asset::USD,
+            OrderSide::Bid,
+            0.5,
+            SystemTime::now(),
+        ))).unwrap();
+        assert_eq!(result.len(), 3);
+
+        assert_eq!(
+            events.try_recv(),
+            Ok(MarketEvent::BookDelta(BookDelta { side: OrderSide::Ask, price: 1.02, qty: 2.0 }))
+        );
+        match events.try_recv() {
+            Ok(MarketEvent::Trade(trade)) => {
+                assert_eq!((trade.taker_order_id, trade.maker_order_id), (2, 1));
+                assert_eq!((trade.price, trade.qty), (1.02, 0.5));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(
+            events.try_recv(),
+            Ok(MarketEvent::BookDelta(BookDelta { side: OrderSide::Ask, price: 1.02, qty: 1.5 }))
+        );
+        assert!(events.try_recv().is_err());
+    }
+}
@@ -0,0 +1,446 @@
+pub mod ring;
+#[cfg(feature = "async")]
+pub mod async_handle;
+
+use std::fmt::Debug;
+use std::hint;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use crate::engine::orderbook::{Orderbook, OrderProcessingResult};
+use crate::engine::orders::OrderRequest;
+use self::ring::RingBuffer;
+
+
+const DEFAULT_QUEUE_CAPACITY: usize = 4096;
+const SPINS_BEFORE_YIELD: u32 = 100;
+
+
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    /// Max number of requests waiting for processing
+    pub input_capacity: usize,
+    /// Max number of results waiting for consumer
+    pub output_capacity: usize,
+    /// Pin orderbook thread to the core with given ID, requires `pinning` feature
+    pub core_id: Option<usize>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig {
+            input_capacity: DEFAULT_QUEUE_CAPACITY,
+            output_capacity: DEFAULT_QUEUE_CAPACITY,
+            core_id: None,
+        }
+    }
+}
+
+
+/// Request rejected by runtime, returned back to sender
+#[derive(Debug)]
+pub enum SubmitError<Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// Input queue is full, try again later
+    Backpressure(OrderRequest<Asset>),
+    /// Runtime is shutting down
+    Stopped(OrderRequest<Asset>),
+}
+
+
+/// Processing result with the ticket issued on submit
+#[derive(Debug)]
+pub struct ProcessedRequest {
+    pub ticket: u64,
+    pub result: OrderProcessingResult,
+}
+
+
+struct Shared<Asset>
+where
+    Asset: Debug + Clone,
+{
+    input: RingBuffer<(u64, OrderRequest<Asset>)>,
+    output: RingBuffer<ProcessedRequest>,
+    running: AtomicBool,
+    // submits between the running check and the push
+    submitting: AtomicUsize,
+    next_ticket: AtomicU64,
+}
+
+
+/// Orderbook running on its own thread.
+///
+/// Gateways send requests through `OrderSubmitter` handles,
+/// processing results are collected from the output queue.
+pub struct BookRuntime<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + 'static,
+{
+    shared: Arc<Shared<Asset>>,
+    worker: Option<JoinHandle<(Orderbook<Asset>, Vec<ProcessedRequest>)>>,
+    pinned: bool,
+}
+
+
+impl<Asset> BookRuntime<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + 'static,
+{
+    /// Move orderbook to the new thread and start processing
+    pub fn start(orderbook: Orderbook<Asset>, config: RuntimeConfig) -> Self {
+        let shared = Arc::new(Shared {
+            input: RingBuffer::with_capacity(config.input_capacity),
+            output: RingBuffer::with_capacity(config.output_capacity),
+            running: AtomicBool::new(true),
+            submitting: AtomicUsize::new(0),
+            next_ticket: AtomicU64::new(1),
+        });
+
+        let worker_shared = shared.clone();
+        let (pinned_tx, pinned_rx) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            let _ = pinned_tx.send(config.core_id.is_some_and(pin_current_thread));
+            run_book(orderbook, &worker_shared)
+        });
+        let pinned = pinned_rx.recv().unwrap_or(false);
+
+        BookRuntime {
+            shared,
+            worker: Some(worker),
+            pinned,
+        }
+    }
+
+
+    /// Orderbook thread is pinned to `RuntimeConfig::core_id`.
+    ///
+    /// False if no core was requested, the core does not exist
+    /// or `pinning` feature is disabled.
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+
+    /// Handle for sending requests, could be cloned between threads
+    pub fn submitter(&self) -> OrderSubmitter<Asset> {
+        OrderSubmitter { shared: self.shared.clone() }
+    }
+
+
+    /// Get next processing result, if any
+    pub fn try_recv(&self) -> Option<ProcessedRequest> {
+        self.shared.output.pop()
+    }
+
+
+    /// Number of requests waiting for processing
+    pub fn pending_requests(&self) -> usize {
+        self.shared.input.len()
+    }
+
+
+    /// Stop accepting requests, process the queued ones and stop the thread.
+    ///
+    /// Returns orderbook together with results not yet collected.
+    pub fn shutdown(mut self) -> (Orderbook<Asset>, Vec<ProcessedRequest>) {
+        self.stop()
+            .expect("orderbook thread is running")
+            .expect("orderbook thread panicked")
+    }
+
+
+    fn stop(&mut self) -> Option<thread::Result<(Orderbook<Asset>, Vec<ProcessedRequest>)>> {
+        self.shared.running.store(false, Ordering::SeqCst);
+        // submits which have seen the runtime running finish their push,
+        // later ones are rejected, so nothing lands after the input is drained
+        while self.shared.submitting.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+
+        let worker = self.worker.take()?;
+        let (mut orderbook, overflow) = match worker.join() {
+            Ok(stopped) => stopped,
+            Err(panic) => return Some(Err(panic)),
+        };
+
+        // results in output queue go first
+        let mut results = Vec::with_capacity(self.shared.output.len() + overflow.len());
+        while let Some(processed) = self.shared.output.pop() {
+            results.push(processed);
+        }
+        results.extend(overflow);
+
+        // requests which raced with the stop signal
+        while let Some((ticket, request)) = self.shared.input.pop() {
+            results.push(ProcessedRequest {
+                ticket,
+                result: orderbook.process_order(request),
+            });
+        }
+        Some(Ok((orderbook, results)))
+    }
+}
+
+
+impl<Asset> Drop for BookRuntime<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq + Send + 'static,
+{
+    fn drop(&mut self) {
+        // panic of the orderbook thread is not propagated from drop
+        let _ = self.stop();
+    }
+}
+
+
+/// Sending side of the runtime input queue
+pub struct OrderSubmitter<Asset>
+where
+    Asset: Debug + Clone,
+{
+    shared: Arc<Shared<Asset>>,
+}
+
+impl<Asset> Clone for OrderSubmitter<Asset>
+where
+    Asset: Debug + Clone,
+{
+    fn clone(&self) -> Self {
+        OrderSubmitter { shared: self.shared.clone() }
+    }
+}
+
+impl<Asset> OrderSubmitter<Asset>
+where
+    Asset: Debug + Clone,
+{
+    /// Queue request without blocking, returns ticket to match the result
+    pub fn try_submit(&self, request: OrderRequest<Asset>) -> Result<u64, SubmitError<Asset>> {
+        // announced before the check, so that shutdown waits for the push
+        self.shared.submitting.fetch_add(1, Ordering::SeqCst);
+        let result = if self.shared.running.load(Ordering::SeqCst) {
+            let ticket = self.shared.next_ticket.fetch_add(1, Ordering::Relaxed);
+            match self.shared.input.push((ticket, request)) {
+                Ok(()) => Ok(ticket),
+                Err((_, request)) => Err(SubmitError::Backpressure(request)),
+            }
+        } else {
+            Err(SubmitError::Stopped(request))
+        };
+        self.shared.submitting.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+}
+
+
+#[cfg(feature = "pinning")]
+fn pin_current_thread(core_id: usize) -> bool {
+    core_affinity::set_for_current(core_affinity::CoreId { id: core_id })
+}
+
+
+#[cfg(not(feature = "pinning"))]
+fn pin_current_thread(_core_id: usize) -> bool {
+    false
+}
+
+
+/// Processing loop of the orderbook thread
+fn run_book<Asset>(
+    mut orderbook: Orderbook<Asset>,
+    shared: &Shared<Asset>,
+) -> (Orderbook<Asset>, Vec<ProcessedRequest>)
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    // results which could not be published after stop signal
+    let mut overflow = Vec::new();
+    let mut idle_spins = 0;
+
+    loop {
+        match shared.input.pop() {
+            Some((ticket, request)) => {
+                idle_spins = 0;
+                let mut processed = ProcessedRequest {
+                    ticket,
+                    result: orderbook.process_order(request),
+                };
+
+                // wait for consumer while it is alive
+                while let Err(rejected) = shared.output.push(processed) {
+                    if !shared.running.load(Ordering::SeqCst) {
+                        overflow.push(rejected);
+                        break;
+                    }
+                    processed = rejected;
+                    thread::yield_now();
+                }
+            }
+
+            None => {
+                if !shared.running.load(Ordering::SeqCst) && shared.input.is_empty() {
+                    return (orderbook, overflow);
+                }
+                idle_spins += 1;
+                if idle_spins < SPINS_BEFORE_YIELD {
+                    hint::spin_loop();
+                } else {
+                    thread::yield_now();
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::domain::OrderSide;
+    use crate::engine::orderbook::Success;
+    use crate::engine::orders;
+    use std::time::SystemTime;
+    use crate::engine::orderbook::test::Asset;
+
+    #[test]
+    fn process_from_many_gateways() {
+        let runtime = BookRuntime::start(Orderbook::new(Asset::BTC, Asset::USD), RuntimeConfig {
+            input_capacity: 8,
+            output_capacity: 8,
+            core_id: None,
+        });
+
+        let gateways: Vec<_> = (0..4)
+            .map(|n| {
+                let submitter = runtime.submitter();
+                thread::spawn(move || {
+                    let mut tickets = Vec::new();
+                    for _ in 0..25 {
+                        let mut request = orders::new_limit_order_request(
+                            1,
+                            Asset::BTC,
+                            Asset::USD,
+                            if n % 2 == 0 { OrderSide::Bid } else { OrderSide::Ask },
+                            if n % 2 == 0 { 1.0 } else { 2.0 },
+                            1.0,
+                            SystemTime::now(),
+                        );
+                        loop {
+                            match submitter.try_submit(request) {
+                                Ok(ticket) => break tickets.push(ticket),
+                                Err(SubmitError::Backpressure(rejected)) => {
+                                    request = rejected;
+                                    thread::yield_now();
+                                }
+                                Err(SubmitError::Stopped(_)) => panic!("runtime stopped"),
+                            }
+                        }
+                    }
+                    tickets
+                })
+            })
+            .collect();
+
+        let mut results = Vec::new();
+        while results.len() < 100 {
+            match runtime.try_recv() {
+                Some(processed) => results.push(processed),
+                None => thread::yield_now(),
+            }
+        }
+
+        let mut submitted: Vec<u64> = gateways
+            .into_iter()
+            .flat_map(|gateway| gateway.join().unwrap())
+            .collect();
+        let mut processed: Vec<u64> = results.iter().map(|r| r.ticket).collect();
+        submitted.sort();
+        processed.sort();
+        assert_eq!(submitted, processed);
+        assert!(results.iter().all(|r| matches!(r.result[0], Ok(Success::Accepted { .. }))));
+
+        let (mut orderbook, rest) = runtime.shutdown();
+        assert!(rest.is_empty());
+        assert_eq!(orderbook.current_spread(), Some((1.0, 2.0)));
+    }
+
+    #[test]
+    fn shutdown_drains_input() {
+        let runtime = BookRuntime::start(Orderbook::new(Asset::BTC, Asset::USD), RuntimeConfig {
+            input_capacity: 64,
+            output_capacity: 2,
+            core_id: None,
+        });
+        let submitter = runtime.submitter();
+
+        for _ in 0..10 {
+            submitter
+                .try_submit(orders::new_limit_order_request(
+                    1,
+                    Asset::BTC,
+                    Asset::USD,
+                    OrderSide::Bid,
+                    1.0,
+                    1.0,
+                    SystemTime::now(),
+                ))
+                .unwrap();
+        }
+
+        let (_, results) = runtime.shutdown();
+        let tickets: Vec<u64> = results.iter().map(|r| r.ticket).collect();
+        assert_eq!(tickets, (1..11).collect::<Vec<u64>>());
+
+        match submitter.try_submit(orders::limit_order_cancel_request(1, 1, OrderSide::Bid)) {
+            Err(SubmitError::Stopped(_)) => (),
+            other => panic!("unexpected submit result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn submit_racing_shutdown() {
+        let runtime = BookRuntime::start(Orderbook::new(Asset::BTC, Asset::USD), RuntimeConfig {
+            input_capacity: 1024,
+            output_capacity: 1 << 16,
+            core_id: None,
+        });
+        assert!(!runtime.is_pinned());
+
+        let gateways: Vec<_> = (0..4)
+            .map(|_| {
+                let submitter = runtime.submitter();
+                thread::spawn(move || {
+                    let mut tickets = Vec::new();
+                    loop {
+                        let request = orders::limit_order_cancel_request(1, 1, OrderSide::Bid);
+                        match submitter.try_submit(request) {
+                            Ok(ticket) => tickets.push(ticket),
+                            Err(SubmitError::Backpressure(_)) => thread::yield_now(),
+                            Err(SubmitError::Stopped(_)) => return tickets,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        while runtime.shared.next_ticket.load(Ordering::Relaxed) < 1000 {
+            thread::yield_now();
+        }
+        let (_, rest) = runtime.shutdown();
+
+        // every accepted ticket is processed
+        let mut submitted: Vec<u64> = gateways
+            .into_iter()
+            .flat_map(|gateway| gateway.join().unwrap())
+            .collect();
+        let mut processed: Vec<u64> = rest.iter().map(|r| r.ticket).collect();
+        submitted.sort();
+        processed.sort();
+        assert_eq!(submitted, processed);
+    }
+}
@@ -0,0 +1,223 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+/// Slot of the ring with its own sequence stamp.
+///
+/// Stamp tells whose turn is it to access the slot:
+/// equal to position - slot is free for producer,
+/// position + 1 - slot holds value for consumer.
+struct Slot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+
+/// Bounded lock-free queue, safe for many producers and consumers.
+///
+/// Capacity is rounded up to the power of two.
+pub struct RingBuffer<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    // keep producer and consumer positions on separate cache lines
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+
+impl<T> RingBuffer<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots: Vec<Slot<T>> = (0..capacity)
+            .map(|pos| {
+                Slot {
+                    stamp: AtomicUsize::new(pos),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                }
+            })
+            .collect();
+
+        RingBuffer {
+            slots: slots.into_boxed_slice(),
+            mask: capacity - 1,
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+
+
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+
+    /// Add value to the queue, returns it back if queue is full
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            if stamp == pos {
+                // slot is free, try to occupy it
+                match self.tail.0.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).as_mut_ptr().write(value) };
+                        slot.stamp.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if (stamp as isize).wrapping_sub(pos as isize) < 0 {
+                // slot still holds value from the previous lap
+                return Err(value);
+            } else {
+                pos = self.tail.0.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+
+    /// Take the oldest value from the queue
+    pub fn pop(&self) -> Option<T> {
+        let mut pos = self.head.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            let expected = pos.wrapping_add(1);
+
+            if stamp == expected {
+                match self.head.0.compare_exchange_weak(
+                    pos,
+                    expected,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).as_ptr().read() };
+                        // free slot for the next lap of producers
+                        slot.stamp.store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if (stamp as isize).wrapping_sub(expected as isize) < 0 {
+                // nothing written yet
+                return None;
+            } else {
+                pos = self.head.0.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+
+    /// Approximate number of queued values
+    pub fn len(&self) -> usize {
+        let tail = self.tail.0.load(Ordering::Relaxed);
+        let head = self.head.0.load(Ordering::Relaxed);
+        tail.wrapping_sub(head).min(self.capacity())
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn fifo_until_full() {
+        let ring = RingBuffer::with_capacity(3);
+        assert_eq!(ring.capacity(), 4);
+
+        for value in 0..4 {
+            assert_eq!(ring.push(value), Ok(()));
+        }
+        assert_eq!(ring.push(4), Err(4));
+        assert_eq!(ring.len(), 4);
+
+        assert_eq!(ring.pop(), Some(0));
+        assert_eq!(ring.push(4), Ok(()));
+        for value in 1..5 {
+            assert_eq!(ring.pop(), Some(value));
+        }
+        assert_eq!(ring.pop(), None);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn many_producers() {
+        let ring = Arc::new(RingBuffer::with_capacity(64));
+        let producers: Vec<_> = (0..4u64)
+            .map(|producer| {
+                let ring = ring.clone();
+                thread::spawn(move || {
+                    for n in 0..1000u64 {
+                        let mut value = producer * 1000 + n;
+                        while let Err(rejected) = ring.push(value) {
+                            value = rejected;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut last_seen = [None; 4];
+        let mut received = 0;
+        while received < 4000 {
+            if let Some(value) = ring.pop() {
+                // values of every producer arrive in order
+                let producer = (value / 1000) as usize;
+                assert!(last_seen[producer].is_none_or(|last| last < value));
+                last_seen[producer] = Some(value);
+                received += 1;
+            } else {
+                thread::yield_now();
+            }
+        }
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn drop_queued_values() {
+        let value = Arc::new(());
+        {
+            let ring = RingBuffer::with_capacity(4);
+            ring.push(value.clone()).unwrap();
+            ring.push(value.clone()).unwrap();
+            assert_eq!(Arc::strong_count(&value), 3);
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
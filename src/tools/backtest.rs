@@ -0,0 +1,549 @@
+//! Strategy backtesting on replayed order flow
+//!
+//! Strategy orders rest in the same book as the replayed orders, so a passive order
+//! is filled only after the orders ahead of it in the queue. Strategy requests reach
+//! the engine after the order entry latency, requests still in flight after the last
+//! replayed event are dropped.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime};
+
+use crate::engine::domain::{OrderSide, OrderType};
+use crate::engine::market_data::{self, Depth, Trade};
+use crate::engine::orderbook::{Failed, Orderbook, OrderProcessingResult, Success};
+use crate::engine::orders::{self, OrderRequest};
+use super::replay::{OrderEvent, Replay, ReplayConfig, ReplayError};
+
+
+/// Trading logic, callbacks may send requests through the context
+pub trait Strategy<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Book after every replayed event
+    fn on_book(&mut self, _ctx: &mut Context<Asset>, _depth: &Depth) {}
+
+    /// Trades of the book, strategy fills included
+    fn on_trade(&mut self, _ctx: &mut Context<Asset>, _trade: &Trade) {}
+
+    /// Processing events of strategy requests and fills of strategy orders
+    fn on_order_event(&mut self, _ctx: &mut Context<Asset>, _event: &Result<Success, Failed>) {}
+}
+
+
+/// Strategy view of the backtest
+pub struct Context<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    now: SystemTime,
+    owner: u64,
+    order_asset: Asset,
+    price_asset: Asset,
+    inventory: f64,
+    cash: f64,
+    requests: Vec<OrderRequest<Asset>>,
+}
+
+
+impl<Asset> Context<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Time of the current event
+    pub fn now(&self) -> SystemTime {
+        self.now
+    }
+
+
+    /// Owner of strategy orders
+    pub fn owner(&self) -> u64 {
+        self.owner
+    }
+
+
+    /// Filled bought minus sold quantity
+    pub fn inventory(&self) -> f64 {
+        self.inventory
+    }
+
+
+    /// Received minus paid price asset, fees included
+    pub fn cash(&self) -> f64 {
+        self.cash
+    }
+
+
+    /// Send request, it has to be made for the strategy owner
+    pub fn submit(&mut self, request: OrderRequest<Asset>) {
+        self.requests.push(request);
+    }
+
+
+    pub fn limit(&mut self, side: OrderSide, price: f64, qty: f64) {
+        let request = orders::new_limit_order_request(
+            self.owner,
+            self.order_asset,
+            self.price_asset,
+            side,
+            price,
+            qty,
+            self.now,
+        );
+        self.submit(request);
+    }
+
+
+    pub fn market(&mut self, side: OrderSide, qty: f64) {
+        let request = orders::new_market_order_request(
+            self.owner,
+            self.order_asset,
+            self.price_asset,
+            side,
+            qty,
+            self.now,
+        );
+        self.submit(request);
+    }
+
+
+    pub fn amend(&mut self, id: u64, side: OrderSide, price: f64, qty: f64) {
+        let request = orders::amend_order_request(self.owner, id, side, price, qty, self.now);
+        self.submit(request);
+    }
+
+
+    pub fn cancel(&mut self, id: u64, side: OrderSide) {
+        let request = orders::limit_order_cancel_request(self.owner, id, side);
+        self.submit(request);
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    /// Strategy is called from the start time, `depth` sets levels of book updates.
+    /// Replay speed is not used.
+    pub replay: ReplayConfig,
+    /// Delay between strategy request and its processing
+    pub latency: Duration,
+    /// Owner of strategy orders, must not be used by replayed events
+    pub owner: u64,
+    /// Min time between report samples, sampled after every event if not set
+    pub sample_interval: Option<Duration>,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig {
+            replay: ReplayConfig::default(),
+            latency: Duration::from_secs(0),
+            owner: u64::MAX,
+            sample_interval: None,
+        }
+    }
+}
+
+
+/// Strategy state after replayed event
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BacktestSample {
+    pub ts: SystemTime,
+    pub inventory: f64,
+    pub cash: f64,
+    /// Mid price, last trade price for one-sided book
+    pub mark: Option<f64>,
+    /// Cash plus inventory at the mark price
+    pub pnl: Option<f64>,
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BacktestReport {
+    /// New orders sent to the engine
+    pub orders: usize,
+    /// Rejected requests, unmatched rest of a market order is not counted
+    pub rejected: usize,
+    pub fills: usize,
+    pub submitted_qty: f64,
+    pub filled_qty: f64,
+    pub fees: f64,
+    pub inventory: f64,
+    pub cash: f64,
+    /// Cash plus inventory at the last mark price
+    pub pnl: f64,
+    pub samples: Vec<BacktestSample>,
+}
+
+impl BacktestReport {
+    /// Filled part of submitted quantity
+    pub fn fill_ratio(&self) -> f64 {
+        if self.submitted_qty > 0.0 {
+            self.filled_qty / self.submitted_qty
+        } else {
+            0.0
+        }
+    }
+}
+
+
+/// Replay with a strategy trading in the replayed book
+pub struct Backtest<Asset, S>
+where
+    Asset: Debug + Clone + Copy + Eq,
+    S: Strategy<Asset>,
+{
+    replay: Replay<Asset>,
+    strategy: S,
+    config: BacktestConfig,
+    ctx: Context<Asset>,
+    // requests with processing time, in order of sending
+    in_flight: VecDeque<(SystemTime, OrderRequest<Asset>)>,
+    // engine IDs wrap around and are unique only per side
+    open_orders: HashSet<(u64, OrderSide)>,
+    mark: Option<f64>,
+    last_sample: Option<SystemTime>,
+    report: BacktestReport,
+}
+
+
+impl<Asset, S> Backtest<Asset, S>
+where
+    Asset: Debug + Clone + Copy + Eq,
+    S: Strategy<Asset>,
+{
+    pub fn new(order_asset: Asset, price_asset: Asset, strategy: S, config: BacktestConfig) -> Self {
+        Backtest {
+            replay: Replay::new(order_asset, price_asset, config.replay.clone()),
+            strategy,
+            ctx: Context {
+                now: SystemTime::UNIX_EPOCH,
+                owner: config.owner,
+                order_asset,
+                price_asset,
+                inventory: 0.0,
+                cash: 0.0,
+                requests: Vec::new(),
+            },
+            config,
+            in_flight: VecDeque::new(),
+            open_orders: HashSet::new(),
+            mark: None,
+            last_sample: None,
+            report: BacktestReport::default(),
+        }
+    }
+
+
+    pub fn strategy(&self) -> &S {
+        &self.strategy
+    }
+
+
+    pub fn orderbook(&self) -> &Orderbook<Asset> {
+        self.replay.orderbook()
+    }
+
+
+    pub fn report(&self) -> &BacktestReport {
+        &self.report
+    }
+
+
+    /// Replay events until the end time
+    pub fn run<I>(&mut self, events: I) -> Result<(), ReplayError>
+    where
+        I: IntoIterator<Item = Result<OrderEvent, ReplayError>>,
+    {
+        for event in events {
+            let event = event?;
+            if self.config.replay.end.is_some_and(|end| event.ts > end) {
+                break;
+            }
+            self.apply(&event);
+        }
+        Ok(())
+    }
+
+
+    /// Send requests due before the event, then pass the event and the book to the strategy
+    pub fn apply(&mut self, event: &OrderEvent) {
+        while self.in_flight.front().is_some_and(|&(due, _)| due <= event.ts) {
+            let (due, request) = self.in_flight.pop_front().unwrap();
+            self.send(due, request);
+        }
+
+        let (_, results) = self.replay.apply_with_results(event);
+        if self.config.replay.start.is_some_and(|start| event.ts < start) {
+            return;
+        }
+
+        self.ctx.now = event.ts;
+        self.handle_results(&results, None);
+
+        let depth = self.replay.orderbook().depth(self.config.replay.depth);
+        if let (Some(bid), Some(ask)) = (depth.bids.first(), depth.asks.first()) {
+            self.mark = Some((bid.price + ask.price) / 2.0);
+        }
+        self.strategy.on_book(&mut self.ctx, &depth);
+        self.queue_requests();
+        self.sample(event.ts);
+    }
+
+
+    fn send(&mut self, ts: SystemTime, request: OrderRequest<Asset>) {
+        match request {
+            OrderRequest::NewLimitOrder { qty, .. } | OrderRequest::NewMarketOrder { qty, .. } => {
+                self.report.orders += 1;
+                self.report.submitted_qty += qty;
+            }
+            _ => (),
+        }
+
+        let side = match request {
+            OrderRequest::NewMarketOrder { side, .. }
+            | OrderRequest::NewLimitOrder { side, .. }
+            | OrderRequest::AmendOrder { side, .. }
+            | OrderRequest::CancelOrder { side, .. } => side,
+        };
+        let results = self.replay.process_order(ts, request);
+        self.ctx.now = ts;
+        self.handle_results(&results, Some(side));
+        self.queue_requests();
+    }
+
+
+    /// Account strategy fills and notify the strategy, own request results are all passed.
+    ///
+    /// `request_side` is the side of the strategy request, `None` for replayed events.
+    /// Fills come in taker and maker pairs, the taker is the strategy order only for
+    /// its own requests, a maker is one if it is open.
+    fn handle_results(&mut self, results: &OrderProcessingResult, request_side: Option<OrderSide>) {
+        let own_request = request_side.is_some();
+        // own new limit order resting after matching
+        let mut resting = None;
+        let mut fills = 0;
+        for result in results {
+            let notify = match *result {
+                Ok(Success::Accepted { id, order_type, .. }) if own_request => {
+                    if order_type == OrderType::Limit {
+                        resting = request_side.map(|side| (id, side));
+                    }
+                    true
+                }
+                Ok(Success::Filled { order_id, side, price, qty, fee, .. })
+                | Ok(Success::PartiallyFilled { order_id, side, price, qty, fee, .. }) => {
+                    let taker = fills % 2 == 0;
+                    fills += 1;
+                    let filled = matches!(*result, Ok(Success::Filled { .. }));
+                    if taker && own_request {
+                        if filled {
+                            resting = None;
+                        }
+                        self.fill(side, price, qty, fee);
+                        true
+                    } else if !taker && self.open_orders.contains(&(order_id, side)) {
+                        if filled {
+                            self.open_orders.remove(&(order_id, side));
+                        }
+                        self.fill(side, price, qty, fee);
+                        true
+                    } else {
+                        false
+                    }
+                }
+                Ok(Success::Cancelled { id, .. }) if own_request => {
+                    if let Some(side) = request_side {
+                        self.open_orders.remove(&(id, side));
+                    }
+                    true
+                }
+                Ok(Success::Amended { .. }) => own_request,
+                Err(ref failed) if own_request => {
+                    match *failed {
+                        // unfilled rest of a market order
+                        Failed::NoMatch(_) => (),
+                        // rest of the order is dropped, the resting order keeps the ID
+                        Failed::DuplicateOrderID(_) => {
+                            resting = None;
+                            self.report.rejected += 1;
+                        }
+                        _ => self.report.rejected += 1,
+                    }
+                    true
+                }
+                _ => false,
+            };
+            if notify {
+                self.strategy.on_order_event(&mut self.ctx, result);
+            }
+        }
+        if let Some(key) = resting {
+            self.open_orders.insert(key);
+        }
+
+        for trade in market_data::trades(results) {
+            self.mark = Some(trade.price);
+            self.strategy.on_trade(&mut self.ctx, &trade);
+        }
+    }
+
+
+    fn fill(&mut self, side: OrderSide, price: f64, qty: f64, fee: f64) {
+        match side {
+            OrderSide::Bid => {
+                self.ctx.inventory += qty;
+                self.ctx.cash -= price * qty;
+            }
+            OrderSide::Ask => {
+                self.ctx.inventory -= qty;
+                self.ctx.cash += price * qty;
+            }
+        }
+        self.ctx.cash -= fee;
+
+        self.report.fills += 1;
+        self.report.filled_qty += qty;
+        self.report.fees += fee;
+        self.report.inventory = self.ctx.inventory;
+        self.report.cash = self.ctx.cash;
+    }
+
+
+    fn queue_requests(&mut self) {
+        let due = self.ctx.now + self.config.latency;
+        self.in_flight.extend(self.ctx.requests.drain(..).map(|request| (due, request)));
+    }
+
+
+    fn sample(&mut self, ts: SystemTime) {
+        let pnl = self.mark.map(|mark| self.ctx.cash + self.ctx.inventory * mark);
+        self.report.pnl = pnl.unwrap_or(self.ctx.cash);
+
+        let due = match (self.last_sample, self.config.sample_interval) {
+            (Some(last), Some(interval)) => ts >= last + interval,
+            _ => true,
+        };
+        if due {
+            self.last_sample = Some(ts);
+            self.report.samples.push(BacktestSample {
+                ts,
+                inventory: self.ctx.inventory,
+                cash: self.ctx.cash,
+                mark: self.mark,
+                pnl,
+            });
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::replay::{self, Format};
+    use crate::engine::orderbook::test::Asset;
+
+    const EVENTS: &str = "\
+ts,kind,id,owner,side,price,qty
+1000,limit,1,1,Bid,0.98,2
+1000,limit,2,2,Ask,1.02,5
+2000,limit,3,1,Bid,0.98,1
+3000,market,4,3,Ask,,2.5
+4000,market,5,3,Ask,,1
+5000,limit,6,2,Ask,1.03,1
+";
+
+    // joins the best bid and offers the position when it is filled
+    #[derive(Default)]
+    struct JoinBid {
+        placed: bool,
+        trades: usize,
+    }
+
+    impl Strategy<Asset> for JoinBid {
+        fn on_book(&mut self, ctx: &mut Context<Asset>, depth: &Depth) {
+            if let (false, Some(best)) = (self.placed, depth.bids.first()) {
+                ctx.limit(OrderSide::Bid, best.price, 1.0);
+                self.placed = true;
+            }
+        }
+
+        fn on_trade(&mut self, _ctx: &mut Context<Asset>, _trade: &Trade) {
+            self.trades += 1;
+        }
+
+        fn on_order_event(&mut self, ctx: &mut Context<Asset>, event: &Result<Success, Failed>) {
+            if let Ok(Success::Filled { side: OrderSide::Bid, .. }) = *event {
+                ctx.limit(OrderSide::Ask, 1.02, 1.0);
+            }
+        }
+    }
+
+    fn run_join_bid(latency: u64) -> Backtest<Asset, JoinBid> {
+        let config = BacktestConfig {
+            latency: Duration::from_nanos(latency),
+            ..BacktestConfig::default()
+        };
+        let mut backtest = Backtest::new(Asset::BTC, Asset::USD, JoinBid::default(), config);
+        backtest.run(replay::read_events(EVENTS.as_bytes(), Format::Csv)).unwrap();
+        backtest
+    }
+
+    #[test]
+    fn queue_position_and_latency() {
+        // bid arrives before order 3 and is filled after order 1
+        let backtest = run_join_bid(500);
+        let report = backtest.report();
+        assert_eq!((report.orders, report.fills, report.rejected), (2, 2, 0));
+        assert_eq!((report.submitted_qty, report.filled_qty, report.fill_ratio()), (2.0, 1.0, 0.5));
+        assert_eq!((report.inventory, report.cash), (1.0, -0.98));
+        assert_eq!(backtest.orderbook().depth(1).asks[0].qty, 6.0);
+        assert_eq!(backtest.strategy().trades, 4);
+
+        assert_eq!(report.samples.len(), 6);
+        let last = report.samples[5];
+        assert_eq!(last.mark, Some(1.0));
+        assert!((report.pnl - 0.02).abs() < 1e-9);
+        assert_eq!(last.pnl, Some(report.pnl));
+
+        // slower bid queues behind order 3 and is filled only partially
+        let report = run_join_bid(1500).report().clone();
+        assert_eq!((report.orders, report.filled_qty, report.inventory), (1, 0.5, 0.5));
+    }
+
+    // bids once and waits
+    #[derive(Default)]
+    struct RestingBid {
+        placed: bool,
+    }
+
+    impl Strategy<Asset> for RestingBid {
+        fn on_book(&mut self, ctx: &mut Context<Asset>, _depth: &Depth) {
+            if !self.placed {
+                ctx.limit(OrderSide::Bid, 0.98, 1.0);
+                self.placed = true;
+            }
+        }
+    }
+
+    #[test]
+    fn wrapped_ids_not_attributed_to_strategy() {
+        // replayed orders take every engine ID many times over, both as takers and makers
+        let mut events = String::from("ts,kind,id,owner,side,price,qty\n1000,limit,1,1,Ask,1.02,1\n");
+        for n in 0..600 {
+            let ts = 2000 + 2 * n;
+            events += &format!("{ts},limit,{},1,Ask,1.02,1\n", 2 * n + 2);
+            events += &format!("{},market,{},2,Bid,,1\n", ts + 1, 2 * n + 3);
+        }
+        events += "5000,market,2000,2,Ask,,1\n";
+
+        let mut backtest = Backtest::new(Asset::BTC, Asset::USD, RestingBid::default(), BacktestConfig::default());
+        backtest.run(replay::read_events(events.as_bytes(), Format::Csv)).unwrap();
+        let report = backtest.report();
+        assert_eq!((report.orders, report.fills, report.rejected), (1, 1, 0));
+        assert_eq!((report.inventory, report.cash), (1.0, -0.98));
+        assert!(backtest.open_orders.is_empty());
+    }
+}
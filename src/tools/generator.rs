@@ -0,0 +1,286 @@
+//! Synthetic order flow for load testing
+//!
+//! Requests arrive as a Poisson process. Limit prices are normally distributed around
+//! the mid price, orders with negative distance cross the mid. Cancels refer to orders
+//! which are known to rest in the book, every generated request has to be passed to
+//! `observe` with its results, otherwise a limit order is generated instead of a cancel.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Exp, Normal};
+
+use crate::engine::domain::OrderSide;
+use crate::engine::orderbook::{Failed, OrderProcessingResult, Success};
+use crate::engine::orders::{self, OrderRequest};
+
+
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    pub seed: u64,
+    /// Time of the first arrival
+    pub start: SystemTime,
+    /// Mean requests per second
+    pub rate: f64,
+    /// Relative weights of market, limit and cancel requests
+    pub market_weight: f64,
+    pub limit_weight: f64,
+    pub cancel_weight: f64,
+    pub mid: f64,
+    pub tick: f64,
+    /// Mean and standard deviation of limit price distance from mid, in ticks
+    pub distance_mean: f64,
+    pub distance_std: f64,
+    /// Order quantity is 1 to `max_lots` lots
+    pub lot: f64,
+    pub max_lots: u32,
+    /// Owners are numbered from 1
+    pub owners: u64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            seed: 0,
+            start: UNIX_EPOCH,
+            rate: 1000.0,
+            market_weight: 0.1,
+            limit_weight: 0.6,
+            cancel_weight: 0.3,
+            mid: 100.0,
+            tick: 0.01,
+            distance_mean: 5.0,
+            distance_std: 5.0,
+            lot: 1.0,
+            max_lots: 10,
+            owners: 100,
+        }
+    }
+}
+
+
+/// Seedable source of order requests with arrival times
+pub struct Generator<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    config: GeneratorConfig,
+    order_asset: Asset,
+    price_asset: Asset,
+    rng: StdRng,
+    arrivals: Exp<f64>,
+    distances: Normal<f64>,
+    now: SystemTime,
+    // resting orders: (id, side, owner), with positions by ID
+    live: Vec<(u64, OrderSide, u64)>,
+    live_idx: HashMap<(u64, OrderSide), usize>,
+}
+
+
+impl<Asset> Generator<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Panics on non-positive rate or negative standard deviation
+    pub fn new(order_asset: Asset, price_asset: Asset, config: GeneratorConfig) -> Self {
+        Generator {
+            order_asset,
+            price_asset,
+            rng: StdRng::seed_from_u64(config.seed),
+            arrivals: Exp::new(config.rate).expect("arrival rate"),
+            distances: Normal::new(config.distance_mean, config.distance_std).expect("price distribution"),
+            now: config.start,
+            config,
+            live: Vec::new(),
+            live_idx: HashMap::new(),
+        }
+    }
+
+
+    /// Resting orders known from observed results
+    pub fn live_orders(&self) -> usize {
+        self.live.len()
+    }
+
+
+    /// Limit orders resting at 1 to `levels` ticks from mid on both sides, at the start time
+    pub fn book(&mut self, levels: usize) -> Vec<OrderRequest<Asset>> {
+        let mut requests = Vec::with_capacity(levels * 2);
+        for level in 1..=levels {
+            for side in [OrderSide::Bid, OrderSide::Ask] {
+                let distance = level as f64 * self.config.tick;
+                let price = match side {
+                    OrderSide::Bid => self.config.mid - distance,
+                    OrderSide::Ask => self.config.mid + distance,
+                };
+                let qty = self.qty();
+                let owner = self.owner();
+                requests.push(orders::new_limit_order_request(
+                    owner,
+                    self.order_asset,
+                    self.price_asset,
+                    side,
+                    price,
+                    qty,
+                    self.config.start,
+                ));
+            }
+        }
+        requests
+    }
+
+
+    /// Next request with its arrival time
+    pub fn next_request(&mut self) -> (SystemTime, OrderRequest<Asset>) {
+        self.now += Duration::from_secs_f64(self.arrivals.sample(&mut self.rng));
+
+        let total = self.config.market_weight + self.config.limit_weight + self.config.cancel_weight;
+        let pick = self.rng.gen::<f64>() * total;
+        let side = if self.rng.gen::<bool>() { OrderSide::Bid } else { OrderSide::Ask };
+
+        let request = if pick < self.config.market_weight {
+            let (owner, qty) = (self.owner(), self.qty());
+            orders::new_market_order_request(owner, self.order_asset, self.price_asset, side, qty, self.now)
+        } else if pick >= total - self.config.cancel_weight && !self.live.is_empty() {
+            let (id, side, owner) = self.live[self.rng.gen_range(0..self.live.len())];
+            orders::limit_order_cancel_request(owner, id, side)
+        } else {
+            let (owner, qty, price) = (self.owner(), self.qty(), self.price(side));
+            let (order_asset, price_asset) = (self.order_asset, self.price_asset);
+            orders::new_limit_order_request(owner, order_asset, price_asset, side, price, qty, self.now)
+        };
+        (self.now, request)
+    }
+
+
+    /// Track resting orders from processing results of the request
+    pub fn observe(&mut self, request: &OrderRequest<Asset>, results: &OrderProcessingResult) {
+        let taker = match *request {
+            OrderRequest::NewLimitOrder { side, owner, .. }
+            | OrderRequest::NewMarketOrder { side, owner, .. } => match results.first() {
+                Some(&Ok(Success::Accepted { id, .. })) => Some((id, side, owner)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let mut rests = matches!(*request, OrderRequest::NewLimitOrder { .. });
+
+        for result in results {
+            let key = match (result, request) {
+                (&Ok(Success::Filled { order_id, side, .. }), _) => (order_id, side),
+                (&Ok(Success::Cancelled { id, .. }), &OrderRequest::CancelOrder { side, .. }) => (id, side),
+                // engine IDs rotate, new order is rejected if its ID is still in use
+                (&Err(Failed::DuplicateOrderID(_)), _) => {
+                    rests = false;
+                    continue;
+                }
+                _ => continue,
+            };
+            // the same ID can rest on the other side
+            if taker.is_some_and(|(id, side, _)| (id, side) == key) {
+                rests = false;
+            } else if let Some(idx) = self.live_idx.remove(&key) {
+                self.live.swap_remove(idx);
+                if let Some(&(id, side, _)) = self.live.get(idx) {
+                    self.live_idx.insert((id, side), idx);
+                }
+            }
+        }
+
+        if let (true, Some((id, side, owner))) = (rests, taker) {
+            self.live_idx.insert((id, side), self.live.len());
+            self.live.push((id, side, owner));
+        }
+    }
+
+
+    fn price(&mut self, side: OrderSide) -> f64 {
+        let ticks = self.distances.sample(&mut self.rng).round();
+        let price = match side {
+            OrderSide::Bid => self.config.mid - ticks * self.config.tick,
+            OrderSide::Ask => self.config.mid + ticks * self.config.tick,
+        };
+        price.max(self.config.tick)
+    }
+
+
+    fn qty(&mut self) -> f64 {
+        self.rng.gen_range(1..=self.config.max_lots.max(1)) as f64 * self.config.lot
+    }
+
+
+    fn owner(&mut self) -> u64 {
+        self.rng.gen_range(1..=self.config.owners.max(1))
+    }
+}
+
+
+impl<Asset> Iterator for Generator<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    type Item = (SystemTime, OrderRequest<Asset>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_request())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::orderbook::Orderbook;
+    use crate::engine::orderbook::test::Asset;
+
+    fn counts(generator: &mut Generator<Asset>, orderbook: &mut Orderbook<Asset>, n: usize) -> [usize; 3] {
+        let mut counts = [0; 3];
+        for _ in 0..n {
+            let (_, request) = generator.next_request();
+            match request {
+                OrderRequest::NewMarketOrder { .. } => counts[0] += 1,
+                OrderRequest::NewLimitOrder { price, .. } => {
+                    assert!((price * 100.0 - (price * 100.0).round()).abs() < 1e-6);
+                    counts[1] += 1;
+                }
+                OrderRequest::CancelOrder { .. } => counts[2] += 1,
+                OrderRequest::AmendOrder { .. } => unreachable!(),
+            }
+            let results = orderbook.process_order(request.clone());
+            // generated cancels refer to resting orders
+            assert!(!results.iter().any(|result| matches!(*result, Err(Failed::OrderNotFound(_)))));
+            generator.observe(&request, &results);
+        }
+        counts
+    }
+
+    #[test]
+    fn seeded_flow_mix() {
+        let config = GeneratorConfig { seed: 7, ..GeneratorConfig::default() };
+        let mut generator = Generator::new(Asset::BTC, Asset::USD, config.clone());
+        let mut orderbook = Orderbook::new(Asset::BTC, Asset::USD);
+        for request in generator.book(10) {
+            let results = orderbook.process_order(request.clone());
+            generator.observe(&request, &results);
+        }
+        assert_eq!(generator.live_orders(), 20);
+
+        let [market, limit, cancel] = counts(&mut generator, &mut orderbook, 10_000);
+        assert!((800..1200).contains(&market), "{}", market);
+        assert!((5600..6400).contains(&limit), "{}", limit);
+        assert!((2600..3400).contains(&cancel), "{}", cancel);
+        let depth = orderbook.depth(usize::MAX);
+        let resting = depth.bids.iter().chain(depth.asks.iter()).map(|level| level.orders);
+        assert_eq!(generator.live_orders(), resting.sum::<usize>());
+
+        // same seed gives the same stream, mean interval is 1 ms
+        let first: Vec<_> = Generator::new(Asset::BTC, Asset::USD, config.clone()).take(1000).collect();
+        let second: Vec<_> = Generator::new(Asset::BTC, Asset::USD, config).take(1000).collect();
+        assert_eq!(format!("{:?}", first), format!("{:?}", second));
+        let elapsed = first[999].0.duration_since(UNIX_EPOCH).unwrap();
+        assert!(elapsed > Duration::from_millis(900) && elapsed < Duration::from_millis(1100));
+    }
+}
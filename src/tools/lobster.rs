@@ -0,0 +1,672 @@
+//! Book reconstruction from LOBSTER data
+//!
+//! Message file rows are `time,type,order_id,size,price,direction`, time in seconds after
+//! midnight, price in units of 1/10000, direction 1 for buy and -1 for sell limit orders.
+//! Orderbook file rows hold `ask_price,ask_size,bid_price,bid_size` for every level
+//! after the message of the same row. Empty levels have size 0.
+//!
+//! Orders resting before the first message are unknown, every initial level is placed
+//! as a single order, and messages referring to unknown order IDs are applied to it.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::engine::clock::ManualClock;
+use crate::engine::domain::OrderSide;
+use crate::engine::orderbook::{Failed, Orderbook, OrderProcessingResult, Success};
+use crate::engine::orders;
+
+
+pub const PRICE_SCALE: f64 = 10_000.0;
+
+// all orders of the book have one owner
+const BOOK_OWNER: u64 = 0;
+const TAKER_OWNER: u64 = 1;
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MessageType {
+    Submit,
+    /// Partial cancellation
+    Cancel,
+    Delete,
+    Execute,
+    ExecuteHidden,
+    Cross,
+    Halt,
+}
+
+impl MessageType {
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(MessageType::Submit),
+            2 => Some(MessageType::Cancel),
+            3 => Some(MessageType::Delete),
+            4 => Some(MessageType::Execute),
+            5 => Some(MessageType::ExecuteHidden),
+            6 => Some(MessageType::Cross),
+            7 => Some(MessageType::Halt),
+            _ => None,
+        }
+    }
+}
+
+
+/// Message file row, side is the side of the limit order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub time: f64,
+    pub kind: MessageType,
+    pub order_id: u64,
+    pub size: u64,
+    pub price: i64,
+    pub side: OrderSide,
+}
+
+impl Message {
+    /// Time of the message on Unix epoch day
+    pub fn timestamp(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs_f64(self.time.max(0.0))
+    }
+}
+
+
+/// Orderbook file row without empty levels, (price, size) from the best level
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BookRow {
+    pub levels: usize,
+    pub bids: Vec<(i64, u64)>,
+    pub asks: Vec<(i64, u64)>,
+}
+
+
+#[derive(Debug)]
+pub enum LobsterError {
+    Csv(csv::Error),
+    /// Bad row, line numbers start from one
+    Format { line: usize, message: String },
+}
+
+impl fmt::Display for LobsterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LobsterError::Csv(ref err) => write!(f, "{}", err),
+            LobsterError::Format { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<csv::Error> for LobsterError {
+    fn from(err: csv::Error) -> Self {
+        LobsterError::Csv(err)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiscrepancyKind {
+    /// Level of the reconstructed book differs, levels start from one
+    Level {
+        side: OrderSide,
+        level: usize,
+        expected: Option<(i64, u64)>,
+        actual: Option<(i64, u64)>,
+    },
+    /// Execution matched other orders than the message order.
+    /// Makers are (order ID, price), initial level orders have ID 0.
+    Execution { order_id: u64, price: i64, makers: Vec<(u64, i64)> },
+    /// Cancel or execution of an order which is not in the book
+    UnknownOrder(u64),
+    /// Engine rejected the request made for the message
+    Rejected(String),
+}
+
+
+/// Difference found after message on the given line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub line: usize,
+    pub time: f64,
+    pub kind: DiscrepancyKind,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "message {} at {:.9}: ", self.line, self.time)?;
+        let level = |level: Option<(i64, u64)>| match level {
+            Some((price, size)) => format!("{} x {}", price, size),
+            None => "empty".to_string(),
+        };
+        match self.kind {
+            DiscrepancyKind::Level { side, level: n, expected, actual } => write!(
+                f,
+                "{:?} level {} expected {}, reconstructed {}",
+                side,
+                n,
+                level(expected),
+                level(actual)
+            ),
+            DiscrepancyKind::Execution { order_id, price, ref makers } => {
+                write!(f, "execution of order {} at {} matched {:?}", order_id, price, makers)
+            }
+            DiscrepancyKind::UnknownOrder(order_id) => write!(f, "unknown order {}", order_id),
+            DiscrepancyKind::Rejected(ref reason) => write!(f, "rejected: {}", reason),
+        }
+    }
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LobsterReport {
+    pub messages: usize,
+    /// Hidden executions, cross trades and halts, which do not change the visible book
+    pub skipped: usize,
+    /// Messages followed by at least one discrepancy
+    pub mismatched: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+
+/// Message file rows
+pub fn read_messages<'a, R>(reader: R) -> impl Iterator<Item = Result<Message, LobsterError>> + 'a
+where
+    R: Read + 'a,
+{
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .into_deserialize::<(f64, u8, u64, u64, i64, i8)>()
+        .enumerate()
+        .map(|(n, row)| {
+            let (time, kind, order_id, size, price, direction) = row?;
+            let kind = MessageType::from_code(kind).ok_or_else(|| LobsterError::Format {
+                line: n + 1,
+                message: format!("unknown message type {}", kind),
+            })?;
+            let side = match direction {
+                1 => OrderSide::Bid,
+                -1 => OrderSide::Ask,
+                _ => {
+                    return Err(LobsterError::Format {
+                        line: n + 1,
+                        message: format!("unknown direction {}", direction),
+                    })
+                }
+            };
+            Ok(Message { time, kind, order_id, size, price, side })
+        })
+}
+
+
+/// Orderbook file rows
+pub fn read_book<'a, R>(reader: R) -> impl Iterator<Item = Result<BookRow, LobsterError>> + 'a
+where
+    R: Read + 'a,
+{
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .into_records()
+        .enumerate()
+        .map(|(n, record)| {
+            let record = record?;
+            let format_error = |message: String| LobsterError::Format { line: n + 1, message };
+            let values = record
+                .iter()
+                .map(|value| value.parse::<i64>().map_err(|_| format_error(format!("bad number {}", value))))
+                .collect::<Result<Vec<i64>, LobsterError>>()?;
+            if values.is_empty() || values.len() % 4 != 0 {
+                return Err(format_error(format!("{} columns", values.len())));
+            }
+
+            let mut row = BookRow { levels: values.len() / 4, ..BookRow::default() };
+            for level in values.chunks(4) {
+                if level[1] > 0 {
+                    row.asks.push((level[0], level[1] as u64));
+                }
+                if level[3] > 0 {
+                    row.bids.push((level[2], level[3] as u64));
+                }
+            }
+            Ok(row)
+        })
+}
+
+
+/// Reconstruct the book from message and orderbook files and compare it after every message
+pub fn reconstruct<Asset, M, B>(
+    order_asset: Asset,
+    price_asset: Asset,
+    messages: M,
+    book: B,
+) -> Result<LobsterReport, LobsterError>
+where
+    Asset: Debug + Clone + Copy + Eq,
+    M: Read,
+    B: Read,
+{
+    let mut lobster = Lobster::new(order_asset, price_asset);
+    let mut report = LobsterReport::default();
+
+    for (n, pair) in read_messages(messages).zip(read_book(book)).enumerate() {
+        let (message, row) = (pair.0?, pair.1?);
+        if n == 0 {
+            lobster.seed(&message, &row);
+        }
+
+        let mut found = lobster.apply(&message);
+        found.extend(lobster.compare(&row));
+
+        report.messages += 1;
+        if !lobster.changes_book(&message) {
+            report.skipped += 1;
+        }
+        if !found.is_empty() {
+            report.mismatched += 1;
+        }
+        report.discrepancies.extend(found.into_iter().map(|kind| Discrepancy {
+            line: n + 1,
+            time: message.time,
+            kind,
+        }));
+    }
+    Ok(report)
+}
+
+
+/// Engine book driven by LOBSTER messages
+pub struct Lobster<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    orderbook: Orderbook<Asset>,
+    clock: ManualClock,
+    // LOBSTER order ID to engine ID and back, engine IDs are unique per side only
+    engine_ids: HashMap<u64, u64>,
+    source_ids: HashMap<(OrderSide, u64), u64>,
+    // orders placed for initial levels
+    initial: HashMap<(OrderSide, i64), u64>,
+    // remaining quantity of resting orders by side and engine ID
+    remaining: HashMap<(OrderSide, u64), f64>,
+}
+
+
+impl<Asset> Lobster<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub fn new(order_asset: Asset, price_asset: Asset) -> Self {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        Lobster {
+            orderbook: Orderbook::with_clock(order_asset, price_asset, clock.clone()),
+            clock,
+            engine_ids: HashMap::new(),
+            source_ids: HashMap::new(),
+            initial: HashMap::new(),
+            remaining: HashMap::new(),
+        }
+    }
+
+
+    pub fn orderbook(&self) -> &Orderbook<Asset> {
+        &self.orderbook
+    }
+
+
+    /// Place the book seen before the first message, row is the book after it
+    pub fn seed(&mut self, first: &Message, row: &BookRow) {
+        self.clock.set(first.timestamp());
+        let mut bids = row.bids.clone();
+        let mut asks = row.asks.clone();
+        let levels = match first.side {
+            OrderSide::Bid => &mut bids,
+            OrderSide::Ask => &mut asks,
+        };
+        match first.kind {
+            MessageType::Submit => adjust_level(levels, first.side, first.price, -(first.size as i64)),
+            MessageType::Cancel | MessageType::Delete | MessageType::Execute => {
+                adjust_level(levels, first.side, first.price, first.size as i64)
+            }
+            _ => (),
+        }
+
+        for (side, levels) in [(OrderSide::Bid, bids), (OrderSide::Ask, asks)] {
+            for (price, size) in levels {
+                if let Some(id) = self.submit(side, price, size) {
+                    self.initial.insert((side, price), id);
+                }
+            }
+        }
+    }
+
+
+    /// Pass message to the engine, returns discrepancies of the message itself
+    pub fn apply(&mut self, message: &Message) -> Vec<DiscrepancyKind> {
+        self.clock.set(message.timestamp());
+        let mut found = Vec::new();
+
+        match message.kind {
+            MessageType::Submit => {
+                match self.submit(message.side, message.price, message.size) {
+                    Some(id) => {
+                        if self.remaining.contains_key(&(message.side, id)) {
+                            self.engine_ids.insert(message.order_id, id);
+                            self.source_ids.insert((message.side, id), message.order_id);
+                        }
+                    }
+                    None => found.push(DiscrepancyKind::Rejected(format!("order {}", message.order_id))),
+                }
+            }
+            MessageType::Cancel | MessageType::Delete => {
+                // deletion of an unknown order removes its part of the initial level
+                let (id, qty) = match self.engine_ids.get(&message.order_id) {
+                    Some(&id) if message.kind == MessageType::Delete => (id, 0.0),
+                    Some(&id) => (id, self.remaining_qty(message.side, id) - message.size as f64),
+                    None => match self.initial.get(&(message.side, message.price)) {
+                        Some(&id) => (id, self.remaining_qty(message.side, id) - message.size as f64),
+                        None => {
+                            found.push(DiscrepancyKind::UnknownOrder(message.order_id));
+                            return found;
+                        }
+                    },
+                };
+
+                let request = if qty > 0.0 {
+                    let price = message.price as f64 / PRICE_SCALE;
+                    orders::amend_order_request(BOOK_OWNER, id, message.side, price, qty, message.timestamp())
+                } else {
+                    orders::limit_order_cancel_request(BOOK_OWNER, id, message.side)
+                };
+                let results = self.orderbook.process_order(request);
+                found.extend(rejections(&results));
+                self.update_remaining(message.side, None, &results);
+            }
+            MessageType::Execute => {
+                let expected = self
+                    .engine_ids
+                    .get(&message.order_id)
+                    .or_else(|| self.initial.get(&(message.side, message.price)))
+                    .cloned();
+                if expected.is_none() {
+                    found.push(DiscrepancyKind::UnknownOrder(message.order_id));
+                    return found;
+                }
+
+                let taker_side = message.side.opposite();
+                let results = self.orderbook.process_order(orders::new_market_order_request(
+                    TAKER_OWNER,
+                    self.orderbook.order_asset(),
+                    self.orderbook.price_asset(),
+                    taker_side,
+                    message.size as f64,
+                    message.timestamp(),
+                ));
+
+                let makers: Vec<(u64, f64)> = results
+                    .iter()
+                    .filter_map(|result| match *result {
+                        Ok(Success::Filled { order_id, side, price, .. })
+                        | Ok(Success::PartiallyFilled { order_id, side, price, .. })
+                            if side == message.side => Some((order_id, price)),
+                        _ => None,
+                    })
+                    .collect();
+                let price = message.price as f64 / PRICE_SCALE;
+                if makers != [(expected.unwrap(), price)] {
+                    found.push(DiscrepancyKind::Execution {
+                        order_id: message.order_id,
+                        price: message.price,
+                        makers: makers
+                            .iter()
+                            .map(|&(id, price)| (self.source_id(message.side, id), scaled_price(price)))
+                            .collect(),
+                    });
+                }
+                self.update_remaining(taker_side, Some(taker_side), &results);
+            }
+            MessageType::ExecuteHidden | MessageType::Cross | MessageType::Halt => (),
+        }
+        found
+    }
+
+
+    /// Compare the book with orderbook file row
+    pub fn compare(&self, row: &BookRow) -> Vec<DiscrepancyKind> {
+        let depth = self.orderbook.depth(row.levels);
+        let mut found = Vec::new();
+        let sides = [(OrderSide::Bid, &row.bids, &depth.bids), (OrderSide::Ask, &row.asks, &depth.asks)];
+        for (side, expected, actual) in sides {
+            for level in 0..row.levels {
+                let expected = expected.get(level).cloned();
+                let actual = actual
+                    .get(level)
+                    .map(|level| (scaled_price(level.price), level.qty.round() as u64));
+                if expected != actual {
+                    found.push(DiscrepancyKind::Level { side, level: level + 1, expected, actual });
+                }
+            }
+        }
+        found
+    }
+
+
+    /// Message types which change the visible book
+    pub fn changes_book(&self, message: &Message) -> bool {
+        !matches!(message.kind, MessageType::ExecuteHidden | MessageType::Cross | MessageType::Halt)
+    }
+
+
+    fn submit(&mut self, side: OrderSide, price: i64, size: u64) -> Option<u64> {
+        let results = self.orderbook.process_order(orders::new_limit_order_request(
+            BOOK_OWNER,
+            self.orderbook.order_asset(),
+            self.orderbook.price_asset(),
+            side,
+            price as f64 / PRICE_SCALE,
+            size as f64,
+            self.clock.time(),
+        ));
+        let duplicate = results.iter().any(|result| matches!(*result, Err(Failed::DuplicateOrderID(_))));
+        match results.first() {
+            Some(&Ok(Success::Accepted { id, .. })) if !duplicate => {
+                // crossing part is matched, the rest stays in the book
+                let filled: f64 = results
+                    .iter()
+                    .filter_map(|result| match *result {
+                        Ok(Success::Filled { side: fill_side, qty, .. })
+                        | Ok(Success::PartiallyFilled { side: fill_side, qty, .. })
+                            if fill_side == side => Some(qty),
+                        _ => None,
+                    })
+                    .sum();
+                if size as f64 > filled {
+                    self.remaining.insert((side, id), size as f64 - filled);
+                }
+                self.update_remaining(side, Some(side), &results);
+                Some(id)
+            }
+            _ => None,
+        }
+    }
+
+
+    fn remaining_qty(&self, side: OrderSide, id: u64) -> f64 {
+        self.remaining.get(&(side, id)).cloned().unwrap_or(0.0)
+    }
+
+
+    fn source_id(&self, side: OrderSide, id: u64) -> u64 {
+        self.source_ids.get(&(side, id)).cloned().unwrap_or(0)
+    }
+
+
+    /// Track quantities of resting orders, closed orders are forgotten.
+    ///
+    /// `side` is the side of the request, fills of the taker on `taker_side` are skipped.
+    fn update_remaining(
+        &mut self,
+        side: OrderSide,
+        taker_side: Option<OrderSide>,
+        results: &OrderProcessingResult,
+    ) {
+        for result in results {
+            let (key, remaining) = match *result {
+                Ok(Success::PartiallyFilled { order_id, side: fill_side, qty, .. })
+                    if Some(fill_side) != taker_side =>
+                {
+                    match self.remaining.get(&(fill_side, order_id)) {
+                        Some(&current) => ((fill_side, order_id), current - qty),
+                        None => continue,
+                    }
+                }
+                Ok(Success::Filled { order_id, side: fill_side, .. }) if Some(fill_side) != taker_side => {
+                    ((fill_side, order_id), 0.0)
+                }
+                Ok(Success::Amended { id, qty, .. }) => ((side, id), qty),
+                Ok(Success::Cancelled { id, .. }) => ((side, id), 0.0),
+                _ => continue,
+            };
+
+            if remaining > 0.0 {
+                if let Some(current) = self.remaining.get_mut(&key) {
+                    *current = remaining;
+                }
+            } else if self.remaining.remove(&key).is_some() {
+                if let Some(source_id) = self.source_ids.remove(&key) {
+                    self.engine_ids.remove(&source_id);
+                }
+                self.initial.retain(|&(side, _), initial_id| (side, *initial_id) != key);
+            }
+        }
+    }
+}
+
+
+/// Add size to the level, keeping levels sorted from the best price
+fn adjust_level(levels: &mut Vec<(i64, u64)>, side: OrderSide, price: i64, size: i64) {
+    let position = levels.iter().position(|&(level_price, _)| match side {
+        OrderSide::Bid => level_price <= price,
+        OrderSide::Ask => level_price >= price,
+    });
+    match position {
+        Some(n) if levels[n].0 == price => {
+            let size = levels[n].1 as i64 + size;
+            if size > 0 {
+                levels[n].1 = size as u64;
+            } else {
+                levels.remove(n);
+            }
+        }
+        _ if size <= 0 => (),
+        Some(n) => levels.insert(n, (price, size as u64)),
+        None => levels.push((price, size as u64)),
+    }
+}
+
+
+fn rejections(results: &OrderProcessingResult) -> Vec<DiscrepancyKind> {
+    results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .map(|failed| DiscrepancyKind::Rejected(format!("{:?}", failed)))
+        .collect()
+}
+
+
+fn scaled_price(price: f64) -> i64 {
+    (price * PRICE_SCALE).round() as i64
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::orderbook::test::Asset;
+
+    const MESSAGES: &str = "\
+34200.0,1,11,30,10000,1
+34200.1,1,12,20,10000,1
+34200.2,2,11,10,10000,1
+34200.3,4,5,50,10000,1
+34200.4,4,11,20,10000,1
+34200.5,3,7,40,10100,-1
+34200.6,5,99,10,10050,1
+34200.7,1,13,5,10200,-1
+";
+
+    const BOOK: &str = "\
+10100,100,10000,80,9999999999,0,-9999999999,0
+10100,100,10000,100,9999999999,0,-9999999999,0
+10100,100,10000,90,9999999999,0,-9999999999,0
+10100,100,10000,40,9999999999,0,-9999999999,0
+10100,100,10000,20,9999999999,0,-9999999999,0
+10100,60,10000,20,9999999999,0,-9999999999,0
+10100,60,10000,20,9999999999,0,-9999999999,0
+10100,60,10000,20,10200,5,-9999999999,0
+";
+
+    #[test]
+    fn reconstruct_matching_book() {
+        // partially cancelled order 11 keeps priority over 12 and is executed first
+        let report = reconstruct(Asset::BTC, Asset::USD, MESSAGES.as_bytes(), BOOK.as_bytes()).unwrap();
+        assert_eq!(report.discrepancies, vec![]);
+        assert_eq!((report.messages, report.skipped, report.mismatched), (8, 1, 0));
+    }
+
+    #[test]
+    fn report_discrepancies() {
+        let book = BOOK.replacen("10100,100,10000,20,", "10100,100,10000,25,", 1);
+        let messages = MESSAGES.replace("34200.4,4,11,20", "34200.4,4,12,20") + "34200.8,3,42,5,10300,-1\n";
+        let book = book + "10100,60,10000,20,10200,5,-9999999999,0\n";
+
+        let report = reconstruct(Asset::BTC, Asset::USD, messages.as_bytes(), book.as_bytes()).unwrap();
+        assert_eq!((report.messages, report.mismatched), (9, 2));
+        assert_eq!(report.discrepancies, vec![
+            Discrepancy {
+                line: 5,
+                time: 34200.4,
+                kind: DiscrepancyKind::Execution { order_id: 12, price: 10000, makers: vec![(11, 10000)] },
+            },
+            Discrepancy {
+                line: 5,
+                time: 34200.4,
+                kind: DiscrepancyKind::Level {
+                    side: OrderSide::Bid,
+                    level: 1,
+                    expected: Some((10000, 25)),
+                    actual: Some((10000, 20)),
+                },
+            },
+            Discrepancy { line: 9, time: 34200.8, kind: DiscrepancyKind::UnknownOrder(42) },
+        ]);
+        assert_eq!(
+            report.discrepancies[1].to_string(),
+            "message 5 at 34200.400000000: Bid level 1 expected 10000 x 25, reconstructed 10000 x 20"
+        );
+
+        assert!(matches!(read_messages(&b"1.0,9,1,1,1,1\n"[..]).next(),
+            Some(Err(LobsterError::Format { line: 1, .. }))));
+    }
+
+    #[test]
+    fn wrapped_taker_ids_keep_resting_orders() {
+        // every execution takes an engine ID, takers reuse IDs of both resting orders
+        let executions = 1100;
+        let mut messages = "34200.0,1,1,2000,10000,1\n34200.0,1,2,5,10100,-1\n".to_string();
+        let mut book = "9999999999,0,10000,2000\n10100,5,10000,2000\n".to_string();
+        for n in 1..=executions {
+            messages += "34201.0,4,1,1,10000,1\n";
+            book += &format!("10100,5,10000,{}\n", 2000 - n);
+        }
+        messages += "34202.0,3,2,5,10100,-1\n";
+        book += &format!("9999999999,0,10000,{}\n", 2000 - executions);
+
+        let report = reconstruct(Asset::BTC, Asset::USD, messages.as_bytes(), book.as_bytes()).unwrap();
+        assert_eq!(report.discrepancies, vec![]);
+        assert_eq!(report.messages, executions + 3);
+    }
+}
@@ -0,0 +1,9 @@
+#[cfg(feature = "replay")]
+pub mod backtest;
+#[cfg(feature = "generator")]
+pub mod generator;
+#[cfg(feature = "replay")]
+pub mod lobster;
+pub mod repl;
+#[cfg(feature = "replay")]
+pub mod replay;
@@ -0,0 +1,570 @@
+//! Text commands for driving an orderbook by hand
+//!
+//! Saved session is a script of the commands which changed the book,
+//! loading replays it on an empty book, so order IDs stay the same.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::engine::domain::{OrderSide, OrderType};
+use crate::engine::market_data::{self, PriceLevel, Trade};
+use crate::engine::orderbook::{Orderbook, OrderProcessingResult, Success};
+use crate::engine::orders::{self, OrderRequest};
+
+
+const DEFAULT_TRADES: usize = 20;
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+pub const HELP: &str = "\
+buy QTY @ PRICE     place limit order, same for sell
+buy market QTY      place market order, same for sell
+cancel ID           cancel order
+amend ID PRICE QTY  change price and quantity of order
+book [LEVELS]       show aggregated book
+trades [COUNT]      show last trades, 20 by default
+owner [ID]          show or switch owner of new requests
+save FILE           save order commands of the session
+load FILE           replay saved session on empty book
+run FILE            run commands from file
+quit                exit";
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Limit { side: OrderSide, price: f64, qty: f64 },
+    Market { side: OrderSide, qty: f64 },
+    Cancel { id: u64 },
+    Amend { id: u64, price: f64, qty: f64 },
+    Book { levels: Option<usize> },
+    Trades { count: Option<usize> },
+    Owner(Option<u64>),
+    Save(PathBuf),
+    Load(PathBuf),
+    Run(PathBuf),
+    Help,
+    Quit,
+}
+
+
+#[derive(Debug)]
+pub enum ReplError {
+    Parse(String),
+    /// Order was not placed in this session, so its side is unknown
+    UnknownOrder(u64),
+    Io(io::Error),
+    /// Command of the script failed, line numbers start from one
+    Script {
+        path: PathBuf,
+        line: usize,
+        error: Box<ReplError>,
+    },
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplError::Parse(ref reason) => write!(f, "{}", reason),
+            ReplError::UnknownOrder(id) => write!(f, "order {} was not placed in this session", id),
+            ReplError::Io(ref err) => write!(f, "{}", err),
+            ReplError::Script { ref path, line, ref error } => {
+                write!(f, "{}:{}: {}", path.display(), line, error)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ReplError {
+    fn from(err: io::Error) -> Self {
+        ReplError::Io(err)
+    }
+}
+
+
+impl Command {
+    /// Parse one line, `None` for blank lines and `#` comments
+    pub fn parse(line: &str) -> Result<Option<Command>, ReplError> {
+        let line = line.split('#').next().unwrap_or("").replace('@', " @ ");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name.to_lowercase(), args),
+            None => return Ok(None),
+        };
+
+        let command = match (name.as_str(), args) {
+            ("buy" | "sell", ["market", qty]) => Command::Market {
+                side: side(&name),
+                qty: number(qty, "quantity")?,
+            },
+            ("buy" | "sell", [qty, "@", price]) | ("buy" | "sell", [qty, price]) => Command::Limit {
+                side: side(&name),
+                price: number(price, "price")?,
+                qty: number(qty, "quantity")?,
+            },
+            ("cancel", [id]) => Command::Cancel { id: number(id, "order ID")? },
+            ("amend", [id, price, qty]) => Command::Amend {
+                id: number(id, "order ID")?,
+                price: number(price, "price")?,
+                qty: number(qty, "quantity")?,
+            },
+            ("book", []) => Command::Book { levels: None },
+            ("book", [levels]) => Command::Book { levels: Some(number(levels, "level count")?) },
+            ("trades", []) => Command::Trades { count: None },
+            ("trades", [count]) => Command::Trades { count: Some(number(count, "trade count")?) },
+            ("owner", []) => Command::Owner(None),
+            ("owner", [owner]) => Command::Owner(Some(number(owner, "owner")?)),
+            ("save", [path]) => Command::Save(PathBuf::from(path)),
+            ("load", [path]) => Command::Load(PathBuf::from(path)),
+            ("run", [path]) => Command::Run(PathBuf::from(path)),
+            ("help", []) => Command::Help,
+            ("quit" | "exit", []) => Command::Quit,
+            _ => return Err(ReplError::Parse(format!("bad command '{}', try 'help'", words.join(" ")))),
+        };
+        Ok(Some(command))
+    }
+}
+
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Command::Limit { side, price, qty } => write!(f, "{} {} @ {}", side_name(side), qty, price),
+            Command::Market { side, qty } => write!(f, "{} market {}", side_name(side), qty),
+            Command::Cancel { id } => write!(f, "cancel {}", id),
+            Command::Amend { id, price, qty } => write!(f, "amend {} {} {}", id, price, qty),
+            Command::Book { levels: Some(levels) } => write!(f, "book {}", levels),
+            Command::Book { levels: None } => write!(f, "book"),
+            Command::Trades { count: Some(count) } => write!(f, "trades {}", count),
+            Command::Trades { count: None } => write!(f, "trades"),
+            Command::Owner(Some(owner)) => write!(f, "owner {}", owner),
+            Command::Owner(None) => write!(f, "owner"),
+            Command::Save(ref path) => write!(f, "save {}", path.display()),
+            Command::Load(ref path) => write!(f, "load {}", path.display()),
+            Command::Run(ref path) => write!(f, "run {}", path.display()),
+            Command::Help => write!(f, "help"),
+            Command::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+
+/// Orderbook with the state of interactive session
+pub struct Session<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    orderbook: Orderbook<Asset>,
+    owner: u64,
+    // sides of orders placed in session, required to cancel and amend
+    sides: HashMap<u64, OrderSide>,
+    trades: Vec<Trade>,
+    history: Vec<Command>,
+    script_depth: usize,
+}
+
+
+impl<Asset> Session<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    /// Session on empty book, requests are placed on behalf of owner 1
+    pub fn new(order_asset: Asset, price_asset: Asset) -> Self {
+        Session {
+            orderbook: Orderbook::new(order_asset, price_asset),
+            owner: 1,
+            sides: HashMap::new(),
+            trades: Vec::new(),
+            history: Vec::new(),
+            script_depth: 0,
+        }
+    }
+
+
+    pub fn orderbook(&self) -> &Orderbook<Asset> {
+        &self.orderbook
+    }
+
+
+    /// Parse and run one line, returns text to print
+    pub fn execute(&mut self, line: &str) -> Result<String, ReplError> {
+        match Command::parse(line)? {
+            Some(command) => self.run(command),
+            None => Ok(String::new()),
+        }
+    }
+
+
+    /// Run command, returns text to print. `Quit` should be handled by caller.
+    pub fn run(&mut self, command: Command) -> Result<String, ReplError> {
+        let ts = SystemTime::now();
+        let order_asset = self.orderbook.order_asset();
+        let price_asset = self.orderbook.price_asset();
+
+        let request = match command {
+            Command::Limit { side, price, qty } => {
+                orders::new_limit_order_request(self.owner, order_asset, price_asset, side, price, qty, ts)
+            }
+            Command::Market { side, qty } => {
+                orders::new_market_order_request(self.owner, order_asset, price_asset, side, qty, ts)
+            }
+            Command::Cancel { id } => {
+                orders::limit_order_cancel_request(self.owner, id, self.order_side(id)?)
+            }
+            Command::Amend { id, price, qty } => {
+                orders::amend_order_request(self.owner, id, self.order_side(id)?, price, qty, ts)
+            }
+            Command::Book { levels } => return Ok(self.book(levels.unwrap_or(usize::MAX))),
+            Command::Trades { count } => return Ok(self.last_trades(count.unwrap_or(DEFAULT_TRADES))),
+            Command::Owner(None) => return Ok(format!("owner {}", self.owner)),
+            Command::Owner(Some(owner)) => {
+                self.owner = owner;
+                self.history.push(command);
+                return Ok(format!("owner {}", owner));
+            }
+            Command::Save(ref path) => return self.save(path),
+            Command::Load(ref path) => return self.load(path),
+            Command::Run(ref path) => return self.run_script(path, true),
+            Command::Help => return Ok(HELP.to_string()),
+            Command::Quit => return Ok(String::new()),
+        };
+
+        self.history.push(command);
+        Ok(self.process(request))
+    }
+
+
+    fn process(&mut self, request: OrderRequest<Asset>) -> String {
+        let new_order_side = match request {
+            OrderRequest::NewLimitOrder { side, .. } |
+            OrderRequest::NewMarketOrder { side, .. } => Some(side),
+            _ => None,
+        };
+        let results = self.orderbook.process_order(request);
+        if let (Some(&Ok(Success::Accepted { id, .. })), Some(side)) = (results.first(), new_order_side) {
+            self.sides.insert(id, side);
+        }
+        self.trades.extend(market_data::trades(&results));
+
+        let spread = match self.orderbook.current_spread() {
+            Some((bid, ask)) => format!("spread: {} / {}", bid, ask),
+            None => "spread: not available".to_string(),
+        };
+        format!("{}\n{}", self.results_table(&results), spread)
+    }
+
+
+    fn results_table(&self, results: &OrderProcessingResult) -> String {
+        let side = |id: &u64| self.sides.get(id).map_or(String::new(), |side| side_name(*side).to_string());
+        let rows: Vec<Vec<String>> = results
+            .iter()
+            .map(|result| match *result {
+                Ok(Success::Accepted { ref id, order_type, .. }) => {
+                    row(&["accepted", &id.to_string(), &side(id), type_name(order_type), "", "", ""])
+                }
+                Ok(Success::Filled { order_id, side, order_type, price, qty, fee, .. }) => row(&[
+                    "filled",
+                    &order_id.to_string(),
+                    side_name(side),
+                    type_name(order_type),
+                    &price.to_string(),
+                    &qty.to_string(),
+                    &fee.to_string(),
+                ]),
+                Ok(Success::PartiallyFilled { order_id, side, order_type, price, qty, fee, .. }) => row(&[
+                    "partially filled",
+                    &order_id.to_string(),
+                    side_name(side),
+                    type_name(order_type),
+                    &price.to_string(),
+                    &qty.to_string(),
+                    &fee.to_string(),
+                ]),
+                Ok(Success::Amended { ref id, price, qty, .. }) => row(&[
+                    "amended",
+                    &id.to_string(),
+                    &side(id),
+                    "",
+                    &price.to_string(),
+                    &qty.to_string(),
+                    "",
+                ]),
+                Ok(Success::Cancelled { ref id, .. }) => {
+                    row(&["cancelled", &id.to_string(), &side(id), "", "", "", ""])
+                }
+                Err(ref failed) => row(&[&format!("rejected: {:?}", failed), "", "", "", "", "", ""]),
+            })
+            .collect();
+        table(&["EVENT", "ORDER", "SIDE", "TYPE", "PRICE", "QTY", "FEE"], &rows, 1)
+    }
+
+
+    fn book(&self, max_levels: usize) -> String {
+        let depth = self.orderbook.depth(max_levels);
+        if depth.bids.is_empty() && depth.asks.is_empty() {
+            return "book is empty".to_string();
+        }
+
+        // bids are mirrored, so prices meet in the middle
+        let level = |level: Option<&PriceLevel>, bid: bool| match level {
+            Some(level) => {
+                let mut cells =
+                    vec![level.price.to_string(), level.qty.to_string(), level.orders.to_string()];
+                if bid {
+                    cells.reverse();
+                }
+                cells
+            }
+            None => vec![String::new(); 3],
+        };
+        let rows: Vec<Vec<String>> = (0..depth.bids.len().max(depth.asks.len()))
+            .map(|i| {
+                let mut row = level(depth.bids.get(i), true);
+                row.extend(level(depth.asks.get(i), false));
+                row
+            })
+            .collect();
+        table(&["ORDERS", "QTY", "BID", "ASK", "QTY", "ORDERS"], &rows, 0)
+    }
+
+
+    fn last_trades(&self, count: usize) -> String {
+        if self.trades.is_empty() {
+            return "no trades".to_string();
+        }
+        let rows: Vec<Vec<String>> = self.trades[self.trades.len().saturating_sub(count)..]
+            .iter()
+            .map(|trade| {
+                row(&[
+                    &trade.taker_order_id.to_string(),
+                    &trade.maker_order_id.to_string(),
+                    side_name(trade.taker_side),
+                    &trade.price.to_string(),
+                    &trade.qty.to_string(),
+                ])
+            })
+            .collect();
+        table(&["TAKER", "MAKER", "SIDE", "PRICE", "QTY"], &rows, 0)
+    }
+
+
+    fn save(&self, path: &Path) -> Result<String, ReplError> {
+        let mut script = String::new();
+        for command in &self.history {
+            script.push_str(&format!("{}\n", command));
+        }
+        fs::write(path, script)?;
+        Ok(format!("saved {} commands to {}", self.history.len(), path.display()))
+    }
+
+
+    /// Replay script on empty book
+    fn load(&mut self, path: &Path) -> Result<String, ReplError> {
+        let mut session = Session::new(self.orderbook.order_asset(), self.orderbook.price_asset());
+        session.script_depth = self.script_depth;
+        session.run_script(path, false)?;
+        *self = session;
+        let loaded = format!("loaded {} commands from {}", self.history.len(), path.display());
+        Ok(format!("{}\n{}", loaded, self.book(usize::MAX)))
+    }
+
+
+    /// Run commands of the file until the first error, `quit` stops the script
+    fn run_script(&mut self, path: &Path, echo: bool) -> Result<String, ReplError> {
+        if self.script_depth >= MAX_SCRIPT_DEPTH {
+            return Err(ReplError::Parse("scripts are nested too deep".to_string()));
+        }
+        let script = fs::read_to_string(path)?;
+
+        self.script_depth += 1;
+        let mut output = Vec::new();
+        let mut result = Ok(());
+        for (n, line) in script.lines().enumerate() {
+            let command = match Command::parse(line) {
+                Ok(Some(Command::Quit)) => break,
+                Ok(Some(command)) => command,
+                Ok(None) => continue,
+                Err(error) => {
+                    result = Err((n, error));
+                    break;
+                }
+            };
+            if echo {
+                output.push(format!("> {}", command));
+            }
+            match self.run(command) {
+                Ok(text) if echo && !text.is_empty() => output.push(text),
+                Ok(_) => (),
+                Err(error) => {
+                    result = Err((n, error));
+                    break;
+                }
+            }
+        }
+        self.script_depth -= 1;
+
+        match result {
+            Ok(()) => Ok(output.join("\n")),
+            Err((n, error)) => Err(ReplError::Script {
+                path: path.to_path_buf(),
+                line: n + 1,
+                error: Box::new(error),
+            }),
+        }
+    }
+
+
+    fn order_side(&self, id: u64) -> Result<OrderSide, ReplError> {
+        self.sides.get(&id).cloned().ok_or(ReplError::UnknownOrder(id))
+    }
+}
+
+
+fn number<T: FromStr>(word: &str, what: &str) -> Result<T, ReplError> {
+    word.parse().map_err(|_| ReplError::Parse(format!("bad {} '{}'", what, word)))
+}
+
+
+fn side(command: &str) -> OrderSide {
+    if command == "buy" { OrderSide::Bid } else { OrderSide::Ask }
+}
+
+
+fn side_name(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Bid => "buy",
+        OrderSide::Ask => "sell",
+    }
+}
+
+
+fn type_name(order_type: OrderType) -> &'static str {
+    match order_type {
+        OrderType::Market => "market",
+        OrderType::Limit => "limit",
+    }
+}
+
+
+fn row(cells: &[&str]) -> Vec<String> {
+    cells.iter().map(|cell| cell.to_string()).collect()
+}
+
+
+/// Columns separated by two spaces, first `left_aligned` columns are aligned left, the rest right
+fn table(headers: &[&str], rows: &[Vec<String>], left_aligned: usize) -> String {
+    let header = row(headers);
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let lines: Vec<String> = Some(&header)
+        .into_iter()
+        .chain(rows)
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, &width))| {
+                    if column < left_aligned {
+                        format!("{:<width$}", cell, width = width)
+                    } else {
+                        format!("{:>width$}", cell, width = width)
+                    }
+                })
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect();
+    lines.join("\n")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use crate::engine::orderbook::test::Asset;
+
+    #[test]
+    fn parse_commands() {
+        let parsed = |line| Command::parse(line).unwrap();
+        let limit = |side, price, qty| Some(Command::Limit { side, price, qty });
+        assert_eq!(parsed("buy 5 @ 0.98"), limit(OrderSide::Bid, 0.98, 5.0));
+        assert_eq!(parsed("SELL 1@1.02"), limit(OrderSide::Ask, 1.02, 1.0));
+        assert_eq!(parsed("sell market 2"), Some(Command::Market { side: OrderSide::Ask, qty: 2.0 }));
+        assert_eq!(parsed("amend 4 1.01 3"), Some(Command::Amend { id: 4, price: 1.01, qty: 3.0 }));
+        assert_eq!(parsed("  cancel 3 # typo"), Some(Command::Cancel { id: 3 }));
+        assert_eq!(parsed("book 5"), Some(Command::Book { levels: Some(5) }));
+        assert_eq!(parsed("# comment"), None);
+        assert_eq!(parsed("buy 5 @ 0.98").unwrap().to_string(), "buy 5 @ 0.98");
+        assert!(matches!(Command::parse("buy five @ 1"), Err(ReplError::Parse(ref reason))
+            if reason == "bad quantity 'five'"));
+        assert!(matches!(Command::parse("cancel"), Err(ReplError::Parse(_))));
+    }
+
+    #[test]
+    fn trade_save_and_load() {
+        let mut session = Session::new(Asset::BTC, Asset::USD);
+        session.execute("buy 5 @ 0.98").unwrap();
+        session.execute("sell 1 @ 1.02").unwrap();
+        session.execute("owner 2").unwrap();
+        let output = session.execute("buy market 0.4").unwrap();
+        assert_eq!(output, "\
+EVENT             ORDER  SIDE    TYPE  PRICE  QTY  FEE
+accepted              3   buy  market
+filled                3   buy  market   1.02  0.4    0
+partially filled      2  sell   limit   1.02  0.4    0
+spread: 0.98 / 1.02");
+
+        assert!(matches!(session.execute("cancel 7"), Err(ReplError::UnknownOrder(7))));
+        let output = session.execute("amend 1 0.99 4").unwrap();
+        assert!(output.contains("\nrejected: OrderNotFound(1)"), "{}", output);
+        session.execute("owner 1").unwrap();
+        session.execute("amend 1 0.99 4").unwrap();
+        assert_eq!(session.execute("book").unwrap(), "\
+ORDERS  QTY   BID   ASK  QTY  ORDERS
+     1    4  0.99  1.02  0.6       1");
+        assert_eq!(session.execute("trades").unwrap(), "\
+TAKER  MAKER  SIDE  PRICE  QTY
+    3      2   buy   1.02  0.4");
+
+        let path = env::temp_dir().join(format!("orderbook-session-{}.txt", process::id()));
+        let saved = session.execute(&format!("save {}", path.display())).unwrap();
+        assert_eq!(saved, format!("saved 7 commands to {}", path.display()));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "\
+buy 5 @ 0.98
+sell 1 @ 1.02
+owner 2
+buy market 0.4
+amend 1 0.99 4
+owner 1
+amend 1 0.99 4
+");
+
+        let mut loaded = Session::new(Asset::BTC, Asset::USD);
+        let output = loaded.execute(&format!("load {}", path.display())).unwrap();
+        assert!(output.ends_with("     1    4  0.99  1.02  0.6       1"));
+        assert_eq!(loaded.execute("owner").unwrap(), "owner 1");
+        assert_eq!(loaded.execute("trades").unwrap(), session.execute("trades").unwrap());
+
+        fs::write(&path, "sell market 1\ncancel 9\n").unwrap();
+        match loaded.execute(&format!("run {}", path.display())) {
+            Err(ReplError::Script { line, ref error, .. }) => {
+                assert_eq!(line, 2);
+                assert!(matches!(**error, ReplError::UnknownOrder(9)));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(loaded.orderbook().depth(1).bids[0].qty, 3.0);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,690 @@
+//! Replay of historical order flow through the engine
+//!
+//! Events are read from CSV with a header row or from newline-delimited JSON,
+//! both with the fields of `OrderEvent`. Timestamps are nanoseconds since Unix epoch,
+//! sides are `Bid`/`Ask`.
+//!
+//! Engine order IDs wrap around after 1000 by default. A new order getting the ID
+//! of an order still resting on its side is rejected with `DuplicateOrderID` and
+//! is not tracked, so flows with long-living orders need a wider range,
+//! see `ReplayConfig::max_order_id`.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::clock::ManualClock;
+use crate::engine::domain::OrderSide;
+use crate::engine::market_data::{self, PriceLevel};
+use crate::engine::orderbook::{Failed, Orderbook, OrderProcessingResult, Success};
+use crate::engine::orders::{self, OrderRequest};
+
+
+const DEFAULT_DEPTH: usize = 10;
+const TRADE_COLUMNS: [&str; 6] = ["ts", "taker_order_id", "maker_order_id", "taker_side", "price", "qty"];
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Limit,
+    Market,
+    Cancel,
+    Amend,
+}
+
+
+/// Historical request, price is not used by market orders and cancels, quantity by cancels
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderEvent {
+    #[serde(with = "crate::engine::serde_ts")]
+    pub ts: SystemTime,
+    pub kind: EventKind,
+    /// Order ID in the source data, engine ID is used for new orders without it
+    #[serde(default)]
+    pub id: Option<u64>,
+    #[serde(default)]
+    pub owner: u64,
+    pub side: OrderSide,
+    #[serde(default)]
+    pub price: Option<f64>,
+    #[serde(default)]
+    pub qty: Option<f64>,
+}
+
+
+/// Trade with order IDs of the source data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeRecord {
+    #[serde(with = "crate::engine::serde_ts")]
+    pub ts: SystemTime,
+    pub taker_order_id: u64,
+    pub maker_order_id: u64,
+    pub taker_side: OrderSide,
+    pub price: f64,
+    pub qty: f64,
+}
+
+
+/// Best levels of the book after the event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookSample {
+    #[serde(with = "crate::engine::serde_ts")]
+    pub ts: SystemTime,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Ndjson,
+}
+
+impl Format {
+    /// CSV for `.csv` files, newline-delimited JSON otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Format::Csv,
+            _ => Format::Ndjson,
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Csv(csv::Error),
+    /// Bad JSON line, line numbers start from one
+    Json { line: usize, error: serde_json::Error },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref err) => write!(f, "{}", err),
+            ReplayError::Csv(ref err) => write!(f, "{}", err),
+            ReplayError::Json { line, ref error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+impl From<csv::Error> for ReplayError {
+    fn from(err: csv::Error) -> Self {
+        ReplayError::Csv(err)
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    /// Pace relative to recorded time, 2.0 is twice as fast, as fast as possible if not set
+    pub speed: Option<f64>,
+    /// Events before start only build the book, nothing is written for them
+    pub start: Option<SystemTime>,
+    /// Replay stops at the first event after end
+    pub end: Option<SystemTime>,
+    /// Levels per side in book samples
+    pub depth: usize,
+    /// Min time between book samples, book is sampled after every event if not set
+    pub sample_interval: Option<Duration>,
+    /// Last engine order ID, engine default if not set
+    pub max_order_id: Option<u64>,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        ReplayConfig {
+            speed: None,
+            start: None,
+            end: None,
+            depth: DEFAULT_DEPTH,
+            sample_interval: None,
+            max_order_id: None,
+        }
+    }
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// Events passed to the engine
+    pub events: usize,
+    /// Events with missing fields or unknown order IDs
+    pub skipped: usize,
+    /// Requests rejected by the engine
+    pub rejected: usize,
+    pub trades: usize,
+    pub samples: usize,
+}
+
+
+/// Output of one replayed event
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReplayStep {
+    pub trades: Vec<TradeRecord>,
+    pub sample: Option<BookSample>,
+}
+
+
+/// Trades or book samples written as CSV or newline-delimited JSON
+pub enum RecordWriter<W: Write> {
+    Csv { writer: Box<csv::Writer<W>>, header: bool },
+    Ndjson(W),
+}
+
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(writer: W, format: Format) -> Self {
+        match format {
+            Format::Csv => RecordWriter::Csv {
+                writer: Box::new(csv::WriterBuilder::new().has_headers(false).from_writer(writer)),
+                header: false,
+            },
+            Format::Ndjson => RecordWriter::Ndjson(writer),
+        }
+    }
+
+
+    pub fn write_trade(&mut self, trade: &TradeRecord) -> Result<(), ReplayError> {
+        match *self {
+            RecordWriter::Csv { ref mut writer, ref mut header } => {
+                if !*header {
+                    writer.write_record(TRADE_COLUMNS)?;
+                    *header = true;
+                }
+                writer.serialize(trade)?;
+            }
+            RecordWriter::Ndjson(ref mut writer) => write_json(writer, trade)?,
+        }
+        Ok(())
+    }
+
+
+    /// CSV rows have fixed number of price/quantity columns per side, missing levels are empty
+    pub fn write_sample(&mut self, sample: &BookSample, depth: usize) -> Result<(), ReplayError> {
+        match *self {
+            RecordWriter::Csv { ref mut writer, ref mut header } => {
+                if !*header {
+                    let mut columns = vec!["ts".to_string()];
+                    for side in &["bid", "ask"] {
+                        for level in 1..=depth {
+                            columns.push(format!("{}_price_{}", side, level));
+                            columns.push(format!("{}_qty_{}", side, level));
+                        }
+                    }
+                    writer.write_record(&columns)?;
+                    *header = true;
+                }
+
+                let mut row = vec![epoch_nanos(sample.ts).to_string()];
+                for levels in &[&sample.bids, &sample.asks] {
+                    for level in 0..depth {
+                        match levels.get(level) {
+                            Some(level) => row.extend([level.price.to_string(), level.qty.to_string()]),
+                            None => row.extend([String::new(), String::new()]),
+                        }
+                    }
+                }
+                writer.write_record(&row)?;
+            }
+            RecordWriter::Ndjson(ref mut writer) => write_json(writer, sample)?,
+        }
+        Ok(())
+    }
+
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            RecordWriter::Csv { ref mut writer, .. } => writer.flush(),
+            RecordWriter::Ndjson(ref mut writer) => writer.flush(),
+        }
+    }
+}
+
+
+pub type Events<'a> = Box<dyn Iterator<Item = Result<OrderEvent, ReplayError>> + 'a>;
+
+
+/// Iterator over events of the reader
+pub fn read_events<'a, R>(reader: R, format: Format) -> Events<'a>
+where
+    R: Read + 'a,
+{
+    match format {
+        Format::Csv => Box::new(
+            csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(reader)
+                .into_deserialize()
+                .map(|event| event.map_err(ReplayError::Csv)),
+        ),
+        Format::Ndjson => Box::new(BufReader::new(reader).lines().enumerate().filter_map(|(n, line)| {
+            match line {
+                Ok(ref line) if line.trim().is_empty() => None,
+                Ok(line) => Some(
+                    serde_json::from_str(&line).map_err(|error| ReplayError::Json { line: n + 1, error }),
+                ),
+                Err(err) => Some(Err(ReplayError::Io(err))),
+            }
+        })),
+    }
+}
+
+
+/// Orderbook driven by historical events on a simulated clock
+pub struct Replay<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    orderbook: Orderbook<Asset>,
+    clock: ManualClock,
+    config: ReplayConfig,
+    // source ID to engine ID and back, for orders in the book,
+    // engine IDs are unique per side only
+    engine_ids: HashMap<u64, u64>,
+    source_ids: HashMap<(OrderSide, u64), u64>,
+    last_sample: Option<SystemTime>,
+    stats: ReplayStats,
+}
+
+
+impl<Asset> Replay<Asset>
+where
+    Asset: Debug + Clone + Copy + Eq,
+{
+    pub fn new(order_asset: Asset, price_asset: Asset, config: ReplayConfig) -> Self {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let mut orderbook = Orderbook::with_clock(order_asset, price_asset, clock.clone());
+        if let Some(max_id) = config.max_order_id {
+            orderbook.set_max_order_id(max_id);
+        }
+        Replay {
+            orderbook,
+            clock,
+            config,
+            engine_ids: HashMap::new(),
+            source_ids: HashMap::new(),
+            last_sample: None,
+            stats: ReplayStats::default(),
+        }
+    }
+
+
+    pub fn orderbook(&self) -> &Orderbook<Asset> {
+        &self.orderbook
+    }
+
+
+    pub fn stats(&self) -> &ReplayStats {
+        &self.stats
+    }
+
+
+    /// Replay events until the end time, writing trades and book samples
+    pub fn run<I, W>(
+        &mut self,
+        events: I,
+        trades: &mut RecordWriter<W>,
+        book: &mut RecordWriter<W>,
+    ) -> Result<(), ReplayError>
+    where
+        I: IntoIterator<Item = Result<OrderEvent, ReplayError>>,
+        W: Write,
+    {
+        // first paced event with its wall time
+        let mut pace_start: Option<(SystemTime, Instant)> = None;
+
+        for event in events {
+            let event = event?;
+            if self.config.end.is_some_and(|end| event.ts > end) {
+                break;
+            }
+
+            if let Some(speed) = self.config.speed.filter(|_| self.in_window(event.ts)) {
+                let (first_ts, started) = *pace_start.get_or_insert((event.ts, Instant::now()));
+                let recorded = event.ts.duration_since(first_ts).unwrap_or_default();
+                let due = started + recorded.div_f64(speed);
+                let now = Instant::now();
+                if due > now {
+                    thread::sleep(due - now);
+                }
+            }
+
+            let step = self.apply(&event);
+            for trade in &step.trades {
+                trades.write_trade(trade)?;
+            }
+            if let Some(ref sample) = step.sample {
+                book.write_sample(sample, self.config.depth)?;
+            }
+        }
+
+        trades.flush()?;
+        book.flush()?;
+        Ok(())
+    }
+
+
+    /// Pass event to the engine at its timestamp.
+    ///
+    /// Output is empty for events before the start time.
+    pub fn apply(&mut self, event: &OrderEvent) -> ReplayStep {
+        self.apply_with_results(event).0
+    }
+
+
+    /// Same as `apply`, with engine results of the event, empty for skipped events
+    pub fn apply_with_results(&mut self, event: &OrderEvent) -> (ReplayStep, OrderProcessingResult) {
+        let request = match self.request(event) {
+            Some(request) => request,
+            None => {
+                self.stats.skipped += 1;
+                return (ReplayStep::default(), Vec::new());
+            }
+        };
+
+        self.clock.set(event.ts);
+        let results = self.orderbook.process_order(request);
+        self.stats.events += 1;
+        self.stats.rejected += results.iter().filter(|result| result.is_err()).count();
+
+        // source ID of the new order, takes part in trades of this event only as taker
+        let taker_id = match (results.first(), event.kind) {
+            (Some(&Ok(Success::Accepted { id, .. })), EventKind::Limit | EventKind::Market) => {
+                let source_id = event.id.unwrap_or(id);
+                if event.kind == EventKind::Limit && rests(&results, event.side) {
+                    self.engine_ids.insert(source_id, id);
+                    self.source_ids.insert((event.side, id), source_id);
+                }
+                Some(source_id)
+            }
+            _ => None,
+        };
+
+        let mut step = ReplayStep::default();
+        if self.in_window(event.ts) {
+            step.trades = market_data::trades(&results)
+                .into_iter()
+                .map(|trade| TradeRecord {
+                    ts: trade.ts,
+                    taker_order_id: taker_id.unwrap_or(trade.taker_order_id),
+                    maker_order_id: self.source_id(trade.taker_side.opposite(), trade.maker_order_id),
+                    taker_side: trade.taker_side,
+                    price: trade.price,
+                    qty: trade.qty,
+                })
+                .collect();
+            self.stats.trades += step.trades.len();
+            step.sample = self.sample(event.ts);
+        }
+
+        self.forget_closed(event.side, taker_id.is_some(), &results);
+        (step, results)
+    }
+
+
+    /// Process request which is not a part of the replayed flow at the given time
+    pub fn process_order(&mut self, ts: SystemTime, request: OrderRequest<Asset>) -> OrderProcessingResult {
+        let (side, new_order) = match request {
+            OrderRequest::NewMarketOrder { side, .. } | OrderRequest::NewLimitOrder { side, .. } => (side, true),
+            OrderRequest::AmendOrder { side, .. } | OrderRequest::CancelOrder { side, .. } => (side, false),
+        };
+        self.clock.set(ts);
+        let results = self.orderbook.process_order(request);
+        self.forget_closed(side, new_order, &results);
+        results
+    }
+
+
+    fn request(&self, event: &OrderEvent) -> Option<OrderRequest<Asset>> {
+        let order_asset = self.orderbook.order_asset();
+        let price_asset = self.orderbook.price_asset();
+        let request = match event.kind {
+            EventKind::Limit => orders::new_limit_order_request(
+                event.owner,
+                order_asset,
+                price_asset,
+                event.side,
+                event.price?,
+                event.qty?,
+                event.ts,
+            ),
+            EventKind::Market => orders::new_market_order_request(
+                event.owner,
+                order_asset,
+                price_asset,
+                event.side,
+                event.qty?,
+                event.ts,
+            ),
+            EventKind::Cancel => {
+                let id = *self.engine_ids.get(&event.id?)?;
+                orders::limit_order_cancel_request(event.owner, id, event.side)
+            }
+            EventKind::Amend => {
+                let id = *self.engine_ids.get(&event.id?)?;
+                orders::amend_order_request(event.owner, id, event.side, event.price?, event.qty?, event.ts)
+            }
+        };
+        Some(request)
+    }
+
+
+    fn sample(&mut self, ts: SystemTime) -> Option<BookSample> {
+        let due = match (self.last_sample, self.config.sample_interval) {
+            (Some(last), Some(interval)) => ts >= last + interval,
+            _ => true,
+        };
+        if !due {
+            return None;
+        }
+
+        self.last_sample = Some(ts);
+        self.stats.samples += 1;
+        let depth = self.orderbook.depth(self.config.depth);
+        Some(BookSample { ts, bids: depth.bids, asks: depth.asks })
+    }
+
+
+    fn in_window(&self, ts: SystemTime) -> bool {
+        self.config.start.is_none_or(|start| ts >= start)
+    }
+
+
+    fn source_id(&self, side: OrderSide, id: u64) -> u64 {
+        self.source_ids.get(&(side, id)).cloned().unwrap_or(id)
+    }
+
+
+    /// Drop ID mapping of resting orders which left the book.
+    ///
+    /// `side` is the side of the request, fills of new orders on it are taker fills.
+    fn forget_closed(&mut self, side: OrderSide, new_order: bool, results: &OrderProcessingResult) {
+        for result in results {
+            let key = match *result {
+                Ok(Success::Filled { order_id, side: fill_side, .. }) if !new_order || fill_side != side => {
+                    (fill_side, order_id)
+                }
+                // only requests of the side cancel orders
+                Ok(Success::Cancelled { id, .. }) => (side, id),
+                _ => continue,
+            };
+            if let Some(source_id) = self.source_ids.remove(&key) {
+                self.engine_ids.remove(&source_id);
+            }
+        }
+    }
+}
+
+
+/// New limit order of the side stayed in the book
+fn rests(results: &OrderProcessingResult, side: OrderSide) -> bool {
+    results.iter().all(|result| match *result {
+        Ok(Success::Filled { side: fill_side, .. }) => fill_side != side,
+        Err(Failed::DuplicateOrderID(_)) => false,
+        _ => true,
+    })
+}
+
+
+fn write_json<W: Write, T: serde::Serialize>(writer: &mut W, record: &T) -> Result<(), ReplayError> {
+    serde_json::to_writer(&mut *writer, record).map_err(io::Error::from)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+
+fn epoch_nanos(ts: SystemTime) -> u64 {
+    let nanos = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    nanos.min(u128::from(u64::MAX)) as u64
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::orderbook::test::Asset;
+
+    const EVENTS: &str = "\
+ts,kind,id,owner,side,price,qty
+1000,limit,101,1,Bid,0.98,5
+2000,limit,102,2,Ask,1.02,1
+3000,limit,103,2,Ask,1.03,2
+4000,cancel,999,1,Bid,,
+5000,market,104,3,Bid,,1.5
+6000,amend,103,2,Ask,1.04,1
+7000,cancel,101,1,Bid,,
+";
+
+    #[test]
+    fn replay_csv_window() {
+        let config = ReplayConfig {
+            start: Some(UNIX_EPOCH + Duration::from_nanos(3000)),
+            end: Some(UNIX_EPOCH + Duration::from_nanos(6500)),
+            depth: 2,
+            ..ReplayConfig::default()
+        };
+        let mut replay = Replay::new(Asset::BTC, Asset::USD, config);
+        let mut trades = RecordWriter::new(Vec::new(), Format::Csv);
+        let mut book = RecordWriter::new(Vec::new(), Format::Ndjson);
+        replay.run(read_events(EVENTS.as_bytes(), Format::Csv), &mut trades, &mut book).unwrap();
+
+        // unknown cancel is skipped, the last cancel is after the end
+        assert_eq!(replay.stats(), &ReplayStats {
+            events: 5,
+            skipped: 1,
+            rejected: 0,
+            trades: 2,
+            samples: 3,
+        });
+        let trades = match trades {
+            RecordWriter::Csv { writer, .. } => String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            RecordWriter::Ndjson(_) => unreachable!(),
+        };
+        assert_eq!(trades, "\
+ts,taker_order_id,maker_order_id,taker_side,price,qty
+5000,104,102,Bid,1.02,1.0
+5000,104,103,Bid,1.03,0.5
+");
+
+        let samples: Vec<BookSample> = match book {
+            RecordWriter::Ndjson(buf) => buf.split(|&b| b == b'\n')
+                .filter(|line| !line.is_empty())
+                .map(|line| serde_json::from_slice(line).unwrap())
+                .collect(),
+            RecordWriter::Csv { .. } => unreachable!(),
+        };
+        assert_eq!(samples[0].ts, UNIX_EPOCH + Duration::from_nanos(3000));
+        assert_eq!(samples[0].asks.len(), 2);
+        let last = &samples[2];
+        assert_eq!((last.bids[0].price, last.bids[0].qty), (0.98, 5.0));
+        assert_eq!((last.asks[0].price, last.asks[0].qty), (1.04, 1.0));
+        assert_eq!(replay.orderbook().depth(1).asks[0].price, 1.04);
+    }
+
+    #[test]
+    fn ndjson_events_and_csv_samples() {
+        let lines = concat!(
+            r#"{"ts": 1000, "kind": "limit", "owner": 1, "side": "Bid", "price": 0.98, "qty": 5.0}"#,
+            "\n\n",
+            r#"{"ts": 2000, "kind": "cancel", "id": 1, "owner": 1, "side": "Bid"}"#,
+            "\n",
+            r#"{"ts": 3000, "kind": "limit", "side": "Ask"}"#,
+            "\n",
+        );
+        let events: Vec<_> = read_events(lines.as_bytes(), Format::Ndjson).map(Result::unwrap).collect();
+        assert_eq!(events[1].kind, EventKind::Cancel);
+
+        let config = ReplayConfig {
+            depth: 1,
+            sample_interval: Some(Duration::from_nanos(1500)),
+            ..ReplayConfig::default()
+        };
+        let mut replay = Replay::new(Asset::BTC, Asset::USD, config);
+        let mut book = RecordWriter::new(Vec::new(), Format::Csv);
+        for event in &events {
+            if let Some(sample) = replay.apply(event).sample {
+                book.write_sample(&sample, 1).unwrap();
+            }
+        }
+        // new order without ID is cancelled by the engine ID, limit without price is skipped
+        assert_eq!((replay.stats().events, replay.stats().skipped, replay.stats().samples), (2, 1, 1));
+        let book = match book {
+            RecordWriter::Csv { writer, .. } => String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            RecordWriter::Ndjson(_) => unreachable!(),
+        };
+        assert_eq!(book, "ts,bid_price_1,bid_qty_1,ask_price_1,ask_qty_1\n1000,0.98,5,,\n");
+
+        assert!(matches!(read_events(&b"{\"ts\": 1}\n"[..], Format::Ndjson).next(),
+            Some(Err(ReplayError::Json { line: 1, .. }))));
+    }
+
+    #[test]
+    fn wrapped_ids_keep_source_ids() {
+        let event = |kind, id, side, price, qty| OrderEvent {
+            ts: UNIX_EPOCH,
+            kind,
+            id: Some(id),
+            owner: 1,
+            side,
+            price,
+            qty,
+        };
+        let flow = |replay: &mut Replay<Asset>| {
+            replay.apply(&event(EventKind::Limit, 1, OrderSide::Bid, Some(0.5), Some(1.0)));
+            for id in 1000..1999 {
+                replay.apply(&event(EventKind::Limit, id, OrderSide::Bid, Some(0.6), Some(1.0)));
+                replay.apply(&event(EventKind::Cancel, id, OrderSide::Bid, None, None));
+            }
+            replay.apply_with_results(&event(EventKind::Limit, 5000, OrderSide::Bid, Some(0.6), Some(1.0))).1
+        };
+
+        // engine ID 1 is taken by the resting bid
+        let mut replay = Replay::new(Asset::BTC, Asset::USD, ReplayConfig::default());
+        let results = flow(&mut replay);
+        assert!(matches!(results[..], [Ok(Success::Accepted { id: 1, .. }), Err(Failed::DuplicateOrderID(1))]));
+        // rejected order is not tracked, its cancel is skipped
+        replay.apply(&event(EventKind::Cancel, 5000, OrderSide::Bid, None, None));
+        assert_eq!((replay.stats().skipped, replay.orderbook().depth(1).bids[0].price), (1, 0.5));
+        let step = replay.apply(&event(EventKind::Market, 5001, OrderSide::Ask, None, Some(1.0)));
+        assert_eq!((step.trades[0].taker_order_id, step.trades[0].maker_order_id), (5001, 1));
+
+        let config = ReplayConfig { max_order_id: Some(10_000), ..ReplayConfig::default() };
+        let mut replay = Replay::new(Asset::BTC, Asset::USD, config);
+        assert!(matches!(flow(&mut replay)[..], [Ok(Success::Accepted { id: 1001, .. })]));
+    }
+}